@@ -0,0 +1,5 @@
+ALTER TABLE preview RENAME COLUMN name TO title;
+ALTER TABLE preview ALTER COLUMN title TYPE TEXT;
+ALTER TABLE preview ALTER COLUMN title DROP NOT NULL;
+
+ALTER TABLE preview ADD COLUMN IF NOT EXISTS open_graph JSONB;
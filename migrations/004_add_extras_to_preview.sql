@@ -0,0 +1 @@
+ALTER TABLE preview ADD COLUMN IF NOT EXISTS extras JSONB;
@@ -0,0 +1,2 @@
+ALTER TABLE preview ADD COLUMN IF NOT EXISTS word_count INTEGER;
+ALTER TABLE preview ADD COLUMN IF NOT EXISTS reading_time INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE preview ADD COLUMN IF NOT EXISTS final_url TEXT;
+ALTER TABLE preview ADD COLUMN IF NOT EXISTS redirects JSONB;
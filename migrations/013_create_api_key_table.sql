@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS api_key (
+    id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- The start of the key, shown in listings to tell keys apart.
+    prefix TEXT NOT NULL,
+    -- SHA-256 of the key in hex; the key itself is never stored.
+    key_hash TEXT NOT NULL,
+    -- Requests per minute and per calendar month; NULL uses the defaults.
+    rate_limit INTEGER,
+    monthly_quota BIGINT,
+    created_at TIMESTAMP DEFAULT NOW(),
+    revoked_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_key_key_hash ON api_key (key_hash);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_key_prefix ON api_key (prefix);
@@ -0,0 +1,161 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sqlx::PgPool;
+
+use super::{
+    key::{generate_key, hash_key, key_prefix},
+    quota::KeyLimiter,
+    repository::{ApiKeyRepository, KeyRepository, NewApiKey},
+    signature::UrlSigner,
+};
+
+pub const KEYS_USAGE: &str = "keys create <name> [--rate-limit <per minute>] [--monthly-quota <requests>] | keys list | keys revoke <id or prefix>";
+
+pub const SIGN_USAGE: &str = "sign <path and query> [--expires-in <seconds>]";
+
+/// How long URLs printed by `sign` stay valid without `--expires-in`: a day.
+const DEFAULT_EXPIRES_IN: u64 = 24 * 60 * 60;
+
+/// Reads the value of `--flag <value>` from `args`.
+fn flag<T: std::str::FromStr>(args: &[String], flag: &str) -> Result<Option<T>, String> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => args
+            .get(index + 1)
+            .and_then(|value| value.parse().ok())
+            .map(Some)
+            .ok_or_else(|| format!("{} needs a number", flag)),
+        None => Ok(None),
+    }
+}
+
+fn limit(value: u64) -> String {
+    if value == 0 {
+        "unlimited".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Runs a `keys` command: creates, lists or revokes API keys.
+///
+/// # Arguments
+/// * `pool` - The Postgres connection pool
+/// * `limiter` - Used for the default limits and this month's usage
+/// * `args` - The arguments after `keys`
+///
+/// # Errors
+/// Returns a message to print if the command is invalid or fails.
+pub async fn run_keys_command(
+    pool: Arc<PgPool>,
+    limiter: &KeyLimiter,
+    args: &[String],
+) -> Result<(), String> {
+    let repository = KeyRepository::builder()
+        .with_pool(pool)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    match args.first().map(String::as_str) {
+        Some("create") => {
+            let name = args
+                .get(1)
+                .filter(|name| !name.starts_with("--"))
+                .ok_or("A key needs a name")?;
+            let key = generate_key();
+            let prefix = key_prefix(&key);
+            let created = repository
+                .insert_key(&NewApiKey {
+                    name,
+                    prefix: &prefix,
+                    key_hash: &hash_key(&key),
+                    rate_limit: flag(args, "--rate-limit")?,
+                    monthly_quota: flag(args, "--monthly-quota")?,
+                })
+                .await
+                .map_err(|e| format!("Failed to create key: {}", e))?;
+
+            println!("Created key {} ({}) for {}", created.id, prefix, name);
+            println!(
+                "Rate limit: {}/min, monthly quota: {}",
+                limit(limiter.rate_limit(&created).into()),
+                limit(limiter.monthly_quota(&created))
+            );
+            println!("{}", key);
+            println!("Store the key now, it cannot be shown again.");
+            Ok(())
+        }
+        Some("list") => {
+            let keys = repository
+                .list_keys()
+                .await
+                .map_err(|e| format!("Failed to list keys: {}", e))?;
+            if keys.is_empty() {
+                println!("No API keys");
+            }
+
+            for key in keys {
+                let status = match &key.revoked_at {
+                    Some(revoked_at) => format!("revoked {}", revoked_at),
+                    None => "active".to_string(),
+                };
+                let used = match limiter.monthly_usage(&key).await {
+                    Some(used) => used.to_string(),
+                    None => "?".to_string(),
+                };
+                println!(
+                    "{}  {}  {}  created {}  {}  {}/min  {}/{} this month",
+                    key.id,
+                    key.prefix,
+                    key.name,
+                    key.created_at,
+                    status,
+                    limit(limiter.rate_limit(&key).into()),
+                    used,
+                    limit(limiter.monthly_quota(&key))
+                );
+            }
+            Ok(())
+        }
+        Some("revoke") => {
+            let id = args.get(1).ok_or("Give the id or prefix of the key")?;
+            match repository.revoke_key(id).await {
+                Ok(true) => {
+                    println!("Revoked key {}", id);
+                    Ok(())
+                }
+                Ok(false) => Err(format!("No active key {}", id)),
+                Err(e) => Err(format!("Failed to revoke key: {}", e)),
+            }
+        }
+        _ => Err(format!("Usage: cargo run {}", KEYS_USAGE)),
+    }
+}
+
+/// Runs the `sign` command: prints a signed URL, e.g. for
+/// `/preview?url=https://example.com`, that expires after `--expires-in`
+/// seconds or a day.
+///
+/// # Arguments
+/// * `signer` - The signer built from `SIGNING_SECRETS`
+/// * `args` - The arguments after `sign`
+///
+/// # Errors
+/// Returns a message to print if the command is invalid or no secret is set.
+pub fn run_sign_command(signer: &UrlSigner, args: &[String]) -> Result<(), String> {
+    let path_and_query = args
+        .first()
+        .filter(|arg| arg.starts_with('/'))
+        .ok_or_else(|| format!("Usage: cargo run {}", SIGN_USAGE))?;
+    let expires_in = flag::<u64>(args, "--expires-in")?.unwrap_or(DEFAULT_EXPIRES_IN);
+    let expires = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        + expires_in;
+
+    println!("{}", signer.sign(path_and_query, Some(expires))?);
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Marks a string as one of our API keys, e.g. in secret scanners.
+const KEY_PREFIX: &str = "rp_";
+/// Random bytes in a key.
+const KEY_BYTES: usize = 32;
+/// Hex characters after `rp_` kept as the key's public prefix.
+const PREFIX_LENGTH: usize = 8;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Generates a new API key.
+///
+/// # Returns
+/// * `String` - `rp_` followed by 64 random hex characters
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; KEY_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", KEY_PREFIX, hex(&bytes))
+}
+
+/// Hashes a key for storage and lookup.
+///
+/// Keys are long and random, so a plain SHA-256 cannot be brute-forced and
+/// keeps the lookup on every request cheap.
+pub fn hash_key(key: &str) -> String {
+    hex(&Sha256::digest(key.as_bytes()))
+}
+
+/// The part of a key that is stored in the clear, e.g. `rp_1a2b3c4d`.
+pub fn key_prefix(key: &str) -> String {
+    key.chars().take(KEY_PREFIX.len() + PREFIX_LENGTH).collect()
+}
@@ -0,0 +1,233 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        HeaderValue, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::state::AppState;
+
+use super::{
+    key::hash_key,
+    quota::Usage,
+    repository::{ApiKeyRepository, KeyRepository},
+};
+
+/// Header that carries the API key.
+const API_KEY_HEADER: &str = "x-api-key";
+/// Response header with the requests left this month.
+const QUOTA_REMAINING_HEADER: &str = "x-quota-remaining";
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("An API key is required")]
+    MissingKey,
+    #[error("Invalid or revoked API key")]
+    InvalidKey,
+    #[error("Rate limit exceeded, retry in {}s", retry_after.as_secs())]
+    RateLimited { retry_after: Duration },
+    #[error("Monthly quota exceeded")]
+    QuotaExceeded { retry_after: Duration },
+    #[error("Failed to check API key: {0}")]
+    Unavailable(String),
+    #[error("Invalid URL signature")]
+    InvalidSignature,
+    #[error("The signed URL has expired")]
+    ExpiredSignature,
+}
+
+impl AuthError {
+    /// A stable name for the error, like `FetchError::kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuthError::MissingKey | AuthError::InvalidKey => "unauthorized",
+            AuthError::RateLimited { .. } => "key_rate_limited",
+            AuthError::QuotaExceeded { .. } => "quota_exceeded",
+            AuthError::Unavailable(_) => "unavailable",
+            AuthError::InvalidSignature => "invalid_signature",
+            AuthError::ExpiredSignature => "expired_signature",
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            AuthError::MissingKey | AuthError::InvalidKey => StatusCode::UNAUTHORIZED,
+            AuthError::RateLimited { .. } | AuthError::QuotaExceeded { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            AuthError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AuthError::InvalidSignature | AuthError::ExpiredSignature => StatusCode::FORBIDDEN,
+        };
+        let body = Json(json!({
+            "error": self.to_string(),
+            "kind": self.kind(),
+            "upstream_status": null,
+        }));
+
+        match self {
+            AuthError::RateLimited { retry_after } | AuthError::QuotaExceeded { retry_after } => (
+                status,
+                [(RETRY_AFTER, retry_after.as_secs().max(1).to_string())],
+                body,
+            )
+                .into_response(),
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+/// Marks a request whose URL was signed, see [`verify_signature`].
+#[derive(Debug, Clone)]
+pub struct SignedRequest {
+    pub signature: String,
+}
+
+#[derive(Deserialize)]
+struct ApiKeyQuery {
+    api_key: Option<String>,
+}
+
+/// Reads the API key from `X-API-Key`, `Authorization: Bearer` or the
+/// `api_key` query parameter, in that order.
+fn api_key(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    let header = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let bearer = || {
+        headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_string)
+    };
+    let query = || {
+        Query::<ApiKeyQuery>::try_from_uri(request.uri())
+            .ok()
+            .and_then(|query| query.0.api_key)
+    };
+
+    header
+        .or_else(bearer)
+        .or_else(query)
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+/// Checks the request's API key and counts it against the key's rate limit
+/// and monthly quota before the route runs.
+///
+/// Requests without a key are let through unless `API_KEY_REQUIRED` is set,
+/// but a key that is given is always checked. Signed requests need no key
+/// and are counted per signed URL instead, see
+/// [`KeyLimiter::acquire_signed`](super::quota::KeyLimiter::acquire_signed).
+///
+/// # Errors
+/// * `AuthError::MissingKey` or `AuthError::InvalidKey` - 401
+/// * `AuthError::RateLimited` or `AuthError::QuotaExceeded` - 429 with `Retry-After`
+/// * `AuthError::Unavailable` - 503 if the keys cannot be read
+pub async fn require_api_key(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    if let Some(signed) = request.extensions().get::<SignedRequest>() {
+        return match state.key_limiter.acquire_signed(&signed.signature).await {
+            Usage::Allowed { .. } => Ok(next.run(request).await),
+            Usage::RateLimited { retry_after } => Err(AuthError::RateLimited { retry_after }),
+            Usage::QuotaExceeded { retry_after } => Err(AuthError::QuotaExceeded { retry_after }),
+        };
+    }
+
+    let required = state.key_limiter.required;
+    check_api_key(state, request, next, required).await
+}
+
+/// Checks the request's API key like [`require_api_key`], but refuses
+/// requests without one whatever `API_KEY_REQUIRED` says. Guards routes that
+/// are not for the public, such as `/metrics`; signed URLs are not accepted.
+///
+/// # Errors
+/// * `AuthError::MissingKey` or `AuthError::InvalidKey` - 401
+/// * `AuthError::RateLimited` or `AuthError::QuotaExceeded` - 429 with `Retry-After`
+/// * `AuthError::Unavailable` - 503 if the keys cannot be read
+pub async fn always_require_api_key(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    check_api_key(state, request, next, true).await
+}
+
+/// Looks up the request's API key and counts it against the key's limits.
+/// Requests without a key are refused if `required`, and let through
+/// otherwise.
+async fn check_api_key(
+    state: Arc<AppState>,
+    request: Request,
+    next: Next,
+    required: bool,
+) -> Result<Response, AuthError> {
+    let Some(key) = api_key(&request) else {
+        return if required {
+            Err(AuthError::MissingKey)
+        } else {
+            Ok(next.run(request).await)
+        };
+    };
+
+    let repository = KeyRepository::builder()
+        .with_pool(state.pool.clone())
+        .build()
+        .expect("Failed to build key repository");
+    let key = repository
+        .get_active_key(&hash_key(&key))
+        .await
+        .map_err(|e| AuthError::Unavailable(e.to_string()))?
+        .ok_or(AuthError::InvalidKey)?;
+
+    let remaining = match state.key_limiter.acquire(&key).await {
+        Usage::Allowed { remaining } => remaining,
+        Usage::RateLimited { retry_after } => return Err(AuthError::RateLimited { retry_after }),
+        Usage::QuotaExceeded { retry_after } => {
+            return Err(AuthError::QuotaExceeded { retry_after })
+        }
+    };
+
+    let mut response = next.run(request).await;
+    if let Some(remaining) = remaining {
+        response
+            .headers_mut()
+            .insert(QUOTA_REMAINING_HEADER, HeaderValue::from(remaining));
+    }
+    Ok(response)
+}
+
+/// Checks the signature of requests whose URL carries one, so URLs signed by
+/// our backend can be embedded in pages without an API key.
+///
+/// Requests without a signature are passed on to [`require_api_key`].
+///
+/// # Errors
+/// * `AuthError::InvalidSignature` or `AuthError::ExpiredSignature` - 403
+pub async fn verify_signature(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    if let Some(signature) = state.url_signer.verify(request.uri())? {
+        request.extensions_mut().insert(SignedRequest { signature });
+    }
+    Ok(next.run(request).await)
+}
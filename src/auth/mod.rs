@@ -0,0 +1,6 @@
+pub mod cli;
+pub mod key;
+pub mod middleware;
+pub mod quota;
+pub mod repository;
+pub mod signature;
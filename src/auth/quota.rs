@@ -0,0 +1,280 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use redis::{AsyncCommands, Client as RedisClient, Script};
+
+use crate::config::constants::Settings;
+
+use super::repository::ApiKey;
+
+/// Counts a request against a key's monthly quota and per-minute rate limit.
+/// Returns `{status, used}`: status 0 if the request is allowed, 1 if the
+/// quota is used up and 2 if the rate limit is reached; `used` is the
+/// month's count so far.
+const ACQUIRE_SCRIPT: &str = r#"
+local rate = tonumber(ARGV[1])
+local quota = tonumber(ARGV[2])
+
+local used = tonumber(redis.call('GET', KEYS[2]) or '0')
+if quota > 0 and used >= quota then
+    return {1, used}
+end
+if rate > 0 and tonumber(redis.call('GET', KEYS[1]) or '0') >= rate then
+    return {2, used}
+end
+
+if rate > 0 then
+    redis.call('INCR', KEYS[1])
+    redis.call('EXPIRE', KEYS[1], 60)
+end
+used = redis.call('INCR', KEYS[2])
+redis.call('EXPIRE', KEYS[2], ARGV[3])
+return {0, used}
+"#;
+
+/// How long a month's count is kept, long enough to report last month.
+const QUOTA_RETENTION: Duration = Duration::from_secs(62 * 24 * 60 * 60);
+
+/// The outcome of counting a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    /// Allowed, with the quota left this month if the key has one.
+    Allowed { remaining: Option<u64> },
+    /// The monthly quota is used up until the next month starts.
+    QuotaExceeded { retry_after: Duration },
+    /// The per-minute rate limit is reached until the next minute.
+    RateLimited { retry_after: Duration },
+}
+
+/// Enforces per-key rate limits and monthly quotas, see `API_KEY_RATE_LIMIT`
+/// and `API_KEY_MONTHLY_QUOTA`.
+///
+/// The counters live in Redis so every replica shares them. Without
+/// `CACHE_DATABASE_URL`, or while Redis cannot be reached, keys are still
+/// checked but requests are not counted.
+pub struct KeyLimiter {
+    /// Whether requests without a key are refused, see `API_KEY_REQUIRED`.
+    pub required: bool,
+    default_rate_limit: u32,
+    default_monthly_quota: u64,
+    redis: Option<RedisClient>,
+}
+
+impl KeyLimiter {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let redis = settings.cache_url.as_deref().and_then(|url| {
+            RedisClient::open(url)
+                .map_err(|e| eprintln!("API key limits will not be enforced: {}", e))
+                .ok()
+        });
+        if settings.api_key_required && settings.cache_url.is_none() {
+            eprintln!("API key limits are not enforced without CACHE_DATABASE_URL");
+        }
+
+        Self {
+            required: settings.api_key_required,
+            default_rate_limit: settings.api_key_rate_limit,
+            default_monthly_quota: settings.api_key_monthly_quota,
+            redis,
+        }
+    }
+
+    /// The requests per minute allowed for a key, 0 for no limit.
+    pub fn rate_limit(&self, key: &ApiKey) -> u32 {
+        key.rate_limit.unwrap_or(self.default_rate_limit)
+    }
+
+    /// The requests per month allowed for a key, 0 for no limit.
+    pub fn monthly_quota(&self, key: &ApiKey) -> u64 {
+        key.monthly_quota.unwrap_or(self.default_monthly_quota)
+    }
+
+    /// Counts a request made with `key`.
+    ///
+    /// Requests that are refused are not counted. If Redis fails the request
+    /// is allowed, so an outage of the cache does not take the API down.
+    pub async fn acquire(&self, key: &ApiKey) -> Usage {
+        self.count(
+            &key.id,
+            &format!("key {}", key.prefix),
+            self.rate_limit(key),
+            self.monthly_quota(key),
+        )
+        .await
+    }
+
+    /// Counts a request made with a signed URL, see `SIGNING_SECRETS`.
+    ///
+    /// Each signed URL gets `API_KEY_RATE_LIMIT` requests per minute and no
+    /// monthly quota, so a URL embedded in a public page cannot be replayed
+    /// without limit.
+    pub async fn acquire_signed(&self, signature: &str) -> Usage {
+        self.count(
+            &format!("signed:{}", signature),
+            "signed URL",
+            self.default_rate_limit,
+            0,
+        )
+        .await
+    }
+
+    async fn count(&self, id: &str, label: &str, rate_limit: u32, quota: u64) -> Usage {
+        let Some(client) = &self.redis else {
+            return Usage::Allowed { remaining: None };
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let result = async {
+            let mut conn = client.get_multiplexed_async_connection().await?;
+            Script::new(ACQUIRE_SCRIPT)
+                .key(rate_key(id, now))
+                .key(quota_key(id, now))
+                .arg(rate_limit)
+                .arg(quota)
+                .arg(QUOTA_RETENTION.as_secs())
+                .invoke_async::<(u8, u64)>(&mut conn)
+                .await
+        }
+        .await;
+
+        match result {
+            Ok((0, used)) => Usage::Allowed {
+                remaining: (quota > 0).then(|| quota.saturating_sub(used)),
+            },
+            Ok((1, _)) => Usage::QuotaExceeded {
+                retry_after: Duration::from_secs(next_month(now) - now),
+            },
+            Ok(_) => Usage::RateLimited {
+                retry_after: Duration::from_secs(60 - now % 60),
+            },
+            Err(e) => {
+                eprintln!("Failed to count request for {}: {}", label, e);
+                Usage::Allowed { remaining: None }
+            }
+        }
+    }
+
+    /// The requests made with `key` this month, if they are counted.
+    pub async fn monthly_usage(&self, key: &ApiKey) -> Option<u64> {
+        let client = self.redis.as_ref()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let result = async {
+            let mut conn = client.get_multiplexed_async_connection().await?;
+            conn.get::<_, Option<u64>>(quota_key(&key.id, now)).await
+        }
+        .await;
+        match result {
+            Ok(used) => Some(used.unwrap_or(0)),
+            Err(e) => {
+                eprintln!("Failed to read usage of key {}: {}", key.prefix, e);
+                None
+            }
+        }
+    }
+}
+
+fn rate_key(id: &str, now: u64) -> String {
+    format!("apikey:rate:{}:{}", id, now / 60)
+}
+
+fn quota_key(id: &str, now: u64) -> String {
+    let (year, month) = year_month(now / 86_400);
+    format!("apikey:quota:{}:{:04}-{:02}", id, year, month)
+}
+
+/// The UTC year and month of a day counted from the Unix epoch, using
+/// Howard Hinnant's `civil_from_days`.
+fn year_month(days: u64) -> (u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month)
+}
+
+/// The days from the Unix epoch to the first of a month, using Howard
+/// Hinnant's `days_from_civil`.
+fn first_of_month(year: u64, month: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = (month + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The Unix time at which the month after `now` starts.
+fn next_month(now: u64) -> u64 {
+    let (year, month) = year_month(now / 86_400);
+    let (year, month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    first_of_month(year, month) * 86_400
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn year_month_of_days() {
+        assert_eq!(year_month(0), (1970, 1));
+        assert_eq!(year_month(20_818), (2026, 12));
+        assert_eq!(year_month(20_819), (2027, 1));
+        assert_eq!(year_month(19_782), (2024, 2));
+        assert_eq!(year_month(19_783), (2024, 3));
+        assert_eq!(year_month(11_016), (2000, 2));
+        assert_eq!(year_month(47_540), (2100, 2));
+    }
+
+    #[test]
+    fn first_of_month_in_days() {
+        assert_eq!(first_of_month(1970, 1), 0);
+        assert_eq!(first_of_month(2027, 1), 20_819);
+        assert_eq!(first_of_month(2024, 3), 19_783);
+        assert_eq!(first_of_month(2000, 3), 11_017);
+        assert_eq!(first_of_month(2100, 3), 47_541);
+    }
+
+    #[test]
+    fn next_month_rolls_over_december_and_leap_days() {
+        // 2026-12-31 23:59:59 to 2027-01-01.
+        assert_eq!(next_month(20_819 * DAY - 1), 20_819 * DAY);
+        // 2024-02-29 12:00 to 2024-03-01.
+        assert_eq!(next_month(19_782 * DAY + DAY / 2), 19_783 * DAY);
+        // The first second of a month counts towards that month.
+        assert_eq!(next_month(19_783 * DAY), first_of_month(2024, 4) * DAY);
+    }
+
+    #[test]
+    fn quota_key_names_the_month() {
+        assert_eq!(
+            quota_key("id", 19_782 * DAY),
+            "apikey:quota:id:2024-02".to_string()
+        );
+        assert_eq!(
+            quota_key("id", 20_819 * DAY),
+            "apikey:quota:id:2027-01".to_string()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use async_trait::async_trait;
+use sqlx::{postgres::PgRow, Error as SqlxError, PgPool, Row};
+use std::sync::Arc;
+
+const INSERT_KEY_QUERY: &str = r#"
+    INSERT INTO api_key (name, prefix, key_hash, rate_limit, monthly_quota)
+    VALUES ($1, $2, $3, $4, $5)
+    RETURNING id::text, name, prefix, rate_limit, monthly_quota, created_at::text, revoked_at::text
+"#;
+
+const GET_ACTIVE_KEY_QUERY: &str = r#"
+    SELECT id::text, name, prefix, rate_limit, monthly_quota, created_at::text, revoked_at::text
+    FROM api_key
+    WHERE key_hash = $1 AND revoked_at IS NULL
+"#;
+
+const LIST_KEYS_QUERY: &str = r#"
+    SELECT id::text, name, prefix, rate_limit, monthly_quota, created_at::text, revoked_at::text
+    FROM api_key
+    ORDER BY created_at
+"#;
+
+const REVOKE_KEY_QUERY: &str = r#"
+    UPDATE api_key
+    SET revoked_at = NOW()
+    WHERE (id::text = $1 OR prefix = $1) AND revoked_at IS NULL
+"#;
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeyRepositoryError {
+    #[error("Database error: {0}")]
+    Database(#[from] SqlxError),
+    #[error("Table does not exist")]
+    TableNotFound,
+    #[error("Other error: {0}")]
+    Other(String),
+}
+
+pub type Result<T> = std::result::Result<T, KeyRepositoryError>;
+
+/// An API key as stored, without the key itself.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    /// Requests per minute, or `None` for `API_KEY_RATE_LIMIT`.
+    pub rate_limit: Option<u32>,
+    /// Requests per calendar month, or `None` for `API_KEY_MONTHLY_QUOTA`.
+    pub monthly_quota: Option<u64>,
+    pub created_at: String,
+    pub revoked_at: Option<String>,
+}
+
+impl From<PgRow> for ApiKey {
+    fn from(row: PgRow) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            prefix: row.get("prefix"),
+            rate_limit: row
+                .get::<Option<i32>, _>("rate_limit")
+                .and_then(|limit| u32::try_from(limit).ok()),
+            monthly_quota: row
+                .get::<Option<i64>, _>("monthly_quota")
+                .and_then(|quota| u64::try_from(quota).ok()),
+            created_at: row.get("created_at"),
+            revoked_at: row.get("revoked_at"),
+        }
+    }
+}
+
+/// A key to store, see [`super::key::generate_key`].
+pub struct NewApiKey<'a> {
+    pub name: &'a str,
+    pub prefix: &'a str,
+    pub key_hash: &'a str,
+    pub rate_limit: Option<u32>,
+    pub monthly_quota: Option<u64>,
+}
+
+#[async_trait]
+pub trait ApiKeyRepository {
+    async fn insert_key(&self, key: &NewApiKey<'_>) -> Result<ApiKey>;
+    /// Finds the key with this hash unless it was revoked.
+    async fn get_active_key(&self, key_hash: &str) -> Result<Option<ApiKey>>;
+    async fn list_keys(&self) -> Result<Vec<ApiKey>>;
+    /// Revokes the key with this id or prefix, returning whether one was.
+    async fn revoke_key(&self, id_or_prefix: &str) -> Result<bool>;
+}
+
+pub struct KeyRepository {
+    pool: Arc<PgPool>,
+}
+
+#[derive(Default)]
+pub struct KeyRepositoryBuilder {
+    pool: Option<Arc<PgPool>>,
+}
+
+impl KeyRepositoryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_pool(mut self, pool: Arc<PgPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn build(self) -> Result<KeyRepository> {
+        let pool = self
+            .pool
+            .ok_or_else(|| KeyRepositoryError::Other("Database pool is required".to_string()))?;
+
+        Ok(KeyRepository { pool })
+    }
+}
+
+impl KeyRepository {
+    pub fn builder() -> KeyRepositoryBuilder {
+        KeyRepositoryBuilder::new()
+    }
+
+    fn handle_error(&self, error: SqlxError) -> KeyRepositoryError {
+        if let SqlxError::Database(db_err) = &error {
+            if db_err.code().as_deref() == Some("42P01") {
+                eprintln!("Warning: Table 'api_key' does not exist. Please run migrations.");
+                return KeyRepositoryError::TableNotFound;
+            }
+        }
+        KeyRepositoryError::Database(error)
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for KeyRepository {
+    async fn insert_key(&self, key: &NewApiKey<'_>) -> Result<ApiKey> {
+        sqlx::query(INSERT_KEY_QUERY)
+            .bind(key.name)
+            .bind(key.prefix)
+            .bind(key.key_hash)
+            .bind(key.rate_limit.and_then(|limit| i32::try_from(limit).ok()))
+            .bind(
+                key.monthly_quota
+                    .and_then(|quota| i64::try_from(quota).ok()),
+            )
+            .fetch_one(&*self.pool)
+            .await
+            .map(ApiKey::from)
+            .map_err(|e| self.handle_error(e))
+    }
+
+    async fn get_active_key(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        sqlx::query(GET_ACTIVE_KEY_QUERY)
+            .bind(key_hash)
+            .fetch_optional(&*self.pool)
+            .await
+            .map(|row| row.map(ApiKey::from))
+            .map_err(|e| self.handle_error(e))
+    }
+
+    async fn list_keys(&self) -> Result<Vec<ApiKey>> {
+        sqlx::query(LIST_KEYS_QUERY)
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(ApiKey::from).collect())
+            .map_err(|e| self.handle_error(e))
+    }
+
+    async fn revoke_key(&self, id_or_prefix: &str) -> Result<bool> {
+        sqlx::query(REVOKE_KEY_QUERY)
+            .bind(id_or_prefix)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| self.handle_error(e))
+    }
+}
@@ -0,0 +1,297 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{extract::Query, http::Uri};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha2::Sha256;
+
+use crate::config::constants::Settings;
+
+use super::middleware::AuthError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Query parameter that carries the signature.
+pub const SIGNATURE_PARAM: &str = "signature";
+/// Query parameter with the Unix time after which a signed URL is refused.
+pub const EXPIRES_PARAM: &str = "expires";
+
+/// Signs and verifies request URLs with HMAC-SHA256, see `SIGNING_SECRETS`.
+///
+/// The signed message is the path, a newline, and every query parameter but
+/// `signature`, each name and value percent-encoded (everything but ASCII
+/// letters and digits), sorted, written as `name=value` and joined with `&`.
+/// The signature is the base64url HMAC of that message, without padding.
+/// An `expires` parameter is signed like the others.
+///
+/// The first secret signs; every secret verifies, so a secret can be rotated
+/// by putting the new one first until URLs signed with the old one expire.
+pub struct UrlSigner {
+    secrets: Vec<String>,
+}
+
+impl UrlSigner {
+    pub fn from_settings(settings: &Settings) -> Self {
+        if !settings.signing_secrets.is_empty() && settings.cache_url.is_none() {
+            eprintln!("Signed URL rate limits are not enforced without CACHE_DATABASE_URL");
+        }
+
+        Self {
+            secrets: settings.signing_secrets.clone(),
+        }
+    }
+
+    /// Whether a secret is configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.secrets.is_empty()
+    }
+
+    /// Signs a path and query, e.g. `/preview?url=https://example.com`.
+    ///
+    /// # Arguments
+    /// * `path_and_query` - The URL to sign, without scheme and host
+    /// * `expires` - The Unix time after which the URL is refused, replacing
+    ///   any `expires` in the query
+    ///
+    /// # Returns
+    /// * `String` - The path and canonical query with `signature` added
+    ///
+    /// # Errors
+    /// Returns a message if no secret is configured or the URL is invalid.
+    pub fn sign(&self, path_and_query: &str, expires: Option<u64>) -> Result<String, String> {
+        let secret = self.secrets.first().ok_or("SIGNING_SECRETS is not set")?;
+        let uri = path_and_query
+            .parse::<Uri>()
+            .map_err(|e| format!("Invalid URL {:?}: {}", path_and_query, e))?;
+
+        let mut params = query_params(&uri)?
+            .into_iter()
+            .filter(|(name, _)| name != SIGNATURE_PARAM)
+            .filter(|(name, _)| expires.is_none() || name != EXPIRES_PARAM)
+            .collect::<Vec<_>>();
+        if let Some(expires) = expires {
+            params.push((EXPIRES_PARAM.to_string(), expires.to_string()));
+        }
+
+        let query = canonical_query(params);
+        let signature =
+            URL_SAFE_NO_PAD.encode(mac(secret, uri.path(), &query).finalize().into_bytes());
+        let separator = if query.is_empty() { "" } else { "&" };
+        Ok(format!(
+            "{}?{}{}{}={}",
+            uri.path(),
+            query,
+            separator,
+            SIGNATURE_PARAM,
+            signature
+        ))
+    }
+
+    /// Checks the signature of a request URL.
+    ///
+    /// # Returns
+    /// * `Ok(Some(String))` with the signature, without padding, if the URL
+    ///   carries a valid one that has not expired.
+    /// * `Ok(None)` if it carries none, or no secret is configured.
+    ///
+    /// # Errors
+    /// * `AuthError::InvalidSignature` if the signature does not match
+    /// * `AuthError::ExpiredSignature` if `expires` has passed
+    pub fn verify(&self, uri: &Uri) -> Result<Option<String>, AuthError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+
+        let params = query_params(uri).map_err(|_| AuthError::InvalidSignature)?;
+        let Some(signature) = params
+            .iter()
+            .find(|(name, _)| name == SIGNATURE_PARAM)
+            .map(|(_, signature)| signature.clone())
+        else {
+            return Ok(None);
+        };
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature.trim_end_matches('='))
+            .map_err(|_| AuthError::InvalidSignature)?;
+        let expires = params
+            .iter()
+            .find(|(name, _)| name == EXPIRES_PARAM)
+            .map(|(_, expires)| expires.parse::<u64>())
+            .transpose()
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        let query = canonical_query(
+            params
+                .into_iter()
+                .filter(|(name, _)| name != SIGNATURE_PARAM)
+                .collect(),
+        );
+        let valid = self.secrets.iter().any(|secret| {
+            mac(secret, uri.path(), &query)
+                .verify_slice(&signature)
+                .is_ok()
+        });
+        if !valid {
+            return Err(AuthError::InvalidSignature);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match expires {
+            Some(expires) if expires < now => Err(AuthError::ExpiredSignature),
+            _ => Ok(Some(URL_SAFE_NO_PAD.encode(signature))),
+        }
+    }
+}
+
+fn query_params(uri: &Uri) -> Result<Vec<(String, String)>, String> {
+    Query::<Vec<(String, String)>>::try_from_uri(uri)
+        .map(|query| query.0)
+        .map_err(|e| format!("Invalid query: {}", e))
+}
+
+fn canonical_query(mut params: Vec<(String, String)>) -> String {
+    params.sort();
+    params
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(name, NON_ALPHANUMERIC),
+                utf8_percent_encode(value, NON_ALPHANUMERIC)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn mac(secret: &str, path: &str, query: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(path.as_bytes());
+    mac.update(b"\n");
+    mac.update(query.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(secrets: &[&str]) -> UrlSigner {
+        UrlSigner {
+            secrets: secrets.iter().map(|secret| secret.to_string()).collect(),
+        }
+    }
+
+    fn verify(signer: &UrlSigner, url: &str) -> Result<Option<String>, AuthError> {
+        signer.verify(&url.parse::<Uri>().unwrap())
+    }
+
+    /// The signature of a signed URL.
+    fn signature(signed: &str) -> &str {
+        signed.rsplit_once("signature=").unwrap().1
+    }
+
+    #[test]
+    fn verifies_what_it_signs() {
+        let signer = signer(&["secret"]);
+        let signed = signer
+            .sign(
+                "/preview?url=https%3A%2F%2Fexample.com%2F%3Fa%3D1&lang=en",
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            signed,
+            format!(
+                "/preview?lang=en&url=https%3A%2F%2Fexample%2Ecom%2F%3Fa%3D1&signature={}",
+                signature(&signed)
+            )
+        );
+        assert_eq!(
+            verify(&signer, &signed).unwrap().as_deref(),
+            Some(signature(&signed))
+        );
+        assert_eq!(
+            verify(&signer, "/preview?url=https%3A%2F%2Fexample.com").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_the_order_of_parameters() {
+        let signer = signer(&["secret"]);
+        let signed = signer.sign("/preview?url=a&lang=en", None).unwrap();
+
+        let reordered = format!("/preview?signature={}&url=a&lang=en", signature(&signed));
+        assert!(verify(&signer, &reordered).unwrap().is_some());
+    }
+
+    #[test]
+    fn refuses_tampered_urls() {
+        let signer = signer(&["secret"]);
+        let signed = signer.sign("/preview?url=a", None).unwrap();
+        let signature = signature(&signed);
+
+        for tampered in [
+            format!("/preview?url=b&signature={}", signature),
+            format!("/preview?url=a&lang=en&signature={}", signature),
+            format!("/other?url=a&signature={}", signature),
+            format!("/preview?url=a&signature={}x", signature),
+            "/preview?url=a&signature=!".to_string(),
+        ] {
+            assert!(
+                matches!(verify(&signer, &tampered), Err(AuthError::InvalidSignature)),
+                "{}",
+                tampered
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_expired_urls() {
+        let signer = signer(&["secret"]);
+
+        let expired = signer.sign("/preview?url=a", Some(1)).unwrap();
+        assert!(matches!(
+            verify(&signer, &expired),
+            Err(AuthError::ExpiredSignature)
+        ));
+
+        let valid = signer.sign("/preview?url=a", Some(u64::MAX)).unwrap();
+        assert!(verify(&signer, &valid).unwrap().is_some());
+
+        // `expires` is signed, so it cannot be pushed back.
+        let extended = expired.replace("expires=1", "expires=99999999999");
+        assert!(matches!(
+            verify(&signer, &extended),
+            Err(AuthError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn signs_with_the_first_secret_and_verifies_with_any() {
+        let old = signer(&["old"]);
+        let rotating = signer(&["new", "old"]);
+        let new = signer(&["new"]);
+
+        let signed_with_old = old.sign("/preview?url=a", None).unwrap();
+        assert!(verify(&rotating, &signed_with_old).unwrap().is_some());
+        assert!(matches!(
+            verify(&new, &signed_with_old),
+            Err(AuthError::InvalidSignature)
+        ));
+
+        let signed_while_rotating = rotating.sign("/preview?url=a", None).unwrap();
+        assert!(verify(&new, &signed_while_rotating).unwrap().is_some());
+        assert!(matches!(
+            verify(&old, &signed_while_rotating),
+            Err(AuthError::InvalidSignature)
+        ));
+    }
+}
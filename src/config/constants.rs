@@ -1,31 +1,311 @@
 use dotenv::dotenv;
-use std::env;
+use std::{env, path::PathBuf, time::Duration};
+
+use super::headless::{parse_domain_wait_strategies, WaitStrategy, DEFAULT_BLOCKED_HOSTS};
+use crate::preview::{
+    limiter::{parse_domain_limits, HostLimit},
+    proxy::{parse_domain_proxies, parse_proxy_list},
+    redirect::DEFAULT_SHORTENER_HOSTS,
+    reputation::{ReputationAction, DEFAULT_SUSPICIOUS_TLDS},
+    safety::{parse_category_lists, SafetyCategory},
+    text::MAX_DESCRIPTION_LENGTH,
+};
+
+/// Sent by the HTTP fetcher and matched against robots.txt groups.
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
 #[derive(Debug, Clone)]
 pub struct Settings {
-    pub database_url: String,
+    /// Only required by the commands that use the database.
+    pub database_url: Option<String>,
     pub app_host: String,
     pub use_headless_browser_only: bool,
     pub cache_url: Option<String>,
+    pub head_only_parse: bool,
+    pub max_head_bytes: usize,
+    pub headless_wait: WaitStrategy,
+    pub headless_wait_domains: Vec<(String, WaitStrategy)>,
+    pub headless_timeout: Duration,
+    pub headless_block_resources: bool,
+    pub headless_blocked_hosts: Vec<String>,
+    pub headless_consent: bool,
+    pub headless_consent_selectors: Vec<String>,
+    pub fetchers: Vec<String>,
+    pub fixture_dir: String,
+    pub rules_file: Option<String>,
+    pub excerpt_length: usize,
+    pub robots_compliance: bool,
+    pub user_agent: String,
+    pub rate_limit: HostLimit,
+    pub rate_limit_domains: Vec<(String, HostLimit)>,
+    pub rate_limit_max_wait: Duration,
+    pub retry_attempts: u32,
+    pub retry_base_delay: Duration,
+    pub retry_max_delay: Duration,
+    pub fetch_connect_timeout: Duration,
+    pub fetch_timeout: Duration,
+    pub shortener_hosts: Vec<String>,
+    pub proxies: Vec<String>,
+    pub proxy_domains: Vec<(String, Vec<String>)>,
+    pub blocklist_files: Vec<PathBuf>,
+    pub hash_prefix_files: Vec<PathBuf>,
+    pub suspicious_tlds: Vec<String>,
+    pub reputation_action: ReputationAction,
+    pub safety_domain_lists: Vec<(SafetyCategory, Vec<PathBuf>)>,
+    pub escape_html: bool,
+    pub api_key_required: bool,
+    pub api_key_rate_limit: u32,
+    pub api_key_monthly_quota: u64,
+    pub signing_secrets: Vec<String>,
 }
 
 impl Settings {
     pub fn from_env() -> Self {
         dotenv().ok();
 
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let database_url = env::var("DATABASE_URL").ok();
         let app_host = env::var("APP_HOST").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
         let use_headless_browser_only = env::var("ONLY_USE_HEADLESS_BROWSER")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
             .unwrap_or(false);
         let cache_url = env::var("CACHE_DATABASE_URL").ok();
+        let head_only_parse = env::var("HEAD_ONLY_PARSE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+        let max_head_bytes = env::var("MAX_HEAD_BYTES")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(512 * 1024);
+        let headless_wait = env::var("HEADLESS_WAIT")
+            .ok()
+            .and_then(|value| {
+                value
+                    .parse::<WaitStrategy>()
+                    .map_err(|e| eprintln!("Ignoring HEADLESS_WAIT: {}", e))
+                    .ok()
+            })
+            .unwrap_or(WaitStrategy::NetworkIdle);
+        let headless_wait_domains = env::var("HEADLESS_WAIT_DOMAINS")
+            .map(|value| parse_domain_wait_strategies(&value))
+            .unwrap_or_default();
+        let headless_timeout = env::var("HEADLESS_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_secs(10));
+        let headless_block_resources = env::var("HEADLESS_BLOCK_RESOURCES")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .unwrap_or(true);
+        let headless_blocked_hosts = DEFAULT_BLOCKED_HOSTS
+            .iter()
+            .map(|host| host.to_string())
+            .chain(
+                env::var("HEADLESS_BLOCKED_HOSTS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|host| host.trim().to_ascii_lowercase())
+                    .filter(|host| !host.is_empty()),
+            )
+            .collect();
+        let headless_consent = env::var("HEADLESS_CONSENT")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .unwrap_or(true);
+        // CSS selectors may contain commas, so they are separated by `;`.
+        let headless_consent_selectors = env::var("HEADLESS_CONSENT_SELECTORS")
+            .unwrap_or_default()
+            .split(';')
+            .map(|selector| selector.trim().to_string())
+            .filter(|selector| !selector.is_empty())
+            .collect();
+        let fetchers = env::var("FETCHERS")
+            .unwrap_or_else(|_| "http,browser".to_string())
+            .split(',')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let fixture_dir = env::var("FIXTURE_DIR").unwrap_or_else(|_| "fixtures".to_string());
+        let rules_file = env::var("RULES_FILE").ok().filter(|path| !path.is_empty());
+        let excerpt_length = env::var("EXCERPT_LENGTH")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(300)
+            .min(MAX_DESCRIPTION_LENGTH);
+        let robots_compliance = env::var("ROBOTS_COMPLIANCE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+        let user_agent = env::var("USER_AGENT")
+            .ok()
+            .filter(|user_agent| !user_agent.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+        let rate_limit = HostLimit {
+            rate: env::var("RATE_LIMIT_RATE")
+                .ok()
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|rate| rate.is_finite() && *rate >= 0.0)
+                .unwrap_or(1.0),
+            burst: env::var("RATE_LIMIT_BURST")
+                .ok()
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or(5)
+                .max(1),
+            concurrency: env::var("RATE_LIMIT_CONCURRENCY")
+                .ok()
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or(2),
+        };
+        let rate_limit_domains = env::var("RATE_LIMIT_DOMAINS")
+            .map(|value| parse_domain_limits(&value))
+            .unwrap_or_default();
+        let rate_limit_max_wait = env::var("RATE_LIMIT_MAX_WAIT_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_secs(10));
+        let retry_attempts = env::var("RETRY_ATTEMPTS")
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(2);
+        let retry_base_delay = env::var("RETRY_BASE_DELAY_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_millis(250));
+        let retry_max_delay = env::var("RETRY_MAX_DELAY_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_secs(5));
+        let fetch_connect_timeout = env::var("FETCH_CONNECT_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_secs(5));
+        let fetch_timeout = env::var("FETCH_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_secs(15));
+        let shortener_hosts = DEFAULT_SHORTENER_HOSTS
+            .iter()
+            .map(|host| host.to_string())
+            .chain(
+                env::var("SHORTENER_HOSTS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|host| host.trim().to_ascii_lowercase())
+                    .filter(|host| !host.is_empty()),
+            )
+            .collect();
+        let proxies = env::var("PROXIES")
+            .map(|value| parse_proxy_list(&value))
+            .unwrap_or_default();
+        let proxy_domains = env::var("PROXY_DOMAINS")
+            .map(|value| parse_domain_proxies(&value))
+            .unwrap_or_default();
+        let paths = |name: &str| {
+            env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        };
+        let blocklist_files = paths("BLOCKLIST_FILES");
+        let hash_prefix_files = paths("HASH_PREFIX_FILES");
+        let suspicious_tlds = DEFAULT_SUSPICIOUS_TLDS
+            .iter()
+            .map(|tld| tld.to_string())
+            .chain(
+                env::var("SUSPICIOUS_TLDS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|tld| tld.trim().trim_start_matches('.').to_ascii_lowercase())
+                    .filter(|tld| !tld.is_empty()),
+            )
+            .collect();
+        let reputation_action = env::var("REPUTATION_ACTION")
+            .ok()
+            .and_then(|value| {
+                value
+                    .parse::<ReputationAction>()
+                    .map_err(|e| eprintln!("Ignoring REPUTATION_ACTION: {}", e))
+                    .ok()
+            })
+            .unwrap_or(ReputationAction::Refuse);
+        let safety_domain_lists = env::var("SAFETY_DOMAIN_LISTS")
+            .map(|value| parse_category_lists(&value))
+            .unwrap_or_default();
+        let escape_html = env::var("ESCAPE_HTML")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+        let api_key_required = env::var("API_KEY_REQUIRED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+        let api_key_rate_limit = env::var("API_KEY_RATE_LIMIT")
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(60);
+        let api_key_monthly_quota = env::var("API_KEY_MONTHLY_QUOTA")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(10_000);
+        let signing_secrets = env::var("SIGNING_SECRETS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|secret| !secret.is_empty())
+            .map(String::from)
+            .collect();
 
         Self {
             database_url,
             app_host,
             use_headless_browser_only,
             cache_url,
+            head_only_parse,
+            max_head_bytes,
+            headless_wait,
+            headless_wait_domains,
+            headless_timeout,
+            headless_block_resources,
+            headless_blocked_hosts,
+            headless_consent,
+            headless_consent_selectors,
+            fetchers,
+            fixture_dir,
+            rules_file,
+            excerpt_length,
+            robots_compliance,
+            user_agent,
+            rate_limit,
+            rate_limit_domains,
+            rate_limit_max_wait,
+            retry_attempts,
+            retry_base_delay,
+            retry_max_delay,
+            fetch_connect_timeout,
+            fetch_timeout,
+            shortener_hosts,
+            proxies,
+            proxy_domains,
+            blocklist_files,
+            hash_prefix_files,
+            suspicious_tlds,
+            reputation_action,
+            safety_domain_lists,
+            escape_html,
+            api_key_required,
+            api_key_rate_limit,
+            api_key_monthly_quota,
+            signing_secrets,
         }
     }
 }
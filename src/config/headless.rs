@@ -0,0 +1,89 @@
+use std::{str::FromStr, time::Duration};
+
+/// Ad and analytics hosts that are always blocked when resource blocking is on.
+pub const DEFAULT_BLOCKED_HOSTS: [&str; 20] = [
+    "google-analytics.com",
+    "googletagmanager.com",
+    "googlesyndication.com",
+    "googleadservices.com",
+    "doubleclick.net",
+    "adservice.google.com",
+    "connect.facebook.net",
+    "amazon-adsystem.com",
+    "scorecardresearch.com",
+    "quantserve.com",
+    "chartbeat.com",
+    "hotjar.com",
+    "segment.io",
+    "segment.com",
+    "mixpanel.com",
+    "nr-data.net",
+    "clarity.ms",
+    "taboola.com",
+    "outbrain.com",
+    "criteo.com",
+];
+
+/// What the headless browser waits for before reading the page.
+///
+/// Parsed from `load`, `network_idle`, `selector:<css>`, `meta:<property>`
+/// or `delay:<milliseconds>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// The page's `load` event.
+    Load,
+    /// The document has loaded and no new resources were requested for a while.
+    NetworkIdle,
+    /// An element matching a CSS selector exists.
+    Selector(String),
+    /// A `<meta>` tag with the given `property` or `name` has non-empty content.
+    MetaTag(String),
+    /// A fixed delay after navigation.
+    Delay(Duration),
+}
+
+impl FromStr for WaitStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (kind, argument) = value.split_once(':').unwrap_or((value, ""));
+        let argument = argument.trim();
+
+        match (kind.trim(), argument.is_empty()) {
+            ("load", true) => Ok(WaitStrategy::Load),
+            ("network_idle", true) => Ok(WaitStrategy::NetworkIdle),
+            ("selector", false) => Ok(WaitStrategy::Selector(argument.to_string())),
+            ("meta", false) => Ok(WaitStrategy::MetaTag(argument.to_string())),
+            ("delay", false) => argument
+                .parse::<u64>()
+                .map(|millis| WaitStrategy::Delay(Duration::from_millis(millis)))
+                .map_err(|e| format!("Invalid delay {:?}: {}", argument, e)),
+            _ => Err(format!("Invalid wait strategy: {:?}", value)),
+        }
+    }
+}
+
+/// Parses per-domain wait strategies written as `domain=strategy;domain=strategy`.
+///
+/// Invalid entries are reported and skipped.
+pub fn parse_domain_wait_strategies(value: &str) -> Vec<(String, WaitStrategy)> {
+    value
+        .split(';')
+        .filter(|rule| !rule.trim().is_empty())
+        .filter_map(|rule| {
+            let Some((domain, strategy)) = rule.split_once('=') else {
+                eprintln!("Ignoring headless wait rule without a domain: {:?}", rule);
+                return None;
+            };
+
+            match strategy.parse() {
+                Ok(strategy) => Some((domain.trim().to_ascii_lowercase(), strategy)),
+                Err(e) => {
+                    eprintln!("Ignoring headless wait rule for {}: {}", domain.trim(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
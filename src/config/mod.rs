@@ -1,4 +1,5 @@
 pub mod constants;
+pub mod headless;
 pub mod settings;
 pub mod state;
 pub mod url;
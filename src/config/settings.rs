@@ -2,11 +2,16 @@ use axum::Router;
 use redis::Client as RedisClient;
 use sqlx::migrate::MigrateError;
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::{sync::Arc, time::Instant};
+use std::{path::PathBuf, sync::Arc, time::Instant};
 use thiserror::Error;
 
 use super::state::AppState;
 use super::{constants::Settings, url::get_routes};
+use crate::auth::{quota::KeyLimiter, signature::UrlSigner};
+use crate::preview::{
+    extractor::ExtractorRegistry, fetcher::FetcherChain, reputation::ReputationChecker,
+    rules::RuleStore, safety::SafetyClassifier,
+};
 
 #[derive(Error, Debug)]
 pub enum MigrationError {
@@ -26,7 +31,9 @@ pub enum MigrationError {
 /// This function will panic if the `DATABASE_URL` environment variable is not set
 /// or if the connection pool cannot be created.
 pub async fn create_pool() -> PgPool {
-    let database_url = Settings::from_env().database_url;
+    let database_url = Settings::from_env()
+        .database_url
+        .expect("DATABASE_URL must be set");
 
     PgPoolOptions::new()
         .max_connections(5)
@@ -43,7 +50,9 @@ pub async fn create_pool() -> PgPool {
 /// # Panics
 /// This function will panic if the server cannot be started.
 pub async fn run_server(state: Arc<AppState>) {
-    let routes: Router = get_routes().with_state(state);
+    state.rules.clone().watch();
+    state.reputation.clone().watch();
+    let routes: Router = get_routes(state.clone()).with_state(state);
 
     let tcp_listener = tokio::net::TcpListener::bind(Settings::from_env().app_host)
         .await
@@ -129,3 +138,82 @@ pub async fn create_cache_client() -> Option<RedisClient> {
 
     redis_url.map(|url| redis::Client::open(url).expect("Failed to create Redis client"))
 }
+
+/// Builds the fetcher chain configured in `FETCHERS`.
+///
+/// # Arguments
+/// * `reputation` - Checks every URL the fetchers are about to request
+///
+/// # Returns
+/// * `FetcherChain` - The fetchers `fetch_metadata` tries, in order
+pub fn create_fetcher_chain(reputation: Arc<ReputationChecker>) -> FetcherChain {
+    FetcherChain::from_settings(&Settings::from_env()).with_reputation(Some(reputation))
+}
+
+/// Builds the extractor registry with the generic and built-in site extractors
+/// and the extractor for the rules file.
+///
+/// # Arguments
+/// * `rules` - The rules loaded from `RULES_FILE`
+///
+/// # Returns
+/// * `ExtractorRegistry` - The extractors run over every fetched HTML page
+pub fn create_extractor_registry(rules: Arc<RuleStore>) -> ExtractorRegistry {
+    ExtractorRegistry::with_builtin(&Settings::from_env(), rules)
+}
+
+/// Loads the rules file configured in `RULES_FILE`, if any.
+///
+/// # Returns
+/// * `RuleStore` - The rules, reloaded by the server when the file changes
+pub fn create_rule_store() -> RuleStore {
+    RuleStore::new(Settings::from_env().rules_file.map(PathBuf::from))
+}
+
+/// Reads the URL shortener domains: the built-in ones plus `SHORTENER_HOSTS`.
+///
+/// # Returns
+/// * `Vec<String>` - Domains whose redirect targets are cached
+pub fn load_shortener_hosts() -> Vec<String> {
+    Settings::from_env().shortener_hosts
+}
+
+/// Reads whether preview responses are HTML-escaped, see `ESCAPE_HTML`.
+///
+/// # Returns
+/// * `bool` - `false` unless `ESCAPE_HTML=true`
+pub fn load_escape_html() -> bool {
+    Settings::from_env().escape_html
+}
+
+/// Loads the URL blocklists from `BLOCKLIST_FILES` and `HASH_PREFIX_FILES`.
+///
+/// # Returns
+/// * `ReputationChecker` - Consulted before every URL is fetched
+pub fn create_reputation_checker() -> ReputationChecker {
+    ReputationChecker::from_settings(&Settings::from_env())
+}
+
+/// Loads the domain category lists from `SAFETY_DOMAIN_LISTS`.
+///
+/// # Returns
+/// * `SafetyClassifier` - Flags adult, gambling and violent previews
+pub fn create_safety_classifier() -> SafetyClassifier {
+    SafetyClassifier::from_settings(&Settings::from_env())
+}
+
+/// Creates the per-key rate limiter and quota counter from the environment.
+///
+/// # Returns
+/// * `KeyLimiter` - Limits shared through Redis when `CACHE_DATABASE_URL` is set
+pub fn create_key_limiter() -> KeyLimiter {
+    KeyLimiter::from_settings(&Settings::from_env())
+}
+
+/// Creates the URL signer from `SIGNING_SECRETS`.
+///
+/// # Returns
+/// * `UrlSigner` - Signs with the first secret and verifies with any
+pub fn create_url_signer() -> UrlSigner {
+    UrlSigner::from_settings(&Settings::from_env())
+}
@@ -2,8 +2,25 @@ use redis::Client as RedisClient;
 use sqlx::PgPool;
 use std::sync::Arc;
 
+use crate::auth::{quota::KeyLimiter, signature::UrlSigner};
+use crate::preview::{
+    extractor::ExtractorRegistry, fetcher::FetcherChain, reputation::ReputationChecker,
+    rules::RuleStore, safety::SafetyClassifier,
+};
+
 #[derive(Clone)]
 pub struct AppState {
     pub pool: Arc<PgPool>,
     pub cache_pool: Arc<Option<RedisClient>>,
+    pub fetchers: Arc<FetcherChain>,
+    pub extractors: Arc<ExtractorRegistry>,
+    pub rules: Arc<RuleStore>,
+    /// URL shortener domains whose targets are cached, see `SHORTENER_HOSTS`.
+    pub shortener_hosts: Arc<Vec<String>>,
+    pub reputation: Arc<ReputationChecker>,
+    pub safety: Arc<SafetyClassifier>,
+    /// Whether response strings are HTML-escaped, see `ESCAPE_HTML`.
+    pub escape_html: bool,
+    pub key_limiter: Arc<KeyLimiter>,
+    pub url_signer: Arc<UrlSigner>,
 }
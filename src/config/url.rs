@@ -1,10 +1,30 @@
-use axum::Router;
+use axum::{middleware, Router};
 use std::sync::Arc;
 
-use crate::preview::url::get_routes as get_preview_routes;
+use crate::{
+    auth::middleware::{always_require_api_key, require_api_key, verify_signature},
+    preview::url::{get_metrics_routes, get_routes as get_preview_routes},
+};
 
 use super::state::AppState;
 
-pub fn get_routes() -> Router<Arc<AppState>> {
-    Router::new().merge(get_preview_routes())
+/// Builds every route. Previews are behind the URL signature check and then
+/// the API key check, see [`verify_signature`] and [`require_api_key`];
+/// metrics always need an API key, see [`always_require_api_key`].
+pub fn get_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let preview_routes = get_preview_routes()
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_api_key,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            verify_signature,
+        ));
+    let metrics_routes = get_metrics_routes().layer(middleware::from_fn_with_state(
+        state,
+        always_require_api_key,
+    ));
+
+    Router::new().merge(preview_routes).merge(metrics_routes)
 }
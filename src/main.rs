@@ -1,28 +1,39 @@
 use std::{env, sync::Arc};
 
+mod auth;
 mod config;
 mod preview;
 
 #[tokio::main]
 async fn main() {
-    let pool = config::settings::create_pool().await;
-    let pool = Arc::new(pool);
-
-    let cache_pool = config::settings::create_cache_client().await;
-    let cache_pool = Arc::new(cache_pool);
-
-    let state = Arc::new(config::state::AppState { pool, cache_pool });
-
     let args: Vec<String> = env::args().collect();
 
+    // Each command only builds what it uses, so `sign` runs without a
+    // database and `keys` without the fetchers.
     if args.len() > 1 {
         match args[1].as_str() {
-            "migrate" => match config::settings::apply_migrations(&state.pool).await {
-                Ok(_) => println!("Migrations successful"),
-                Err(e) => eprintln!("Migration failed: {}", e),
-            },
+            "migrate" => {
+                let pool = config::settings::create_pool().await;
+                match config::settings::apply_migrations(&pool).await {
+                    Ok(_) => println!("Migrations successful"),
+                    Err(e) => eprintln!("Migration failed: {}", e),
+                }
+            }
             "server" => {
-                config::settings::run_server(state).await;
+                config::settings::run_server(create_state().await).await;
+            }
+            "keys" => {
+                let pool = Arc::new(config::settings::create_pool().await);
+                let key_limiter = config::settings::create_key_limiter();
+                if let Err(e) = auth::cli::run_keys_command(pool, &key_limiter, &args[2..]).await {
+                    eprintln!("{}", e);
+                }
+            }
+            "sign" => {
+                let url_signer = config::settings::create_url_signer();
+                if let Err(e) = auth::cli::run_sign_command(&url_signer, &args[2..]) {
+                    eprintln!("{}", e);
+                }
             }
             _ => {
                 eprintln!("Invalid command provided");
@@ -30,10 +41,45 @@ async fn main() {
             }
         }
     } else {
-        config::settings::run_server(state).await;
+        config::settings::run_server(create_state().await).await;
     }
 }
 
+/// Builds everything the server needs.
+async fn create_state() -> Arc<config::state::AppState> {
+    let pool = config::settings::create_pool().await;
+    let pool = Arc::new(pool);
+
+    let cache_pool = config::settings::create_cache_client().await;
+    let cache_pool = Arc::new(cache_pool);
+
+    let reputation = Arc::new(config::settings::create_reputation_checker());
+    let fetchers = Arc::new(config::settings::create_fetcher_chain(reputation.clone()));
+    let rules = Arc::new(config::settings::create_rule_store());
+    let extractors = Arc::new(config::settings::create_extractor_registry(rules.clone()));
+    let shortener_hosts = Arc::new(config::settings::load_shortener_hosts());
+    let safety = Arc::new(config::settings::create_safety_classifier());
+    let escape_html = config::settings::load_escape_html();
+    let key_limiter = Arc::new(config::settings::create_key_limiter());
+    let url_signer = Arc::new(config::settings::create_url_signer());
+
+    Arc::new(config::state::AppState {
+        pool,
+        cache_pool,
+        fetchers,
+        extractors,
+        rules,
+        shortener_hosts,
+        reputation,
+        safety,
+        escape_html,
+        key_limiter,
+        url_signer,
+    })
+}
+
 fn print_usage() {
     println!("Usage: cargo run [migrate|server]");
+    println!("       cargo run {}", auth::cli::KEYS_USAGE);
+    println!("       cargo run {}", auth::cli::SIGN_USAGE);
 }
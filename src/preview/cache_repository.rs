@@ -3,7 +3,7 @@ use redis::{AsyncCommands, Client as RedisClient};
 use std::{sync::Arc, time::Duration};
 use thiserror::Error;
 
-use super::model::{MetaData, MetaDataResponse};
+use super::model::{FetchedWith, MetaData, MetaDataResponse, ResolvedRedirect};
 
 #[derive(Error, Debug)]
 pub enum CacheError {
@@ -19,8 +19,24 @@ pub type Result<T> = std::result::Result<T, CacheError>;
 
 #[async_trait]
 pub trait CacheRepository {
-    async fn get_metadata(&self, url: &str) -> Result<Option<MetaDataResponse>>;
-    async fn set_metadata(&self, metadata: &MetaData, ttl: Duration) -> Result<()>;
+    async fn get_metadata(&self, key: &str) -> Result<Option<MetaDataResponse>>;
+    async fn set_metadata(&self, key: &str, metadata: &MetaData, ttl: Duration) -> Result<()>;
+    /// Returns the fetcher that last produced a preview for the host.
+    async fn get_strategy(&self, host: &str) -> Result<Option<FetchedWith>>;
+    async fn set_strategy(
+        &self,
+        host: &str,
+        fetched_with: FetchedWith,
+        ttl: Duration,
+    ) -> Result<()>;
+    /// Returns where a shortened URL last led.
+    async fn get_redirect(&self, url: &str) -> Result<Option<ResolvedRedirect>>;
+    async fn set_redirect(
+        &self,
+        url: &str,
+        resolved: &ResolvedRedirect,
+        ttl: Duration,
+    ) -> Result<()>;
 }
 
 pub struct RedisRepository {
@@ -56,6 +72,20 @@ impl RedisRepository {
         RedisRepositoryBuilder::new()
     }
 
+    /// Previews get their own namespace, so a requested URL can never name
+    /// another kind of entry such as `strategy:example.com`.
+    fn metadata_key(key: &str) -> String {
+        format!("preview:{}", key)
+    }
+
+    fn strategy_key(host: &str) -> String {
+        format!("strategy:{}", host)
+    }
+
+    fn redirect_key(url: &str) -> String {
+        format!("redirect:{}", url)
+    }
+
     async fn get_connection(&self) -> Result<impl AsyncCommands> {
         self.client
             .get_multiplexed_async_connection()
@@ -66,10 +96,13 @@ impl RedisRepository {
 
 #[async_trait]
 impl CacheRepository for RedisRepository {
-    async fn get_metadata(&self, url: &str) -> Result<Option<MetaDataResponse>> {
+    async fn get_metadata(&self, key: &str) -> Result<Option<MetaDataResponse>> {
         let mut conn = self.get_connection().await?;
 
-        let result: Option<String> = conn.get(url).await.map_err(CacheError::Redis)?;
+        let result: Option<String> = conn
+            .get(Self::metadata_key(key))
+            .await
+            .map_err(CacheError::Redis)?;
 
         match result {
             Some(data) => Ok(Some(
@@ -79,12 +112,74 @@ impl CacheRepository for RedisRepository {
         }
     }
 
-    async fn set_metadata(&self, metadata: &MetaData, ttl: Duration) -> Result<()> {
+    async fn set_metadata(&self, key: &str, metadata: &MetaData, ttl: Duration) -> Result<()> {
         let mut conn = self.get_connection().await?;
 
         let json = serde_json::to_string(metadata).map_err(CacheError::Serialization)?;
 
-        conn.set_ex(metadata.link.as_str(), json, ttl.as_secs() as u64)
+        conn.set_ex(Self::metadata_key(key), json, ttl.as_secs())
+            .await
+            .map_err(CacheError::Redis)
+    }
+
+    async fn get_strategy(&self, host: &str) -> Result<Option<FetchedWith>> {
+        let mut conn = self.get_connection().await?;
+
+        let result: Option<String> = conn
+            .get(Self::strategy_key(host))
+            .await
+            .map_err(CacheError::Redis)?;
+
+        match result {
+            Some(data) => Ok(Some(
+                serde_json::from_str(&data).map_err(CacheError::Serialization)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_strategy(
+        &self,
+        host: &str,
+        fetched_with: FetchedWith,
+        ttl: Duration,
+    ) -> Result<()> {
+        let mut conn = self.get_connection().await?;
+
+        let json = serde_json::to_string(&fetched_with).map_err(CacheError::Serialization)?;
+
+        conn.set_ex(Self::strategy_key(host), json, ttl.as_secs())
+            .await
+            .map_err(CacheError::Redis)
+    }
+
+    async fn get_redirect(&self, url: &str) -> Result<Option<ResolvedRedirect>> {
+        let mut conn = self.get_connection().await?;
+
+        let result: Option<String> = conn
+            .get(Self::redirect_key(url))
+            .await
+            .map_err(CacheError::Redis)?;
+
+        match result {
+            Some(data) => Ok(Some(
+                serde_json::from_str(&data).map_err(CacheError::Serialization)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_redirect(
+        &self,
+        url: &str,
+        resolved: &ResolvedRedirect,
+        ttl: Duration,
+    ) -> Result<()> {
+        let mut conn = self.get_connection().await?;
+
+        let json = serde_json::to_string(resolved).map_err(CacheError::Serialization)?;
+
+        conn.set_ex(Self::redirect_key(url), json, ttl.as_secs())
             .await
             .map_err(CacheError::Redis)
     }
@@ -0,0 +1,180 @@
+use serde_json::{json, Value};
+
+/// Cookies that record a refusal of optional cookies for common consent
+/// management platforms, so their banners and walls are not shown.
+///
+/// Enabled per rule with `consent = ["google", ...]`, see `RULES_FILE`.
+pub const CONSENT_COOKIE_PRESETS: [(&str, &[(&str, &str)]); 6] = [
+    // Google and YouTube's consent.google.com interstitial.
+    (
+        "google",
+        &[
+            ("CONSENT", "PENDING+987"),
+            ("SOCS", "CAESHAgBEhJnd3NfMjAyMzA4MTAtMF9SQzEaAmVuIAEaBgiA_LyaBg"),
+        ],
+    ),
+    (
+        "onetrust",
+        &[("OptanonAlertBoxClosed", "2024-01-01T00:00:00.000Z")],
+    ),
+    (
+        "cookiebot",
+        &[(
+            "CookieConsent",
+            "{stamp:%27-1%27%2Cnecessary:true%2Cpreferences:false%2Cstatistics:false%2Cmarketing:false%2Cmethod:%27explicit%27%2Cver:1}",
+        )],
+    ),
+    (
+        "cookieyes",
+        &[(
+            "cookieyes-consent",
+            "consent:no,action:yes,necessary:yes,functional:no,analytics:no,performance:no,advertisement:no",
+        )],
+    ),
+    ("complianz", &[("cmplz_banner-status", "dismissed")]),
+    ("cookie-notice", &[("cookie_notice_accepted", "false")]),
+];
+
+/// The cookies of the preset called `name`.
+pub fn consent_cookies(name: &str) -> Option<&'static [(&'static str, &'static str)]> {
+    CONSENT_COOKIE_PRESETS
+        .iter()
+        .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+        .map(|(_, cookies)| *cookies)
+}
+
+/// A consent management platform whose banner the headless browser dismisses.
+pub struct ConsentFramework {
+    pub name: &'static str,
+    /// Matches the banner while it is shown.
+    pub banner: &'static str,
+    /// Buttons tried in order; reject buttons come before accept buttons.
+    pub buttons: &'static [&'static str],
+}
+
+/// Common consent management platforms. Banners rendered in a cross-origin
+/// iframe or a closed shadow root cannot be reached and are left alone.
+pub const CONSENT_FRAMEWORKS: [ConsentFramework; 11] = [
+    ConsentFramework {
+        name: "onetrust",
+        banner: "#onetrust-banner-sdk, #onetrust-pc-sdk",
+        buttons: &[
+            "#onetrust-reject-all-handler",
+            ".ot-pc-refuse-all-handler",
+            "#onetrust-accept-btn-handler",
+        ],
+    },
+    ConsentFramework {
+        name: "cookiebot",
+        banner: "#CybotCookiebotDialog",
+        buttons: &[
+            "#CybotCookiebotDialogBodyButtonDecline",
+            "#CybotCookiebotDialogBodyLevelButtonLevelOptinDeclineAll",
+            "#CybotCookiebotDialogBodyLevelButtonLevelOptinAllowAll",
+        ],
+    },
+    ConsentFramework {
+        name: "didomi",
+        banner: "#didomi-popup, #didomi-notice",
+        buttons: &[
+            "#didomi-notice-disagree-button",
+            ".didomi-continue-without-agreeing",
+            "#didomi-notice-agree-button",
+        ],
+    },
+    ConsentFramework {
+        name: "quantcast",
+        banner: ".qc-cmp2-container",
+        buttons: &[
+            r#".qc-cmp2-summary-buttons button[mode="secondary"]"#,
+            r#".qc-cmp2-summary-buttons button[mode="primary"]"#,
+        ],
+    },
+    ConsentFramework {
+        name: "trustarc",
+        banner: "#truste-consent-track, #consent_blackbar",
+        buttons: &["#truste-consent-required", "#truste-consent-button"],
+    },
+    ConsentFramework {
+        name: "cookieyes",
+        banner: ".cky-consent-container",
+        buttons: &[".cky-btn-reject", ".cky-btn-accept"],
+    },
+    ConsentFramework {
+        name: "complianz",
+        banner: "#cmplz-cookiebanner-container .cmplz-cookiebanner",
+        buttons: &[".cmplz-btn.cmplz-deny", ".cmplz-btn.cmplz-accept"],
+    },
+    ConsentFramework {
+        name: "osano",
+        banner: ".osano-cm-dialog",
+        buttons: &[
+            ".osano-cm-denyAll",
+            ".osano-cm-accept-all",
+            ".osano-cm-accept",
+        ],
+    },
+    ConsentFramework {
+        name: "cookie-notice",
+        banner: "#cookie-notice",
+        buttons: &["#cn-refuse-cookie", "#cn-accept-cookie"],
+    },
+    ConsentFramework {
+        name: "borlabs",
+        banner: "#BorlabsCookieBox",
+        buttons: &[
+            "#BorlabsCookieBox a[data-cookie-refuse]",
+            "#BorlabsCookieBox a[data-cookie-accept]",
+        ],
+    },
+    // The consent.google.com interstitial, whose first form rejects all.
+    ConsentFramework {
+        name: "google",
+        banner: r#"form[action*="consent.google"]"#,
+        buttons: &[
+            r#"button[aria-label^="Reject all"]"#,
+            r#"form[action*="consent.google"] button"#,
+        ],
+    },
+];
+
+/// Builds the script that clicks the first visible consent button.
+///
+/// `selectors` from `HEADLESS_CONSENT_SELECTORS` are tried first, on any
+/// page; the buttons of [`CONSENT_FRAMEWORKS`] only while their banner is
+/// visible. The script evaluates to the name of the framework whose button
+/// was clicked (`custom` for `selectors`), or `null`.
+pub fn consent_script(selectors: &[String]) -> String {
+    let custom = json!(["custom", null, selectors]);
+    let frameworks = CONSENT_FRAMEWORKS
+        .iter()
+        .map(|framework| json!([framework.name, framework.banner, framework.buttons]));
+    let candidates = std::iter::once(custom)
+        .chain(frameworks)
+        .collect::<Vec<_>>();
+
+    format!(
+        r#"(() => {{
+    const visible = (element) => !!(element.offsetWidth || element.offsetHeight || element.getClientRects().length);
+    const find = (selector) => {{
+        try {{
+            return Array.from(document.querySelectorAll(selector)).find(visible);
+        }} catch (e) {{
+            return undefined;
+        }}
+    }};
+    for (const [name, banner, buttons] of {}) {{
+        if (banner && !find(banner)) continue;
+        for (const selector of buttons) {{
+            const button = find(selector);
+            if (button) {{
+                button.click();
+                return name;
+            }}
+        }}
+    }}
+    return null;
+}})()"#,
+        Value::Array(candidates)
+    )
+}
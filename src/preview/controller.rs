@@ -2,21 +2,68 @@ use std::{sync::Arc, time::Duration};
 
 use axum::{
     extract::{Query, State},
+    http::{
+        header::{ACCEPT_LANGUAGE, RETRY_AFTER},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
     Json,
 };
+use serde_json::json;
 
 use super::{
     cache_repository::{CacheRepository, RedisRepository},
-    model::{MetaDataResponse, PreviewParams},
-    service::fetch_metadata,
+    fetcher::{FetchError, FetchOptions},
+    language::accept_language,
+    model::{
+        FetchStrategy, FetchedWith, MetaData, MetaDataResponse, PreviewParams, ResolvedRedirect,
+        Safety,
+    },
+    redirect::is_shortener,
+    repository::{MetadataRepository, Repository},
+    sanitize::{escape_html, sanitize},
+    service::{fetch_feed_entries, fetch_metadata},
 };
 use crate::config::state::AppState;
 
+/// How long the fetcher that worked for a host is remembered.
+const STRATEGY_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// How long the target of a shortened URL is remembered.
+const SHORTENER_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
 pub async fn fetch_link_preview(
     State(state): State<Arc<AppState>>,
     Query(params): Query<PreviewParams>,
-) -> Json<MetaDataResponse> {
+    headers: HeaderMap,
+) -> Result<Json<MetaDataResponse>, FetchError> {
     let url = params.url.as_str();
+    let language = params
+        .lang
+        .as_deref()
+        .or_else(|| {
+            headers
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+        })
+        .and_then(accept_language);
+    let cache_key = cache_key(&params, language.as_deref());
+
+    // Blocklisted URLs are refused before the cache or any fetch is consulted.
+    state
+        .reputation
+        .check([url])
+        .inspect_err(|e| state.fetchers.metrics().record_failure(e))?;
+
+    let rules = state.rules.current();
+    let options = FetchOptions {
+        language,
+        ..rules.fetch_options(url)
+    };
+    // A strategy forced by the rules file replaces `auto` but not an explicit one.
+    let strategy = match params.strategy {
+        FetchStrategy::Auto => rules.fetch_strategy(url).unwrap_or(FetchStrategy::Auto),
+        strategy => strategy,
+    };
 
     match &*state.cache_pool {
         Some(cache_client) => {
@@ -25,26 +72,286 @@ pub async fn fetch_link_preview(
                 .build()
                 .expect("Failed to build cache repository");
 
-            if let Some(metadata) = cache_repo
-                .get_metadata(url)
-                .await
-                .expect("Failed to get metadata from cache")
-            {
-                return Json(metadata);
+            // Previews fetched with credentials are never shared through the cache.
+            // Unreadable entries are treated as a miss and overwritten below.
+            if !options.private {
+                match cache_repo.get_metadata(&cache_key).await {
+                    Ok(Some(metadata)) => {
+                        return Ok(Json(present(metadata, &params, state.escape_html)));
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Failed to get metadata from cache: {}", e),
+                }
+            }
+
+            let host = reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(String::from));
+
+            let strategy = match (strategy, host.as_deref()) {
+                (FetchStrategy::Auto, Some(host)) => remembered_strategy(&cache_repo, host).await,
+                (strategy, _) => strategy,
+            };
+
+            let shortened = is_shortener(url, &state.shortener_hosts);
+            let resolved = if shortened {
+                cache_repo.get_redirect(url).await.unwrap_or_else(|e| {
+                    eprintln!("Failed to get redirect from cache: {}", e);
+                    None
+                })
+            } else {
+                None
+            };
+
+            let metadata = fetch_and_store_metadata(
+                &state,
+                url,
+                resolved.as_ref(),
+                strategy,
+                &options,
+                params.feed_entries,
+            )
+            .await?;
+
+            // Remember where a freshly unwrapped shortener led, unless
+            // credentials may have changed where it leads.
+            let unwrapped = metadata.final_url.as_ref().filter(|final_url| {
+                !options.private && shortened && resolved.is_none() && *final_url != url
+            });
+            if let Some(final_url) = unwrapped {
+                let resolved = ResolvedRedirect {
+                    final_url: final_url.clone(),
+                    redirects: metadata.redirects.clone().unwrap_or_default(),
+                };
+                if let Err(e) = cache_repo.set_redirect(url, &resolved, SHORTENER_TTL).await {
+                    eprintln!("Failed to store redirect in cache: {}", e);
+                }
             }
 
-            let metadata = fetch_metadata(url).await.expect("Failed to fetch metadata");
+            // Only record fresh decisions so a remembered strategy expires,
+            // and only from public pages.
+            if let (FetchStrategy::Auto, Some(host), Some(fetched_with), false) = (
+                strategy,
+                host.as_deref(),
+                metadata.fetched_with,
+                options.private,
+            ) {
+                if let Err(e) = cache_repo
+                    .set_strategy(host, fetched_with, STRATEGY_TTL)
+                    .await
+                {
+                    eprintln!("Failed to store fetch strategy in cache: {}", e);
+                }
+            }
 
-            cache_repo
-                .set_metadata(&metadata, Duration::from_secs(10 * 60))
-                .await
-                .expect("Failed to store metadata in cache");
+            if !options.private {
+                if let Err(e) = cache_repo
+                    .set_metadata(&cache_key, &metadata, Duration::from_secs(10 * 60))
+                    .await
+                {
+                    eprintln!("Failed to store metadata in cache: {}", e);
+                }
+            }
 
-            Json(MetaDataResponse::from(&metadata))
+            Ok(Json(present(
+                MetaDataResponse::from(&metadata),
+                &params,
+                state.escape_html,
+            )))
         }
         None => {
-            let metadata = fetch_metadata(url).await.expect("Failed to fetch metadata");
-            Json(MetaDataResponse::from(&metadata))
+            let metadata = fetch_and_store_metadata(
+                &state,
+                url,
+                None,
+                strategy,
+                &options,
+                params.feed_entries,
+            )
+            .await?;
+            Ok(Json(present(
+                MetaDataResponse::from(&metadata),
+                &params,
+                state.escape_html,
+            )))
         }
     }
 }
+
+impl IntoResponse for FetchError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            FetchError::RobotsDisallowed { .. } | FetchError::Unsafe { .. } => {
+                StatusCode::FORBIDDEN
+            }
+            FetchError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            FetchError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            FetchError::NoFetcher(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        let body = Json(json!({
+            "error": self.to_string(),
+            "kind": self.kind(),
+            "upstream_status": self.upstream_status(),
+        }));
+
+        match self {
+            FetchError::RateLimited { retry_after, .. } => (
+                status,
+                [(
+                    RETRY_AFTER,
+                    (retry_after.as_secs_f64().ceil() as u64).max(1).to_string(),
+                )],
+                body,
+            )
+                .into_response(),
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+/// Applies the per-request presentation options to a stored preview. Images
+/// of flagged previews are dropped here rather than before caching, so
+/// callers that want them still get them.
+///
+/// With `escape_html`, every string but the URLs is HTML-escaped last, so
+/// stored and cached previews stay plain text.
+fn present(metadata: MetaDataResponse, params: &PreviewParams, escape: bool) -> MetaDataResponse {
+    let flagged = metadata.safety.as_ref().is_some_and(Safety::is_flagged);
+    let metadata = if params.hide_flagged_images && flagged {
+        MetaDataResponse {
+            image: None,
+            ..metadata
+        }
+    } else {
+        metadata
+    };
+
+    if escape {
+        escape_html(metadata)
+    } else {
+        metadata
+    }
+}
+
+/// Serves fetch counters in the Prometheus text format.
+pub async fn metrics(State(state): State<Arc<AppState>>) -> String {
+    state.fetchers.metrics().render()
+}
+
+/// Builds the cache key for a preview request. Forced strategies, requested
+/// languages and feed entries are cached separately so they never serve a
+/// preview produced by another fetcher, in another language or without the
+/// entries.
+fn cache_key(params: &PreviewParams, language: Option<&str>) -> String {
+    let key = match params.strategy {
+        FetchStrategy::Auto => params.url.clone(),
+        strategy => format!("{}:{}", strategy.as_str(), params.url),
+    };
+    let key = if params.feed_entries {
+        format!("feeds:{}", key)
+    } else {
+        key
+    };
+
+    match language {
+        Some(language) => format!(
+            "lang={}:{}",
+            language.replace(' ', "").to_ascii_lowercase(),
+            key
+        ),
+        None => key,
+    }
+}
+
+/// Skips straight to the headless browser for hosts where the static fetch
+/// previously fell short.
+async fn remembered_strategy(cache_repo: &impl CacheRepository, host: &str) -> FetchStrategy {
+    match cache_repo.get_strategy(host).await {
+        Ok(Some(FetchedWith::Browser)) => FetchStrategy::Browser,
+        Ok(_) => FetchStrategy::Auto,
+        Err(e) => {
+            eprintln!("Failed to get fetch strategy from cache: {}", e);
+            FetchStrategy::Auto
+        }
+    }
+}
+
+/// Fetches metadata for the URL, and the latest entries of its feeds if
+/// requested, and persists it to Postgres.
+///
+/// A shortened URL whose target is already known is not requested again;
+/// the target is fetched directly and the known redirects are reported.
+///
+/// The fetchers check every URL against the blocklists before requesting
+/// it, so a redirect to a blocklisted site is refused before it is followed.
+/// The URLs it redirected through are then flagged in `reputation` like the
+/// requested one.
+///
+/// The preview is sanitized before it is stored, see [`sanitize`]. Previews
+/// fetched with cookies or credentials from the rules file
+/// (`options.private`) are never stored, since the table is shared by link.
+///
+/// A failure to persist is logged but does not fail the request.
+async fn fetch_and_store_metadata(
+    state: &AppState,
+    url: &str,
+    resolved: Option<&ResolvedRedirect>,
+    strategy: FetchStrategy,
+    options: &FetchOptions,
+    feed_entries: bool,
+) -> Result<MetaData, FetchError> {
+    let target = resolved.map_or(url, |resolved| resolved.final_url.as_str());
+    let mut metadata = fetch_metadata(
+        target,
+        strategy,
+        options,
+        &state.fetchers,
+        &state.extractors,
+        &state.safety,
+    )
+    .await
+    .inspect_err(|e| state.fetchers.metrics().record_failure(e))?;
+
+    if let Some(resolved) = resolved {
+        let redirects = resolved
+            .redirects
+            .iter()
+            .cloned()
+            .chain(metadata.redirects.take().unwrap_or_default())
+            .collect::<Vec<_>>();
+        metadata.redirects = (!redirects.is_empty()).then_some(redirects);
+        metadata.link = url.to_string();
+    }
+
+    let redirected = metadata
+        .redirects
+        .iter()
+        .flatten()
+        .map(|redirect| redirect.url.as_str())
+        .chain(metadata.final_url.as_deref());
+    metadata.reputation = state
+        .reputation
+        .check(std::iter::once(url).chain(redirected))
+        .inspect_err(|e| state.fetchers.metrics().record_failure(e))?;
+
+    if feed_entries {
+        metadata = fetch_feed_entries(metadata, options.language.clone(), &state.fetchers).await;
+    }
+
+    let metadata = sanitize(metadata);
+    if options.private {
+        return Ok(metadata);
+    }
+
+    let repository = Repository::builder()
+        .with_pool(state.pool.clone())
+        .build()
+        .expect("Failed to build repository");
+
+    if let Err(e) = repository.insert_metadata(&metadata).await {
+        eprintln!("Failed to store metadata in database: {}", e);
+    }
+
+    Ok(metadata)
+}
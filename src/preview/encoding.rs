@@ -0,0 +1,99 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Number of leading bytes scanned for a `<meta charset>` declaration.
+///
+/// The HTML spec only requires the first 1024 bytes, but plenty of real-world
+/// pages put the declaration after a long block of comments or scripts.
+const META_PRESCAN_BYTES: usize = 4096;
+
+/// Decodes an HTML document into a `String`, sniffing its character encoding.
+///
+/// The encoding is chosen in the order browsers use:
+/// 1. A byte order mark.
+/// 2. The `charset` parameter of the `Content-Type` header.
+/// 3. A `<meta charset>` or `<meta http-equiv="Content-Type">` declaration.
+/// 4. Statistical detection over the document bytes.
+///
+/// # Arguments
+/// * `bytes` - The raw response body.
+/// * `content_type` - The value of the `Content-Type` response header, if any.
+///
+/// # Returns
+/// * The decoded document. Malformed sequences are replaced with U+FFFD.
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_from_content_type))
+        .or_else(|| charset_from_meta(bytes))
+        .unwrap_or_else(|| detect_encoding(bytes));
+
+    let (content, _, _) = encoding.decode(bytes);
+    content.into_owned()
+}
+
+/// Extracts the `charset` parameter from a `Content-Type` value.
+fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes())
+    })
+}
+
+/// Scans the start of the document for a charset declared in a `<meta>` tag.
+fn charset_from_meta(bytes: &[u8]) -> Option<&'static Encoding> {
+    let prefix = &bytes[..bytes.len().min(META_PRESCAN_BYTES)];
+    let prefix = prefix.to_ascii_lowercase();
+
+    let mut rest = prefix.as_slice();
+    while let Some(start) = find(rest, b"<meta") {
+        let tag = &rest[start..];
+        let end = tag.iter().position(|&b| b == b'>').unwrap_or(tag.len());
+
+        if let Some(encoding) = charset_from_meta_tag(&tag[..end]) {
+            // A page served as bytes cannot meaningfully declare itself UTF-16.
+            if encoding == UTF_16LE || encoding == UTF_16BE {
+                return Some(UTF_8);
+            }
+            return Some(encoding);
+        }
+
+        rest = &tag[end..];
+    }
+
+    None
+}
+
+fn charset_from_meta_tag(tag: &[u8]) -> Option<&'static Encoding> {
+    let position = find(tag, b"charset")?;
+    let value = tag[position + b"charset".len()..]
+        .iter()
+        .skip_while(|b| b.is_ascii_whitespace())
+        .copied()
+        .collect::<Vec<u8>>();
+
+    let value = value.strip_prefix(b"=")?;
+    let value = value
+        .iter()
+        .skip_while(|b| b.is_ascii_whitespace() || **b == b'"' || **b == b'\'')
+        .take_while(|b| !(b.is_ascii_whitespace() || matches!(b, b'"' | b'\'' | b';' | b'/')))
+        .copied()
+        .collect::<Vec<u8>>();
+
+    Encoding::for_label(&value)
+}
+
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,144 @@
+use reqwest::Url;
+use scraper::Html as ScraperHTML;
+use serde_json::Value;
+
+use crate::preview::{
+    model::MetaDataResponse,
+    pattern::UrlPattern,
+    text::{normalize_text, MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH, MAX_TITLE_LENGTH},
+};
+
+use super::{extras, parse_count, select_all_text, select_attr, select_text, Extractor};
+
+/// Amazon's storefronts. A glob like `amazon.*` would also match hosts such as
+/// `amazon.example.com`.
+const HOSTS: [&str; 22] = [
+    "amazon.com",
+    "amazon.ca",
+    "amazon.com.mx",
+    "amazon.com.br",
+    "amazon.co.uk",
+    "amazon.de",
+    "amazon.fr",
+    "amazon.it",
+    "amazon.es",
+    "amazon.nl",
+    "amazon.se",
+    "amazon.pl",
+    "amazon.com.be",
+    "amazon.com.tr",
+    "amazon.ae",
+    "amazon.sa",
+    "amazon.eg",
+    "amazon.in",
+    "amazon.co.jp",
+    "amazon.sg",
+    "amazon.com.au",
+    "amazon.cn",
+];
+
+/// Product pages on Amazon storefronts.
+pub struct AmazonExtractor;
+
+impl Extractor for AmazonExtractor {
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn patterns(&self) -> Vec<UrlPattern> {
+        HOSTS
+            .iter()
+            .flat_map(|host| {
+                [
+                    UrlPattern::new(host, "/**/dp/**"),
+                    UrlPattern::new(host, "/gp/product/**"),
+                ]
+            })
+            .collect()
+    }
+
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        let bullets = select_all_text(
+            document,
+            "#feature-bullets li span.a-list-item",
+            MAX_DESCRIPTION_LENGTH,
+        );
+
+        let rating = select_attr(document, "#acrPopover", "title").and_then(|title| {
+            title
+                .split_whitespace()
+                .next()?
+                .replace(',', ".")
+                .parse::<f64>()
+                .ok()
+        });
+
+        let review_count = select_text(document, "#acrCustomerReviewText", MAX_FIELD_LENGTH)
+            .and_then(|text| parse_count(text.split_whitespace().next()?));
+
+        MetaDataResponse {
+            title: select_text(document, "#productTitle", MAX_TITLE_LENGTH),
+            description: normalize_text(&bullets.join(" "), MAX_DESCRIPTION_LENGTH),
+            image: select_attr(document, "#landingImage", "data-old-hires")
+                .or_else(|| select_attr(document, "#landingImage", "src")),
+            extras: extras([
+                ("asin", asin(url).map(Value::from)),
+                (
+                    "price",
+                    select_text(
+                        document,
+                        "#corePrice_feature_div .a-offscreen, .a-price .a-offscreen",
+                        MAX_FIELD_LENGTH,
+                    )
+                    .map(Value::from),
+                ),
+                ("rating", rating.map(Value::from)),
+                ("review_count", review_count.map(Value::from)),
+                (
+                    "availability",
+                    select_text(document, "#availability", MAX_FIELD_LENGTH).map(Value::from),
+                ),
+                (
+                    "brand",
+                    select_text(document, "#bylineInfo", MAX_FIELD_LENGTH).map(Value::from),
+                ),
+            ]),
+            ..Default::default()
+        }
+    }
+}
+
+/// Reads the ASIN from `/dp/<asin>` or `/gp/product/<asin>`.
+fn asin(url: &Url) -> Option<String> {
+    let segments = url.path_segments()?.collect::<Vec<_>>();
+    segments
+        .windows(2)
+        .find(|pair| pair[0] == "dp" || pair[0] == "product")
+        .map(|pair| pair[1].to_string())
+        .filter(|asin| asin.len() == 10 && asin.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(url: &str) -> bool {
+        AmazonExtractor.matches(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn matches_product_pages_on_storefronts() {
+        assert!(matches("https://www.amazon.com/Some-Product/dp/B000000000"));
+        assert!(matches("https://www.amazon.co.uk/gp/product/B000000000"));
+        assert!(matches(
+            "https://smile.amazon.co.jp/Some-Product/dp/B000000000"
+        ));
+    }
+
+    #[test]
+    fn ignores_lookalike_hosts() {
+        assert!(!matches("https://amazon.evil.com/dp/B000000000"));
+        assert!(!matches("https://www.amazon.example.co.uk/dp/B000000000"));
+        assert!(!matches("https://notamazon.com/dp/B000000000"));
+    }
+}
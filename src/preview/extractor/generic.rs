@@ -0,0 +1,48 @@
+use reqwest::Url;
+use scraper::Html as ScraperHTML;
+
+use crate::{
+    config::constants::Settings,
+    preview::{
+        feed::discover_feeds, model::MetaDataResponse, pattern::UrlPattern,
+        service::extract_metadata,
+    },
+};
+
+use super::Extractor;
+
+/// Reads OpenGraph and standard `<meta>` tags, falling back to an excerpt of
+/// the article text, and discovers the page's feeds. Matches every URL and
+/// runs last.
+pub struct GenericExtractor {
+    excerpt_length: usize,
+}
+
+impl GenericExtractor {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            excerpt_length: settings.excerpt_length,
+        }
+    }
+}
+
+impl Extractor for GenericExtractor {
+    fn priority(&self) -> i32 {
+        i32::MIN
+    }
+
+    fn patterns(&self) -> Vec<UrlPattern> {
+        Vec::new()
+    }
+
+    fn matches(&self, _url: &Url) -> bool {
+        true
+    }
+
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        MetaDataResponse {
+            feeds: discover_feeds(document, url),
+            ..extract_metadata(document, self.excerpt_length)
+        }
+    }
+}
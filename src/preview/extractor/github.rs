@@ -0,0 +1,93 @@
+use reqwest::Url;
+use scraper::Html as ScraperHTML;
+use serde_json::Value;
+
+use crate::preview::{
+    model::MetaDataResponse,
+    pattern::UrlPattern,
+    text::{MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH},
+};
+
+use super::{extras, parse_count, select_all_text, select_attr, select_text, Extractor};
+
+/// Top-level GitHub paths that are not `owner/repo`.
+const RESERVED_OWNERS: [&str; 12] = [
+    "about",
+    "apps",
+    "collections",
+    "enterprise",
+    "explore",
+    "features",
+    "marketplace",
+    "orgs",
+    "pricing",
+    "settings",
+    "sponsors",
+    "topics",
+];
+
+/// Repository pages on github.com.
+pub struct GitHubExtractor;
+
+impl Extractor for GitHubExtractor {
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn patterns(&self) -> Vec<UrlPattern> {
+        vec![
+            UrlPattern::new("github.com", "/*/*"),
+            UrlPattern::new("github.com", "/*/*/**"),
+        ]
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let owner = url
+            .path_segments()
+            .and_then(|mut segments| segments.next())
+            .unwrap_or_default();
+
+        !RESERVED_OWNERS.contains(&owner)
+            && self.patterns().iter().any(|pattern| pattern.matches(url))
+    }
+
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        let mut segments = url.path_segments().into_iter().flatten();
+        let owner = segments.next().unwrap_or_default().to_string();
+        let repo = segments.next().unwrap_or_default().to_string();
+        let is_repo_root = segments.all(|segment| segment.is_empty());
+
+        let count = |selector: &str| {
+            select_attr(document, selector, "title")
+                .or_else(|| select_text(document, selector, MAX_FIELD_LENGTH))
+                .and_then(|value| parse_count(&value))
+                .map(Value::from)
+        };
+
+        let topics = select_all_text(document, "a.topic-tag", MAX_FIELD_LENGTH);
+
+        MetaDataResponse {
+            title: is_repo_root.then(|| format!("{}/{}", owner, repo)),
+            description: is_repo_root
+                .then(|| select_text(document, ".BorderGrid-cell p.f4", MAX_DESCRIPTION_LENGTH))
+                .flatten(),
+            extras: extras([
+                ("owner", Some(Value::from(owner.as_str()))),
+                ("repo", Some(Value::from(repo.as_str()))),
+                ("stars", count("#repo-stars-counter-star")),
+                ("forks", count("#repo-network-counter")),
+                (
+                    "language",
+                    select_text(
+                        document,
+                        r#"[itemprop="programmingLanguage"], .BorderGrid-cell .color-fg-default.text-bold.mr-1"#,
+                        MAX_FIELD_LENGTH,
+                    )
+                    .map(Value::from),
+                ),
+                ("topics", (!topics.is_empty()).then(|| Value::from(topics))),
+            ]),
+            ..Default::default()
+        }
+    }
+}
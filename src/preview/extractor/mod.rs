@@ -0,0 +1,147 @@
+pub mod amazon;
+pub mod generic;
+pub mod github;
+pub mod reddit;
+pub mod rules;
+pub mod stackoverflow;
+pub mod wikipedia;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use reqwest::Url;
+use scraper::{Html as ScraperHTML, Selector};
+use serde_json::Value;
+
+use crate::config::constants::Settings;
+
+use super::{model::MetaDataResponse, pattern::UrlPattern, rules::RuleStore, text::normalize_text};
+
+use amazon::AmazonExtractor;
+use generic::GenericExtractor;
+use github::GitHubExtractor;
+use reddit::RedditExtractor;
+use rules::RuleExtractor;
+use stackoverflow::StackOverflowExtractor;
+use wikipedia::WikipediaExtractor;
+
+/// Extracts a preview from a parsed page.
+pub trait Extractor: Send + Sync {
+    /// Extractors with a higher priority run first and their fields win.
+    fn priority(&self) -> i32;
+
+    /// The URLs this extractor applies to.
+    fn patterns(&self) -> Vec<UrlPattern>;
+
+    fn matches(&self, url: &Url) -> bool {
+        self.patterns().iter().any(|pattern| pattern.matches(url))
+    }
+
+    /// Returns whatever fields the extractor could find, leaving the rest `None`.
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse;
+}
+
+/// The extractors run over every HTML page, ordered by priority.
+pub struct ExtractorRegistry {
+    extractors: Vec<Box<dyn Extractor>>,
+}
+
+impl ExtractorRegistry {
+    pub fn new(mut extractors: Vec<Box<dyn Extractor>>) -> Self {
+        extractors.sort_by_key(|extractor| std::cmp::Reverse(extractor.priority()));
+        Self { extractors }
+    }
+
+    /// Builds a registry with the generic extractor, all built-in site
+    /// extractors and the extractor for the rules file.
+    pub fn with_builtin(settings: &Settings, rules: Arc<RuleStore>) -> Self {
+        Self::new(vec![
+            Box::new(RuleExtractor::new(rules)),
+            Box::new(GenericExtractor::new(settings)),
+            Box::new(GitHubExtractor),
+            Box::new(AmazonExtractor),
+            Box::new(RedditExtractor),
+            Box::new(WikipediaExtractor),
+            Box::new(StackOverflowExtractor),
+        ])
+    }
+
+    /// Runs every extractor matching `url`, highest priority first, and
+    /// merges their results so that a field is taken from the first
+    /// extractor that found it.
+    ///
+    /// # Arguments
+    /// * `url` - The final URL of the page.
+    /// * `html` - The HTML content of the page.
+    ///
+    /// # Returns
+    /// * `MetaDataResponse` containing the merged metadata.
+    pub fn extract(&self, url: &str, html: &str) -> MetaDataResponse {
+        // Fetchers only return URLs they managed to request.
+        let Ok(url) = Url::parse(url) else {
+            return MetaDataResponse::default();
+        };
+        let document = ScraperHTML::parse_document(html);
+
+        self.extractors
+            .iter()
+            .filter(|extractor| extractor.matches(&url))
+            .fold(MetaDataResponse::default(), |metadata, extractor| {
+                metadata.or(extractor.extract(&url, &document))
+            })
+    }
+}
+
+/// Returns the normalized text of the first element matching `selector`.
+pub fn select_text(document: &ScraperHTML, selector: &str, max_length: usize) -> Option<String> {
+    let selector = Selector::parse(selector).ok()?;
+    document
+        .select(&selector)
+        .find_map(|element| normalize_text(&element.text().collect::<String>(), max_length))
+}
+
+/// Returns the normalized text of every element matching `selector`.
+pub fn select_all_text(document: &ScraperHTML, selector: &str, max_length: usize) -> Vec<String> {
+    let Ok(selector) = Selector::parse(selector) else {
+        return Vec::new();
+    };
+    document
+        .select(&selector)
+        .filter_map(|element| normalize_text(&element.text().collect::<String>(), max_length))
+        .collect()
+}
+
+/// Returns the trimmed value of `attribute` on the first element matching
+/// `selector` that has it.
+pub fn select_attr(document: &ScraperHTML, selector: &str, attribute: &str) -> Option<String> {
+    let selector = Selector::parse(selector).ok()?;
+    document
+        .select(&selector)
+        .filter_map(|element| element.value().attr(attribute))
+        .map(str::trim)
+        .find(|value| !value.is_empty())
+        .map(String::from)
+}
+
+/// Parses a count such as `1,234`, `12.5k` or `3M`.
+pub fn parse_count(value: &str) -> Option<u64> {
+    let value = value.trim().replace(',', "").to_ascii_lowercase();
+    let (number, multiplier) = match value.chars().last()? {
+        'k' => (&value[..value.len() - 1], 1_000.0),
+        'm' => (&value[..value.len() - 1], 1_000_000.0),
+        _ => (value.as_str(), 1.0),
+    };
+    let number = number.trim().parse::<f64>().ok()?;
+    Some((number * multiplier).round() as u64)
+}
+
+/// Collects the present values into an extras map, or `None` if all are absent.
+pub fn extras<const N: usize>(
+    entries: [(&str, Option<Value>); N],
+) -> Option<BTreeMap<String, Value>> {
+    let extras = entries
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key.to_string(), value)))
+        .collect::<BTreeMap<_, _>>();
+
+    (!extras.is_empty()).then_some(extras)
+}
@@ -0,0 +1,72 @@
+use reqwest::Url;
+use scraper::Html as ScraperHTML;
+use serde_json::Value;
+
+use crate::preview::{
+    model::MetaDataResponse,
+    pattern::UrlPattern,
+    text::{normalize_text, MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH, MAX_TITLE_LENGTH},
+};
+
+use super::{extras, parse_count, select_attr, select_text, Extractor};
+
+/// Comment threads on reddit.com, both the current and the old layout.
+pub struct RedditExtractor;
+
+impl Extractor for RedditExtractor {
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn patterns(&self) -> Vec<UrlPattern> {
+        vec![UrlPattern::new("reddit.com", "/r/*/comments/**")]
+    }
+
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        let subreddit = url.path_segments().and_then(|mut segments| segments.nth(1));
+
+        // The current layout renders the post as a <shreddit-post> custom
+        // element; old.reddit.com uses data attributes on `.thing.link`.
+        let post = |attribute: &str, old_attribute: &str| {
+            select_attr(document, "shreddit-post", attribute)
+                .or_else(|| select_attr(document, ".thing.link", old_attribute))
+        };
+
+        MetaDataResponse {
+            title: post("post-title", "data-title")
+                .and_then(|title| normalize_text(&title, MAX_TITLE_LENGTH))
+                .or_else(|| select_text(document, ".thing.link a.title", MAX_TITLE_LENGTH)),
+            description: select_text(
+                document,
+                r#"shreddit-post [slot="text-body"], .thing.link .usertext-body .md"#,
+                MAX_DESCRIPTION_LENGTH,
+            ),
+            extras: extras([
+                ("subreddit", subreddit.map(Value::from)),
+                (
+                    "author",
+                    post("author", "data-author")
+                        .and_then(|author| normalize_text(&author, MAX_FIELD_LENGTH))
+                        .map(Value::from),
+                ),
+                (
+                    "score",
+                    post("score", "data-score")
+                        .and_then(|score| score.parse::<i64>().ok())
+                        .map(Value::from),
+                ),
+                (
+                    "comment_count",
+                    post("comment-count", "data-comments-count")
+                        .and_then(|count| parse_count(&count))
+                        .map(Value::from),
+                ),
+                (
+                    "created_at",
+                    select_attr(document, "shreddit-post", "created-timestamp").map(Value::from),
+                ),
+            ]),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use reqwest::Url;
+use scraper::Html as ScraperHTML;
+
+use crate::preview::{model::MetaDataResponse, pattern::UrlPattern, rules::RuleStore};
+
+use super::Extractor;
+
+/// Applies the selectors from the rules file. Runs before every other
+/// extractor so that a rule can fix a field a built-in extractor gets wrong.
+pub struct RuleExtractor {
+    rules: Arc<RuleStore>,
+}
+
+impl RuleExtractor {
+    pub fn new(rules: Arc<RuleStore>) -> Self {
+        Self { rules }
+    }
+}
+
+impl Extractor for RuleExtractor {
+    fn priority(&self) -> i32 {
+        i32::MAX
+    }
+
+    fn patterns(&self) -> Vec<UrlPattern> {
+        self.rules.current().patterns()
+    }
+
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        self.rules.current().extract(url, document)
+    }
+}
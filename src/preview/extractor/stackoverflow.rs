@@ -0,0 +1,76 @@
+use reqwest::Url;
+use scraper::{Html as ScraperHTML, Selector};
+use serde_json::Value;
+
+use crate::preview::{
+    model::MetaDataResponse,
+    pattern::UrlPattern,
+    text::{MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH, MAX_TITLE_LENGTH},
+};
+
+use super::{extras, parse_count, select_all_text, select_attr, select_text, Extractor};
+
+/// Sites running the Stack Exchange engine.
+const HOSTS: [&str; 6] = [
+    "stackoverflow.com",
+    "stackexchange.com",
+    "superuser.com",
+    "serverfault.com",
+    "askubuntu.com",
+    "mathoverflow.net",
+];
+
+/// Question pages on Stack Overflow and the other Stack Exchange sites.
+pub struct StackOverflowExtractor;
+
+impl Extractor for StackOverflowExtractor {
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn patterns(&self) -> Vec<UrlPattern> {
+        HOSTS
+            .iter()
+            .flat_map(|host| {
+                [
+                    UrlPattern::new(host, "/questions/*"),
+                    UrlPattern::new(host, "/questions/*/**"),
+                ]
+            })
+            .collect()
+    }
+
+    fn extract(&self, _url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        let mut tags = select_all_text(document, "#question .post-tag", MAX_FIELD_LENGTH);
+        tags.dedup();
+
+        let score = select_attr(document, "#question", "data-score")
+            .or_else(|| select_attr(document, "#question .js-vote-count", "data-value"))
+            .and_then(|score| score.parse::<i64>().ok());
+
+        let answer_count = select_attr(document, "#answers-header h2", "data-answercount")
+            .or_else(|| select_text(document, r#"[itemprop="answerCount"]"#, MAX_FIELD_LENGTH))
+            .and_then(|count| parse_count(&count));
+
+        let accepted = Selector::parse(r#"[itemprop="acceptedAnswer"], .accepted-answer"#)
+            .map(|selector| document.select(&selector).next().is_some())
+            .unwrap_or(false);
+
+        MetaDataResponse {
+            title: select_text(document, "#question-header h1", MAX_TITLE_LENGTH),
+            description: select_text(document, "#question .s-prose", MAX_DESCRIPTION_LENGTH),
+            extras: extras([
+                ("score", score.map(Value::from)),
+                ("answer_count", answer_count.map(Value::from)),
+                ("has_accepted_answer", Some(Value::from(accepted))),
+                ("tags", (!tags.is_empty()).then(|| Value::from(tags))),
+                (
+                    "asked_at",
+                    select_attr(document, r#"time[itemprop="dateCreated"]"#, "datetime")
+                        .map(Value::from),
+                ),
+            ]),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use reqwest::Url;
+use scraper::{Html as ScraperHTML, Selector};
+use serde_json::Value;
+
+use crate::preview::{
+    model::MetaDataResponse,
+    pattern::UrlPattern,
+    text::{normalize_text, MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH, MAX_TITLE_LENGTH},
+};
+
+use super::{extras, select_text, Extractor};
+
+/// Articles on any language edition of wikipedia.org.
+pub struct WikipediaExtractor;
+
+impl Extractor for WikipediaExtractor {
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn patterns(&self) -> Vec<UrlPattern> {
+        vec![UrlPattern::new("wikipedia.org", "/wiki/*")]
+    }
+
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        let language = url
+            .host_str()
+            .and_then(|host| host.split('.').next())
+            .filter(|label| !matches!(*label, "www" | "m" | "wikipedia"))
+            .map(String::from);
+
+        MetaDataResponse {
+            title: select_text(document, "#firstHeading", MAX_TITLE_LENGTH),
+            description: lead_paragraph(document),
+            extras: extras([
+                ("language", language.map(Value::from)),
+                (
+                    "last_modified",
+                    select_text(document, "#footer-info-lastmod", MAX_FIELD_LENGTH)
+                        .map(Value::from),
+                ),
+            ]),
+            ..Default::default()
+        }
+    }
+}
+
+/// The first non-empty paragraph of the article body, without citation markers.
+fn lead_paragraph(document: &ScraperHTML) -> Option<String> {
+    let selector = Selector::parse("#mw-content-text .mw-parser-output > p").unwrap();
+
+    document
+        .select(&selector)
+        .filter(|paragraph| {
+            !paragraph
+                .value()
+                .classes()
+                .any(|class| class == "mw-empty-elt")
+        })
+        .find_map(|paragraph| {
+            let text = strip_citations(&paragraph.text().collect::<String>());
+            normalize_text(&text, MAX_DESCRIPTION_LENGTH)
+        })
+}
+
+/// Removes short bracketed markers such as `[1]`, `[a]` and `[citation needed]`.
+fn strip_citations(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        stripped.push_str(&rest[..start]);
+        match rest[start..].find(']') {
+            Some(end) if end <= 24 && !rest[start + 1..start + end].contains('[') => {
+                rest = &rest[start + end + 1..];
+            }
+            _ => {
+                stripped.push('[');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
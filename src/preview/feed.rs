@@ -0,0 +1,229 @@
+use std::cmp::Reverse;
+
+use feed_rs::model::{Entry, Feed as ParsedFeed, Text};
+use reqwest::Url;
+use scraper::{Html as ScraperHTML, Selector};
+
+use super::{
+    model::{Feed, FeedEntry, FeedKind, MetaDataResponse},
+    text::{html_to_text, normalize_text, MAX_DESCRIPTION_LENGTH, MAX_TITLE_LENGTH},
+};
+
+/// Number of entries returned per fetched feed.
+pub const MAX_FEED_ENTRIES: usize = 10;
+
+/// The feed kind a MIME type stands for.
+///
+/// Generic XML and JSON types are not feeds on their own, see [`sniff_feed`].
+pub fn feed_kind(mime: &str) -> Option<FeedKind> {
+    match mime {
+        "application/rss+xml" | "application/rdf+xml" => Some(FeedKind::Rss),
+        "application/atom+xml" => Some(FeedKind::Atom),
+        "application/feed+json" => Some(FeedKind::Json),
+        _ => None,
+    }
+}
+
+/// Recognizes a feed served as generic XML or JSON from its first bytes.
+pub fn sniff_feed(mime: Option<&str>, prefix: &[u8]) -> Option<FeedKind> {
+    let prefix = String::from_utf8_lossy(prefix).to_ascii_lowercase();
+    if prefix.contains("<html") || prefix.contains("<!doctype html") {
+        return None;
+    }
+
+    match mime {
+        None | Some("text/xml" | "application/xml" | "text/plain") => {
+            if prefix.contains("<rss") || prefix.contains("<rdf:rdf") {
+                Some(FeedKind::Rss)
+            } else if prefix.contains("<feed") {
+                Some(FeedKind::Atom)
+            } else {
+                None
+            }
+        }
+        Some("application/json") => prefix
+            .contains("jsonfeed.org/version")
+            .then_some(FeedKind::Json),
+        _ => None,
+    }
+}
+
+/// Finds the feeds a page advertises with `<link rel="alternate">`.
+///
+/// # Arguments
+/// * `document` - The parsed HTML document.
+/// * `url` - The URL of the page, used to resolve relative feed URLs.
+///
+/// # Returns
+/// * `Some(Vec<Feed>)` with the unique feeds, in document order.
+/// * `None` if the page advertises no feed.
+pub fn discover_feeds(document: &ScraperHTML, url: &Url) -> Option<Vec<Feed>> {
+    let link_selector = Selector::parse(r#"link[rel~="alternate" i][type][href]"#).unwrap();
+    let base_selector = Selector::parse("base[href]").unwrap();
+
+    // Relative URLs are resolved against `<base href>` when the page sets one.
+    let base = document
+        .select(&base_selector)
+        .next()
+        .and_then(|base| base.value().attr("href"))
+        .and_then(|href| url.join(href.trim()).ok())
+        .unwrap_or_else(|| url.clone());
+
+    let mut feeds: Vec<Feed> = Vec::new();
+    for link in document.select(&link_selector) {
+        let element = link.value();
+        let mime = element
+            .attr("type")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let kind = match mime.as_str() {
+            "application/json" => Some(FeedKind::Json),
+            mime => feed_kind(mime),
+        };
+        let Some(kind) = kind else {
+            continue;
+        };
+        let Some(feed_url) = element
+            .attr("href")
+            .and_then(|href| base.join(href.trim()).ok())
+            .filter(|feed_url| matches!(feed_url.scheme(), "http" | "https"))
+        else {
+            continue;
+        };
+
+        if feeds.iter().all(|feed| feed.url != feed_url.as_str()) {
+            feeds.push(Feed {
+                url: feed_url.to_string(),
+                kind,
+                title: element
+                    .attr("title")
+                    .and_then(|title| normalize_text(title, MAX_TITLE_LENGTH)),
+                entries: None,
+            });
+        }
+    }
+
+    (!feeds.is_empty()).then_some(feeds)
+}
+
+/// Parses a fetched feed into its title and latest entries.
+///
+/// # Arguments
+/// * `url` - The final URL of the feed, used to resolve entry links.
+/// * `body` - The feed document.
+///
+/// # Returns
+/// * `Some(Feed)` with up to [`MAX_FEED_ENTRIES`] entries, newest first.
+/// * `None` if the body is not a valid feed.
+pub fn parse_feed(url: &str, body: &[u8]) -> Option<Feed> {
+    parse(url, body).map(|(feed, _)| feed)
+}
+
+/// Builds a preview for a URL that points at a feed.
+///
+/// # Arguments
+/// * `kind` - The kind of feed, as detected from the response.
+/// * `url` - The final URL of the feed.
+/// * `body` - The downloaded part of the feed document.
+///
+/// # Returns
+/// * `MetaDataResponse` with the feed's title, description and logo and the
+///   feed itself in `feeds`.
+pub fn extract_feed_metadata(kind: FeedKind, url: &str, body: &[u8]) -> MetaDataResponse {
+    let Some((feed, parsed)) = parse(url, body) else {
+        // A feed cut off by the body budget cannot be parsed, but is still a feed.
+        return MetaDataResponse {
+            feeds: Some(vec![Feed {
+                url: url.to_string(),
+                kind,
+                title: None,
+                entries: None,
+            }]),
+            ..Default::default()
+        };
+    };
+
+    MetaDataResponse {
+        title: feed.title.clone(),
+        description: parsed
+            .description
+            .and_then(|description| html_to_text(&description.content, MAX_DESCRIPTION_LENGTH)),
+        image: parsed
+            .logo
+            .or(parsed.icon)
+            .and_then(|image| Url::parse(url).ok()?.join(&image.uri).ok())
+            .map(String::from),
+        language: parsed.language,
+        feeds: Some(vec![feed]),
+        ..Default::default()
+    }
+}
+
+fn parse(url: &str, body: &[u8]) -> Option<(Feed, ParsedFeed)> {
+    let mut parsed = feed_rs::parser::parse(body)
+        .map_err(|e| eprintln!("Failed to parse feed {}: {}", url, e))
+        .ok()?;
+    let base = Url::parse(url).ok();
+
+    let kind = match parsed.feed_type {
+        feed_rs::model::FeedType::Atom => FeedKind::Atom,
+        feed_rs::model::FeedType::JSON => FeedKind::Json,
+        _ => FeedKind::Rss,
+    };
+
+    let mut entries = std::mem::take(&mut parsed.entries);
+    entries.sort_by_key(|entry| Reverse(entry.published.or(entry.updated)));
+
+    let entries = entries
+        .iter()
+        .take(MAX_FEED_ENTRIES)
+        .map(|entry| feed_entry(entry, base.as_ref()))
+        .collect();
+
+    let feed = Feed {
+        url: url.to_string(),
+        kind,
+        title: parsed
+            .title
+            .as_ref()
+            .and_then(|title| feed_text(title, MAX_TITLE_LENGTH)),
+        entries: Some(entries),
+    };
+
+    Some((feed, parsed))
+}
+
+fn feed_entry(entry: &Entry, base: Option<&Url>) -> FeedEntry {
+    let link = entry
+        .links
+        .iter()
+        .find(|link| link.rel.as_deref().is_none_or(|rel| rel == "alternate"))
+        .or(entry.links.first());
+
+    FeedEntry {
+        title: entry
+            .title
+            .as_ref()
+            .and_then(|title| feed_text(title, MAX_TITLE_LENGTH)),
+        url: link.and_then(|link| match base {
+            Some(base) => base.join(&link.href).ok().map(String::from),
+            None => Some(link.href.clone()),
+        }),
+        published: entry
+            .published
+            .or(entry.updated)
+            .map(|published| published.to_rfc3339()),
+    }
+}
+
+/// Normalizes a feed text construct. Atom marks HTML titles with
+/// `type="html"`; RSS descriptions are HTML by definition and go through
+/// [`html_to_text`] directly.
+fn feed_text(text: &Text, max_length: usize) -> Option<String> {
+    if text.content_type.subty() == "html" {
+        html_to_text(&text.content, max_length)
+    } else {
+        normalize_text(&text.content, max_length)
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use async_trait::async_trait;
+use headless_chrome::browser::default_executable;
+use headless_chrome::{Browser, LaunchOptions};
+use percent_encoding::percent_decode_str;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+    Url,
+};
+use tokio::runtime::Handle;
+
+use crate::{
+    config::constants::Settings,
+    preview::{
+        headless::{
+            authenticate_proxy, dismiss_consent, intercept_requests, set_cookies, track_redirects,
+            wait_for_page, wait_strategy_for, NavigationGuard, OriginHeaders,
+        },
+        media::ContentKind,
+        model::FetchedWith,
+    },
+};
+
+use super::{FetchError, FetchOptions, FetchResponse, FetchTimings, Fetcher};
+
+/// Reads the HTTP status of the main document from the navigation timing entry.
+const RESPONSE_STATUS_SCRIPT: &str =
+    "(performance.getEntriesByType('navigation')[0] || {}).responseStatus || 0";
+
+/// Fetches URLs by rendering them in headless Chrome.
+///
+/// After navigating, the browser waits according to the URL's wait
+/// strategy (from the rules file, or else the configured one), bounded by
+/// `HEADLESS_TIMEOUT_MS`; if the condition is not met in time the page is
+/// read as it is. With `HEADLESS_BLOCK_RESOURCES` enabled, images, fonts,
+/// media and ad/analytics hosts are not loaded.
+///
+/// Cookies from the rules file are set for the requested host only, and
+/// extra headers and credentials are only sent to the requested origin. Every document the
+/// page navigates to is checked with the options' guard, and a refusal is
+/// returned instead of the page. With `HEADLESS_CONSENT`
+/// enabled, consent banners are dismissed before the page is read.
+pub struct BrowserFetcher {
+    settings: Settings,
+}
+
+impl BrowserFetcher {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            settings: settings.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for BrowserFetcher {
+    fn name(&self) -> &'static str {
+        "browser"
+    }
+
+    fn fetched_with(&self) -> FetchedWith {
+        FetchedWith::Browser
+    }
+
+    async fn fetch(&self, url: &str, options: &FetchOptions) -> Result<FetchResponse, FetchError> {
+        let url = url.to_string();
+        let settings = self.settings.clone();
+        let options = options.clone();
+        let runtime = Handle::current();
+
+        // The browser API is blocking and waits can take seconds.
+        tokio::task::spawn_blocking(move || render(&url, &settings, &options, runtime))
+            .await
+            .map_err(|e| FetchError::BrowserError(format!("Browser task failed: {}", e)))?
+    }
+}
+
+/// Launches Chrome, through `proxy` if given.
+///
+/// Chrome takes the proxy without credentials; HTTP proxy credentials are
+/// given when the proxy challenges, and never to sites, see
+/// [`authenticate_proxy`]. SOCKS proxies that need credentials are not
+/// supported by Chrome.
+fn launch(proxy: Option<&str>) -> Result<Browser, FetchError> {
+    let Some(proxy) = proxy else {
+        return Browser::default()
+            .map_err(|e| FetchError::BrowserError(format!("Failed to initialize browser: {}", e)));
+    };
+
+    let mut server =
+        Url::parse(proxy).map_err(|e| FetchError::Proxy(format!("Invalid proxy: {}", e)))?;
+    if server.scheme().starts_with("socks") && !server.username().is_empty() {
+        return Err(FetchError::Proxy(
+            "The headless browser cannot authenticate to SOCKS proxies".to_string(),
+        ));
+    }
+    let _ = server.set_username("");
+    let _ = server.set_password(None);
+    // Chrome resolves names through SOCKS5 proxies anyway and only knows `socks5`.
+    let server = server
+        .to_string()
+        .trim_end_matches('/')
+        .replacen("socks5h://", "socks5://", 1);
+
+    let launch_options = LaunchOptions::default_builder()
+        .path(Some(default_executable().map_err(|e| {
+            FetchError::BrowserError(format!("Failed to find Chrome: {}", e))
+        })?))
+        .proxy_server(Some(&server))
+        .build()
+        .map_err(|e| FetchError::BrowserError(format!("Invalid launch options: {}", e)))?;
+
+    Browser::new(launch_options)
+        .map_err(|e| FetchError::BrowserError(format!("Failed to initialize browser: {}", e)))
+}
+
+/// The username and password of a proxy URL, percent-decoded.
+fn proxy_credentials(proxy: &str) -> Option<(String, String)> {
+    let url = Url::parse(proxy).ok()?;
+    if url.username().is_empty() {
+        return None;
+    }
+
+    let decode = |value: &str| percent_decode_str(value).decode_utf8_lossy().into_owned();
+    Some((
+        decode(url.username()),
+        decode(url.password().unwrap_or_default()),
+    ))
+}
+
+fn render(
+    url: &str,
+    settings: &Settings,
+    options: &FetchOptions,
+    runtime: Handle,
+) -> Result<FetchResponse, FetchError> {
+    let started = Instant::now();
+
+    let browser = launch(options.proxy.as_deref())?;
+
+    let tab = browser
+        .new_tab()
+        .map_err(|e| FetchError::BrowserError(format!("Failed to create new tab: {}", e)))?;
+
+    tab.set_default_timeout(settings.headless_timeout);
+
+    let blocked_hosts = settings
+        .headless_block_resources
+        .then(|| settings.headless_blocked_hosts.clone());
+    let origin_headers = OriginHeaders {
+        origin: Url::parse(url)
+            .map_err(|e| FetchError::BrowserError(format!("Invalid URL {}: {}", url, e)))?,
+        headers: options.extra_headers().cloned().collect(),
+        authorization: options.authorization.clone(),
+    };
+    let refused = Arc::new(Mutex::new(None));
+    let navigation = (!options.guard.is_empty()).then(|| NavigationGuard {
+        guard: options.guard.clone(),
+        runtime,
+        refused: refused.clone(),
+    });
+    let take_refused = || refused.lock().unwrap().take();
+    if navigation.is_some() || blocked_hosts.is_some() || !origin_headers.is_empty() {
+        intercept_requests(&tab, navigation, blocked_hosts, Some(origin_headers))?;
+    }
+
+    if !options.cookies.is_empty() {
+        set_cookies(&tab, url, &options.cookies)?;
+    }
+
+    if let Some((username, password)) = options.proxy.as_deref().and_then(proxy_credentials) {
+        authenticate_proxy(&tab, username, password)?;
+    }
+
+    // Overriding the user agent is the only way to also set
+    // `navigator.language`, which scripts use to localize the page.
+    if let Some(language) = &options.language {
+        let user_agent = browser
+            .get_version()
+            .map_err(|e| FetchError::BrowserError(format!("Failed to read user agent: {}", e)))?
+            .user_agent;
+        tab.set_user_agent(&user_agent, Some(language), None)
+            .map_err(|e| FetchError::BrowserError(format!("Failed to set language: {}", e)))?;
+    }
+
+    let navigations = track_redirects(&tab)?;
+
+    tab.navigate_to(url).map_err(|e| {
+        take_refused().unwrap_or_else(|| {
+            FetchError::from_navigation(format!("Failed to navigate to {}: {}", url, e))
+        })
+    })?;
+
+    tab.wait_for_element("html")
+        .map_err(|e| FetchError::BrowserError(format!("Failed to wait for HTML element: {}", e)))?;
+
+    let strategy = options
+        .wait
+        .as_ref()
+        .unwrap_or_else(|| wait_strategy_for(settings, url));
+    if let Err(e) = wait_for_page(&tab, strategy, settings.headless_timeout) {
+        eprintln!("Reading {} before {:?} was satisfied: {}", url, strategy, e);
+    }
+
+    if settings.headless_consent {
+        match dismiss_consent(
+            &tab,
+            &settings.headless_consent_selectors,
+            settings.headless_timeout,
+        ) {
+            Ok(dismissed) if !dismissed.is_empty() => {
+                println!("Dismissed {} consent on {}", dismissed.join(", "), url)
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Reading {} with its consent banner: {}", url, e),
+        }
+    }
+
+    // A redirect or meta refresh was refused while the page loaded.
+    if let Some(e) = take_refused() {
+        return Err(e);
+    }
+
+    let html = tab
+        .get_content()
+        .map_err(|e| FetchError::BrowserError(format!("Failed to get page content: {}", e)))?;
+
+    let status = tab
+        .evaluate(RESPONSE_STATUS_SCRIPT, false)
+        .ok()
+        .and_then(|result| result.value)
+        .and_then(|value| value.as_u64())
+        .and_then(|status| u16::try_from(status).ok())
+        .filter(|status| *status > 0);
+
+    let redirects = std::mem::take(&mut navigations.lock().unwrap().redirects);
+
+    // `get_content` serializes the DOM, so the body is always UTF-8 HTML.
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+
+    Ok(FetchResponse {
+        url: tab.get_url(),
+        status,
+        headers,
+        kind: ContentKind::Html,
+        mime_type: Some("text/html".to_string()),
+        body: html.into_bytes(),
+        truncated: false,
+        redirects,
+        timings: FetchTimings {
+            first_byte: None,
+            total: started.elapsed(),
+        },
+    })
+}
@@ -0,0 +1,99 @@
+use std::{io::ErrorKind, path::PathBuf, time::Instant};
+
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+
+use crate::preview::{media::classify, model::FetchedWith};
+
+use super::{FetchError, FetchOptions, FetchResponse, FetchTimings, Fetcher};
+
+/// Serves URLs from files on disk, for tests and offline development.
+///
+/// `https://example.com/blog/post?id=1` is read from
+/// `<root>/example.com/blog/post`; paths ending in `/` read `index.html`.
+/// The query string is ignored.
+pub struct FixtureFetcher {
+    root: PathBuf,
+}
+
+impl FixtureFetcher {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path_for(&self, url: &str) -> Result<PathBuf, FetchError> {
+        let url = reqwest::Url::parse(url)
+            .map_err(|e| FetchError::FixtureError(format!("Invalid URL {}: {}", url, e)))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| FetchError::FixtureError(format!("URL has no host: {}", url)))?;
+
+        let mut path = self.root.join(host);
+        for segment in url.path_segments().into_iter().flatten() {
+            if segment == ".." {
+                return Err(FetchError::FixtureError(format!(
+                    "Refusing to leave the fixture directory: {}",
+                    url
+                )));
+            }
+            if !segment.is_empty() {
+                path.push(segment);
+            }
+        }
+
+        if url.path().ends_with('/') {
+            path.push("index.html");
+        }
+
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl Fetcher for FixtureFetcher {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn fetched_with(&self) -> FetchedWith {
+        FetchedWith::Fixture
+    }
+
+    async fn fetch(&self, url: &str, _options: &FetchOptions) -> Result<FetchResponse, FetchError> {
+        let started = Instant::now();
+        let path = self.path_for(url)?;
+
+        let body = tokio::fs::read(&path).await.map_err(|e| match e.kind() {
+            ErrorKind::NotFound => {
+                FetchError::FixtureError(format!("No fixture at {}", path.display()))
+            }
+            _ => FetchError::FixtureError(format!("Failed to read {}: {}", path.display(), e)),
+        })?;
+
+        let mut headers = HeaderMap::new();
+        let is_html = path
+            .extension()
+            .is_some_and(|extension| extension == "html" || extension == "htm");
+        if is_html {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        }
+
+        let content_type = is_html.then_some("text/html");
+        let (kind, mime_type) = classify(content_type, &body);
+
+        Ok(FetchResponse {
+            url: url.to_string(),
+            status: Some(200),
+            headers,
+            kind,
+            mime_type,
+            body,
+            truncated: false,
+            redirects: Vec::new(),
+            timings: FetchTimings {
+                first_byte: None,
+                total: started.elapsed(),
+            },
+        })
+    }
+}
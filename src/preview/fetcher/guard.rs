@@ -0,0 +1,62 @@
+use std::{fmt, sync::Arc};
+
+use tokio::runtime::Handle;
+
+use crate::preview::{reputation::ReputationChecker, robots::RobotsChecker};
+
+use super::FetchError;
+
+/// Checks every URL a fetcher is about to request: the requested URL, each
+/// redirect and meta refresh, and each document the browser navigates to.
+///
+/// Set by the chain on the options it passes to every fetcher but fixtures.
+#[derive(Clone, Default)]
+pub struct RequestGuard {
+    /// See `ROBOTS_COMPLIANCE`.
+    robots: Option<Arc<RobotsChecker>>,
+    /// See `REPUTATION_ACTION`.
+    reputation: Option<Arc<ReputationChecker>>,
+}
+
+impl RequestGuard {
+    pub fn new(
+        robots: Option<Arc<RobotsChecker>>,
+        reputation: Option<Arc<ReputationChecker>>,
+    ) -> Self {
+        Self { robots, reputation }
+    }
+
+    /// Whether there is anything to check.
+    pub fn is_empty(&self) -> bool {
+        self.robots.is_none() && self.reputation.is_none()
+    }
+
+    /// # Errors
+    /// * `FetchError::Unsafe` if `url` is blocklisted and
+    ///   `REPUTATION_ACTION` is `refuse`.
+    /// * `FetchError::RobotsDisallowed` if robots.txt disallows `url`.
+    pub async fn check(&self, url: &str) -> Result<(), FetchError> {
+        if let Some(reputation) = &self.reputation {
+            reputation.check([url])?;
+        }
+        if let Some(robots) = &self.robots {
+            robots.check(url).await?;
+        }
+        Ok(())
+    }
+
+    /// [`Self::check`] for threads outside the runtime, such as the
+    /// browser's event handlers.
+    pub fn check_blocking(&self, runtime: &Handle, url: &str) -> Result<(), FetchError> {
+        runtime.block_on(self.check(url))
+    }
+}
+
+impl fmt::Debug for RequestGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestGuard")
+            .field("robots", &self.robots.is_some())
+            .field("reputation", &self.reputation.is_some())
+            .finish()
+    }
+}
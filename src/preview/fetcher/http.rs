@@ -0,0 +1,486 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_TYPE, COOKIE, LOCATION},
+    redirect::Policy,
+    Client, ClientBuilder, Proxy, RequestBuilder, Response, Url,
+};
+
+use crate::{
+    config::constants::Settings,
+    preview::{
+        media::{classify, ContentKind, SNIFF_BYTES},
+        model::{FetchedWith, Redirect, RedirectKind},
+        redirect::{is_cross_origin, meta_refresh_target, MAX_REDIRECTS},
+    },
+};
+
+use super::{FetchError, FetchOptions, FetchResponse, FetchTimings, Fetcher};
+
+/// Elements whose contents are text, not markup, with their closing tags.
+/// A `</head>` inside a script string or a title does not end the head.
+const RAW_TEXT_ELEMENTS: [(&[u8], &[u8]); 5] = [
+    (b"<script", b"</script"),
+    (b"<style", b"</style"),
+    (b"<title", b"</title"),
+    (b"<textarea", b"</textarea"),
+    (b"<noscript", b"</noscript"),
+];
+/// Bytes needed after a `<` to tell which tag it opens.
+const TAG_LOOKAHEAD: usize = 11;
+
+/// Fetches URLs with a plain HTTP request using reqwest.
+///
+/// The body is streamed until the content kind can be determined from the
+/// `Content-Type` header and magic bytes, and then only as much of it as is
+/// useful for that kind is downloaded. With `HEAD_ONLY_PARSE` enabled, HTML
+/// documents stop streaming at the end of `<head>` or after `MAX_HEAD_BYTES`.
+/// That leaves no body to read, so previews lose the article excerpt, word
+/// count and reading time, and `auto` can no longer tell SPA shells from
+/// server-rendered pages by their body text.
+///
+/// Redirects are followed by hand so that each step can be reported, including
+/// quick `<meta http-equiv="refresh">` redirects.
+pub struct HttpFetcher {
+    client: Client,
+    /// A client per proxy, since reqwest sets proxies per client.
+    proxied_clients: Mutex<HashMap<String, Client>>,
+    user_agent: String,
+    /// See `FETCH_CONNECT_TIMEOUT_MS` and `FETCH_TIMEOUT_MS`.
+    connect_timeout: Duration,
+    timeout: Duration,
+    head_only_parse: bool,
+    max_head_bytes: usize,
+}
+
+impl HttpFetcher {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            client: client_builder(
+                &settings.user_agent,
+                settings.fetch_connect_timeout,
+                settings.fetch_timeout,
+            )
+            .build()
+            .expect("Failed to build HTTP client"),
+            proxied_clients: Mutex::new(HashMap::new()),
+            user_agent: settings.user_agent.clone(),
+            connect_timeout: settings.fetch_connect_timeout,
+            timeout: settings.fetch_timeout,
+            head_only_parse: settings.head_only_parse,
+            max_head_bytes: settings.max_head_bytes,
+        }
+    }
+
+    /// The client for `proxy`, or for direct connections.
+    ///
+    /// # Errors
+    /// Returns `FetchError::Proxy` if the proxy URL is not usable.
+    fn client(&self, proxy: Option<&str>) -> Result<Client, FetchError> {
+        let Some(proxy) = proxy else {
+            return Ok(self.client.clone());
+        };
+
+        let mut clients = self.proxied_clients.lock().unwrap();
+        if let Some(client) = clients.get(proxy) {
+            return Ok(client.clone());
+        }
+
+        let client = Proxy::all(proxy)
+            .and_then(|proxy| {
+                client_builder(&self.user_agent, self.connect_timeout, self.timeout)
+                    .proxy(proxy)
+                    .build()
+            })
+            .map_err(|e| FetchError::Proxy(format!("Invalid proxy: {}", e)))?;
+        clients.insert(proxy.to_string(), client.clone());
+        Ok(client)
+    }
+
+    /// Builds a GET request with the extra headers, cookies, credentials and
+    /// language. Everything but the language is left out once a redirect has
+    /// left the requested origin.
+    fn request(
+        &self,
+        client: &Client,
+        url: &str,
+        options: &FetchOptions,
+        cross_origin: bool,
+    ) -> RequestBuilder {
+        let mut request = client.get(url);
+        if let Some(language) = &options.language {
+            request = request.header(ACCEPT_LANGUAGE, language);
+        }
+        if cross_origin {
+            return request;
+        }
+        request = options
+            .extra_headers()
+            .fold(request, |request, (name, value)| {
+                request.header(name, value)
+            });
+        if let Some(cookies) = options.cookie_header() {
+            request = request.header(COOKIE, cookies);
+        }
+        if let Some(authorization) = &options.authorization {
+            request = request.header(AUTHORIZATION, authorization.expose());
+        }
+        request
+    }
+
+    /// Sends the request and follows HTTP redirects, recording them in
+    /// `redirects`. Each target is checked with the options' guard before it
+    /// is requested.
+    ///
+    /// # Errors
+    /// Returns `FetchError::Redirect` on an invalid `Location` or after
+    /// [`MAX_REDIRECTS`] redirects, or the guard's error for a refused target.
+    async fn send(
+        &self,
+        url: &str,
+        options: &FetchOptions,
+        redirects: &mut Vec<Redirect>,
+    ) -> Result<Response, FetchError> {
+        let client = self.client(options.proxy.as_deref())?;
+        let mut current = url.to_string();
+        let mut cross_origin = false;
+
+        loop {
+            let response = self
+                .request(&client, &current, options, cross_origin)
+                .send()
+                .await?;
+            let status = response.status();
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok());
+
+            let Some(location) = location.filter(|_| status.is_redirection()) else {
+                return Ok(response);
+            };
+
+            let target = response.url().join(location).map_err(|e| {
+                FetchError::Redirect(format!(
+                    "Invalid redirect from {} to {:?}: {}",
+                    response.url(),
+                    location,
+                    e
+                ))
+            })?;
+            if redirects.len() >= MAX_REDIRECTS {
+                return Err(FetchError::Redirect(format!(
+                    "More than {} redirects from {}",
+                    MAX_REDIRECTS, url
+                )));
+            }
+
+            options.guard.check(target.as_str()).await?;
+
+            redirects.push(Redirect {
+                url: response.url().to_string(),
+                status: Some(status.as_u16()),
+                kind: RedirectKind::Http,
+            });
+            cross_origin |= is_cross_origin(response.url(), &target);
+            current = target.to_string();
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for HttpFetcher {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn fetched_with(&self) -> FetchedWith {
+        FetchedWith::Static
+    }
+
+    async fn fetch(&self, url: &str, options: &FetchOptions) -> Result<FetchResponse, FetchError> {
+        let started = Instant::now();
+        let mut redirects = Vec::new();
+        let mut current = url.to_string();
+
+        loop {
+            let mut response = self
+                .fetch_page(&current, options, &mut redirects, started)
+                .await?;
+
+            let refresh = (response.kind == ContentKind::Html && redirects.len() < MAX_REDIRECTS)
+                .then(|| Url::parse(&response.url).ok())
+                .flatten()
+                .and_then(|base| meta_refresh_target(&response.html(), &base));
+
+            match refresh {
+                Some(target) => {
+                    options.guard.check(target.as_str()).await?;
+                    redirects.push(Redirect {
+                        url: response.url,
+                        status: response.status,
+                        kind: RedirectKind::MetaRefresh,
+                    });
+                    current = target.to_string();
+                }
+                None => {
+                    response.redirects = redirects;
+                    return Ok(response);
+                }
+            }
+        }
+    }
+}
+
+impl HttpFetcher {
+    /// Fetches one page, following its HTTP redirects.
+    async fn fetch_page(
+        &self,
+        url: &str,
+        options: &FetchOptions,
+        redirects: &mut Vec<Redirect>,
+        started: Instant,
+    ) -> Result<FetchResponse, FetchError> {
+        let mut response = self.send(url, options, redirects).await?;
+        let first_byte = started.elapsed();
+
+        let final_url = response.url().to_string();
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+
+        let mut body = Vec::new();
+        let mut classified: Option<(ContentKind, Option<String>)> = None;
+        let mut truncated = false;
+        let mut head = HeadScanner::default();
+
+        loop {
+            if let Some((kind, _)) = classified {
+                let head_only = kind == ContentKind::Html && self.head_only_parse;
+
+                let budget = if head_only {
+                    self.max_head_bytes
+                } else {
+                    kind.body_budget()
+                };
+                if body.len() >= budget {
+                    truncated = true;
+                    body.truncate(budget);
+                    break;
+                }
+
+                if head_only {
+                    if let Some(end) = head.scan(&body) {
+                        truncated = true;
+                        body.truncate(end);
+                        break;
+                    }
+                }
+            }
+
+            match response.chunk().await? {
+                Some(chunk) => body.extend_from_slice(&chunk),
+                None => break,
+            }
+
+            if classified.is_none() && body.len() >= SNIFF_BYTES {
+                classified = Some(classify(content_type, &body));
+            }
+        }
+
+        let (kind, mime_type) = classified.unwrap_or_else(|| classify(content_type, &body));
+
+        Ok(FetchResponse {
+            url: final_url,
+            status: Some(status),
+            headers,
+            kind,
+            mime_type,
+            body,
+            truncated,
+            redirects: Vec::new(),
+            timings: FetchTimings {
+                first_byte: Some(first_byte),
+                total: started.elapsed(),
+            },
+        })
+    }
+}
+
+/// The client configuration shared by direct and proxied clients.
+///
+/// `timeout` bounds each request, body included, so an origin that trickles
+/// its response fails with `FetchError::Timeout` and can be retried.
+fn client_builder(user_agent: &str, connect_timeout: Duration, timeout: Duration) -> ClientBuilder {
+    Client::builder()
+        .user_agent(user_agent)
+        .redirect(Policy::none())
+        .connect_timeout(connect_timeout)
+        .timeout(timeout)
+}
+
+/// Whether `bytes` starts with the tag `name`, followed by the end of the
+/// tag name, so `</head` does not match `</header>`.
+fn starts_with_tag(bytes: &[u8], name: &[u8]) -> bool {
+    bytes.len() > name.len()
+        && bytes[..name.len()].eq_ignore_ascii_case(name)
+        && matches!(
+            bytes[name.len()],
+            b'>' | b'/' | b' ' | b'\t' | b'\n' | b'\r' | b'\x0c'
+        )
+}
+
+/// Finds where `<head>` ends while an HTML body is streamed in.
+///
+/// Comments and the contents of scripts, styles and other raw text elements
+/// are skipped, so markers inside them are not mistaken for the end. Each
+/// call resumes where the previous one stopped, and stops early when a tag
+/// or comment is split across chunks.
+#[derive(Debug, Default)]
+struct HeadScanner {
+    position: usize,
+    in_comment: bool,
+    /// The closing tag of the raw text element being skipped.
+    raw_text: Option<&'static [u8]>,
+}
+
+impl HeadScanner {
+    /// # Returns
+    /// * `Some(usize)` with the length of the prefix up to and including the
+    ///   closing `</head>`, or up to an opening `<body` tag.
+    /// * `None` if neither has been seen yet.
+    fn scan(&mut self, body: &[u8]) -> Option<usize> {
+        'scan: while self.position < body.len() {
+            let rest = &body[self.position..];
+
+            if self.in_comment {
+                match rest.windows(3).position(|bytes| bytes == b"-->") {
+                    Some(end) => {
+                        self.position += end + 3;
+                        self.in_comment = false;
+                        continue;
+                    }
+                    None => {
+                        self.position = body.len().saturating_sub(2).max(self.position);
+                        return None;
+                    }
+                }
+            }
+
+            if let Some(close) = self.raw_text {
+                let end = (0..rest.len()).find(|&offset| {
+                    rest[offset] == b'<' && starts_with_tag(&rest[offset..], close)
+                });
+                match end {
+                    Some(end) => {
+                        self.position += end + close.len();
+                        self.raw_text = None;
+                        continue;
+                    }
+                    None => {
+                        self.position = body.len().saturating_sub(close.len()).max(self.position);
+                        return None;
+                    }
+                }
+            }
+
+            let Some(open) = rest.iter().position(|&b| b == b'<') else {
+                self.position = body.len();
+                return None;
+            };
+            self.position += open;
+            let tag = &body[self.position..];
+            if tag.len() < TAG_LOOKAHEAD {
+                return None;
+            }
+
+            if tag.starts_with(b"<!--") {
+                self.position += 4;
+                self.in_comment = true;
+                continue;
+            }
+            if starts_with_tag(tag, b"</head") {
+                let close = tag.iter().position(|&b| b == b'>')?;
+                return Some(self.position + close + 1);
+            }
+            if starts_with_tag(tag, b"<body") {
+                return Some(self.position);
+            }
+            for (open, close) in RAW_TEXT_ELEMENTS {
+                if starts_with_tag(tag, open) {
+                    self.position += open.len();
+                    self.raw_text = Some(close);
+                    continue 'scan;
+                }
+            }
+            self.position += 1;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head_end(html: &str) -> Option<&str> {
+        HeadScanner::default()
+            .scan(html.as_bytes())
+            .map(|end| &html[..end])
+    }
+
+    #[test]
+    fn head_ends_at_closing_head_or_body() {
+        assert_eq!(
+            head_end("<html><head><title>A</title></head><body>text</body>"),
+            Some("<html><head><title>A</title></head>")
+        );
+        assert_eq!(
+            head_end("<html><meta charset=utf-8><body class=x>text</body>"),
+            Some("<html><meta charset=utf-8>")
+        );
+    }
+
+    #[test]
+    fn head_markers_in_scripts_comments_and_titles_are_skipped() {
+        let html = concat!(
+            "<head><script>document.write('</head><body>')</script>",
+            "<!-- </head> --><title>The <body> element</title>",
+            "<meta property=og:title content=A></head><body></body>",
+        );
+        assert_eq!(
+            head_end(html),
+            Some(&html[..html.len() - "<body></body>".len()])
+        );
+    }
+
+    #[test]
+    fn header_is_not_the_end_of_head() {
+        assert_eq!(
+            head_end("<head><header-x></header-x><meta name=a>          "),
+            None
+        );
+    }
+
+    #[test]
+    fn head_end_is_found_across_chunks() {
+        let html = "<head><script>var a = '</head>';</script><meta name=a></head><body>";
+        let mut scanner = HeadScanner::default();
+        let mut found = None;
+        for end in 1..=html.len() {
+            if let Some(head) = scanner.scan(&html.as_bytes()[..end]) {
+                found = Some(head);
+                break;
+            }
+        }
+        assert_eq!(found, Some(html.len() - "<body>".len()));
+    }
+}
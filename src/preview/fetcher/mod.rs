@@ -0,0 +1,640 @@
+pub mod browser;
+pub mod fixture;
+pub mod guard;
+pub mod http;
+pub mod retry;
+
+use std::{fmt, path::PathBuf, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, ACCEPT_LANGUAGE, CONTENT_TYPE};
+use thiserror::Error;
+
+use crate::config::{constants::Settings, headless::WaitStrategy};
+
+use super::{
+    encoding::decode_html,
+    limiter::{retry_after, HostLimiter},
+    media::ContentKind,
+    metrics::FetchMetrics,
+    model::{FetchStrategy, FetchedWith, Redirect},
+    proxy::ProxyRouter,
+    reputation::ReputationChecker,
+    robots::RobotsChecker,
+};
+
+use browser::BrowserFetcher;
+use fixture::FixtureFetcher;
+use guard::RequestGuard;
+use http::HttpFetcher;
+use retry::RetryPolicy;
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("DNS lookup failed: {0}")]
+    Dns(String),
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    #[error("Connection failed: {0}")]
+    Connect(String),
+    #[error("{url} returned {status}")]
+    ClientError { url: String, status: u16 },
+    #[error("{url} returned {status}")]
+    ServerError { url: String, status: u16 },
+    #[error("Proxy error: {0}")]
+    Proxy(String),
+    #[error("Redirect error: {0}")]
+    Redirect(String),
+    #[error("Request error: {0}")]
+    RequestError(reqwest::Error),
+    #[error("Browser error: {0}")]
+    BrowserError(String),
+    #[error("Fixture error: {0}")]
+    FixtureError(String),
+    #[error("robots.txt disallows {url} for {user_agent} ({rule})")]
+    RobotsDisallowed {
+        url: String,
+        user_agent: String,
+        rule: String,
+    },
+    #[error("Refusing to preview {url}: {reason}")]
+    Unsafe { url: String, reason: String },
+    #[error("Rate limit for {host} reached, retry after {retry_after:.0?}")]
+    RateLimited { host: String, retry_after: Duration },
+    #[error("No fetcher is configured for the {0} strategy")]
+    NoFetcher(&'static str),
+    #[allow(dead_code)]
+    #[error("Unknown error")]
+    Unknown,
+}
+
+impl From<reqwest::Error> for FetchError {
+    /// Sorts transport errors by cause. reqwest only says whether an error
+    /// happened while connecting, so DNS and TLS failures are told apart by
+    /// the messages of the underlying errors.
+    fn from(error: reqwest::Error) -> Self {
+        let mut causes = String::new();
+        let mut source = std::error::Error::source(&error);
+        while let Some(cause) = source {
+            let cause_message = cause.to_string();
+            if !causes.contains(&cause_message) {
+                causes = format!("{}: {}", causes, cause_message);
+            }
+            source = cause.source();
+        }
+        let message = format!("{}{}", error, causes);
+
+        if error.is_timeout() {
+            FetchError::Timeout(message)
+        } else if error.is_connect() {
+            // The URL is left out so a host name cannot look like a cause.
+            let lower = causes.to_ascii_lowercase();
+            if [
+                "dns error",
+                "failed to lookup address",
+                "name or service not known",
+            ]
+            .iter()
+            .any(|marker| lower.contains(marker))
+            {
+                FetchError::Dns(message)
+            } else if ["certificate", "tls", "ssl", "handshake"]
+                .iter()
+                .any(|marker| lower.contains(marker))
+            {
+                FetchError::Tls(message)
+            } else {
+                FetchError::Connect(message)
+            }
+        } else {
+            FetchError::RequestError(error)
+        }
+    }
+}
+
+impl FetchError {
+    /// Sorts a failed browser navigation by Chrome's `net::ERR_*` code.
+    pub fn from_navigation(message: String) -> Self {
+        if message.contains("ERR_NAME_NOT_RESOLVED") || message.contains("ERR_NAME_RESOLUTION") {
+            FetchError::Dns(message)
+        } else if message.contains("ERR_CERT_") || message.contains("ERR_SSL_") {
+            FetchError::Tls(message)
+        } else if message.contains("ERR_TIMED_OUT") || message.contains("ERR_CONNECTION_TIMED_OUT")
+        {
+            FetchError::Timeout(message)
+        } else if message.contains("ERR_CONNECTION_") || message.contains("ERR_ADDRESS_") {
+            FetchError::Connect(message)
+        } else {
+            FetchError::BrowserError(message)
+        }
+    }
+
+    /// Turns a 4xx or 5xx status into an error.
+    pub fn from_status(url: &str, status: u16) -> Option<Self> {
+        let url = url.to_string();
+        match status {
+            400..=499 => Some(FetchError::ClientError { url, status }),
+            500..=599 => Some(FetchError::ServerError { url, status }),
+            _ => None,
+        }
+    }
+
+    /// The error's class, reported to API callers as `kind` and in `/metrics`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FetchError::Dns(_) => "dns",
+            FetchError::Tls(_) => "tls",
+            FetchError::Timeout(_) => "timeout",
+            FetchError::Connect(_) => "connect",
+            FetchError::ClientError { .. } => "client_error",
+            FetchError::ServerError { .. } => "server_error",
+            FetchError::Proxy(_) => "proxy",
+            FetchError::Redirect(_) => "redirect",
+            FetchError::RequestError(_) => "request",
+            FetchError::BrowserError(_) => "browser",
+            FetchError::FixtureError(_) => "fixture",
+            FetchError::RobotsDisallowed { .. } => "robots_disallowed",
+            FetchError::Unsafe { .. } => "unsafe",
+            FetchError::RateLimited { .. } => "rate_limited",
+            FetchError::NoFetcher(_) => "no_fetcher",
+            FetchError::Unknown => "unknown",
+        }
+    }
+
+    /// The status the origin answered with, for 4xx and 5xx errors.
+    pub fn upstream_status(&self) -> Option<u16> {
+        match self {
+            FetchError::ClientError { status, .. } | FetchError::ServerError { status, .. } => {
+                Some(*status)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether trying again soon may succeed: timeouts, dropped connections,
+    /// gateway errors and 429s.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            FetchError::Timeout(_) | FetchError::Connect(_) => true,
+            FetchError::ClientError { status, .. } => *status == 429,
+            FetchError::ServerError { status, .. } => matches!(status, 500 | 502 | 503 | 504),
+            _ => false,
+        }
+    }
+}
+
+/// How long the parts of a fetch took.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchTimings {
+    /// Time until the response headers arrived, when the fetcher can tell.
+    pub first_byte: Option<Duration>,
+    pub total: Duration,
+}
+
+/// A fetched resource.
+#[derive(Debug)]
+pub struct FetchResponse {
+    /// The URL after following redirects.
+    pub url: String,
+    /// The HTTP status, when the fetcher can tell.
+    pub status: Option<u16>,
+    pub headers: HeaderMap,
+    pub kind: ContentKind,
+    /// The effective MIME type, from magic bytes or `Content-Type`.
+    pub mime_type: Option<String>,
+    /// The downloaded part of the body, see [`ContentKind::body_budget`].
+    pub body: Vec<u8>,
+    /// Whether `body` stops short of the full resource.
+    pub truncated: bool,
+    /// The redirects followed to reach `url`.
+    pub redirects: Vec<Redirect>,
+    pub timings: FetchTimings,
+}
+
+impl FetchResponse {
+    fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// Decodes the body as an HTML document, see [`decode_html`].
+    pub fn html(&self) -> String {
+        decode_html(&self.body, self.content_type())
+    }
+}
+
+/// A credential, kept out of `Debug` output and logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+/// Per-request settings passed to every fetcher, see `RULES_FILE`.
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// Extra request headers sent to the requested origin only.
+    pub headers: Vec<(String, String)>,
+    /// Cookies sent to the requested origin only.
+    pub cookies: Vec<(String, Secret)>,
+    /// The `Authorization` header sent to the requested origin only.
+    pub authorization: Option<Secret>,
+    /// The request carries credentials, so its preview must not be cached.
+    pub private: bool,
+    /// Replaces the browser's configured wait strategy.
+    pub wait: Option<WaitStrategy>,
+    /// Sent as `Accept-Language`, replacing any such header in `headers`.
+    pub language: Option<String>,
+    /// The proxy to connect through, picked by the chain, see `PROXIES`.
+    pub proxy: Option<String>,
+    /// Checks each URL before it is requested, set by the chain.
+    pub guard: RequestGuard,
+}
+
+impl FetchOptions {
+    /// The `Cookie` header for `cookies`, if any.
+    pub fn cookie_header(&self) -> Option<String> {
+        (!self.cookies.is_empty()).then(|| {
+            self.cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value.expose()))
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+    }
+
+    /// `headers` without the ones `language` replaces.
+    pub fn extra_headers(&self) -> impl Iterator<Item = &(String, String)> {
+        self.headers.iter().filter(|(name, _)| {
+            self.language.is_none() || !name.eq_ignore_ascii_case(ACCEPT_LANGUAGE.as_str())
+        })
+    }
+}
+
+#[async_trait]
+pub trait Fetcher: Send + Sync {
+    /// The name used to configure the fetcher in `FETCHERS`.
+    fn name(&self) -> &'static str;
+    /// What the fetcher reports in `fetched_with`.
+    fn fetched_with(&self) -> FetchedWith;
+    async fn fetch(&self, url: &str, options: &FetchOptions) -> Result<FetchResponse, FetchError>;
+}
+
+/// An ordered list of fetchers, tried in turn by `fetch_metadata`.
+pub struct FetcherChain {
+    fetchers: Vec<Box<dyn Fetcher>>,
+    /// Treat `Auto` as `Browser`, see `ONLY_USE_HEADLESS_BROWSER`.
+    browser_only: bool,
+    /// Checks robots.txt and robots directives, see `ROBOTS_COMPLIANCE`.
+    robots: Option<Arc<RobotsChecker>>,
+    /// Refuses blocklisted URLs, see `REPUTATION_ACTION`.
+    reputation: Option<Arc<ReputationChecker>>,
+    /// Per-host politeness limits, see `RATE_LIMIT_*`.
+    limiter: Option<HostLimiter>,
+    /// Outbound proxies, see `PROXIES`.
+    proxies: Option<Arc<ProxyRouter>>,
+    /// How transient errors are retried, see `RETRY_*`.
+    retry: RetryPolicy,
+    metrics: FetchMetrics,
+}
+
+impl FetcherChain {
+    pub fn new(fetchers: Vec<Box<dyn Fetcher>>) -> Self {
+        Self {
+            fetchers,
+            browser_only: false,
+            robots: None,
+            reputation: None,
+            limiter: None,
+            proxies: None,
+            retry: RetryPolicy::none(),
+            metrics: FetchMetrics::default(),
+        }
+    }
+
+    pub fn with_browser_only(mut self, browser_only: bool) -> Self {
+        self.browser_only = browser_only;
+        self
+    }
+
+    pub fn with_robots(mut self, robots: Option<Arc<RobotsChecker>>) -> Self {
+        self.robots = robots;
+        self
+    }
+
+    pub fn with_reputation(mut self, reputation: Option<Arc<ReputationChecker>>) -> Self {
+        self.reputation = reputation;
+        self
+    }
+
+    pub fn with_limiter(mut self, limiter: Option<HostLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    pub fn with_proxies(mut self, proxies: Option<Arc<ProxyRouter>>) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn robots(&self) -> Option<&RobotsChecker> {
+        self.robots.as_deref()
+    }
+
+    /// Builds the chain named in `FETCHERS`, e.g. `http,browser`.
+    ///
+    /// Unknown names are reported and skipped.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let fetchers = settings
+            .fetchers
+            .iter()
+            .filter_map(|name| -> Option<Box<dyn Fetcher>> {
+                match name.as_str() {
+                    "http" => Some(Box::new(HttpFetcher::new(settings))),
+                    "browser" => Some(Box::new(BrowserFetcher::new(settings))),
+                    "fixture" => Some(Box::new(FixtureFetcher::new(PathBuf::from(
+                        &settings.fixture_dir,
+                    )))),
+                    other => {
+                        eprintln!("Ignoring unknown fetcher: {}", other);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let proxies = Some(ProxyRouter::from_settings(settings))
+            .filter(|router| !router.is_empty())
+            .map(Arc::new);
+
+        Self::new(fetchers)
+            .with_browser_only(settings.use_headless_browser_only)
+            .with_robots(settings.robots_compliance.then(|| {
+                Arc::new(RobotsChecker::new(&settings.user_agent).with_proxies(proxies.clone()))
+            }))
+            .with_limiter(Some(HostLimiter::from_settings(settings)))
+            .with_retry(RetryPolicy::from_settings(settings))
+            .with_proxies(proxies)
+    }
+
+    /// Applies `ONLY_USE_HEADLESS_BROWSER` to the requested strategy.
+    pub fn resolve(&self, strategy: FetchStrategy) -> FetchStrategy {
+        match strategy {
+            FetchStrategy::Auto if self.browser_only => FetchStrategy::Browser,
+            strategy => strategy,
+        }
+    }
+
+    /// The fetchers a strategy may use, in chain order.
+    pub fn for_strategy(&self, strategy: FetchStrategy) -> Vec<&dyn Fetcher> {
+        self.fetchers
+            .iter()
+            .map(|fetcher| fetcher.as_ref())
+            .filter(|fetcher| match strategy {
+                FetchStrategy::Static => fetcher.fetched_with() != FetchedWith::Browser,
+                FetchStrategy::Browser => fetcher.fetched_with() == FetchedWith::Browser,
+                FetchStrategy::Auto => true,
+            })
+            .collect()
+    }
+
+    /// Counters of fetch outcomes and retries, see `/metrics`.
+    pub fn metrics(&self) -> &FetchMetrics {
+        &self.metrics
+    }
+
+    /// Fetches `url` with one of the chain's fetchers, within the host's rate
+    /// limit, retrying transient errors with jittered exponential backoff.
+    /// The URL, and every redirect the fetcher follows, is checked with a
+    /// [`RequestGuard`] first. Fixtures are never limited or checked.
+    ///
+    /// # Errors
+    /// * `FetchError::RobotsDisallowed` if robots.txt disallows the URL or a
+    ///   redirect target.
+    /// * `FetchError::Unsafe` if the URL or a redirect target is blocklisted
+    ///   and `REPUTATION_ACTION` is `refuse`.
+    /// * `FetchError::ClientError` or `FetchError::ServerError` if the origin
+    ///   answers with a 4xx or 5xx status.
+    /// * `FetchError::RateLimited` if the host's limit is not freed in time,
+    ///   or if the origin answers with 429 or `Retry-After`, in which case the
+    ///   host is backed off for that long.
+    pub async fn fetch(
+        &self,
+        fetcher: &dyn Fetcher,
+        url: &str,
+        options: &FetchOptions,
+    ) -> Result<FetchResponse, FetchError> {
+        let mut attempt = 0;
+        loop {
+            let result = self.fetch_once(fetcher, url, options).await;
+            self.metrics.record_fetch(fetcher.name(), &result);
+
+            match result {
+                Err(e) if e.is_transient() && attempt < self.retry.attempts => {
+                    let delay = self.retry.delay(attempt);
+                    eprintln!(
+                        "Retrying {} with {} in {:.0?} after {}",
+                        url,
+                        fetcher.name(),
+                        delay,
+                        e
+                    );
+                    self.metrics.record_retry(&e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Fetches through the proxy routed for `url`, if any, and records
+    /// whether the proxy worked.
+    async fn fetch_routed(
+        &self,
+        fetcher: &dyn Fetcher,
+        url: &str,
+        options: &FetchOptions,
+    ) -> Result<FetchResponse, FetchError> {
+        let routed = match &self.proxies {
+            Some(router) if fetcher.fetched_with() != FetchedWith::Fixture => {
+                router.pick(url).map(|proxy| (router, proxy))
+            }
+            _ => None,
+        };
+        let Some((router, proxy)) = routed else {
+            return fetcher.fetch(url, options).await;
+        };
+
+        let options = FetchOptions {
+            proxy: Some(proxy.clone()),
+            ..options.clone()
+        };
+        let result = fetcher.fetch(url, &options).await;
+
+        let status_error = result
+            .as_ref()
+            .ok()
+            .and_then(|response| response.status)
+            .and_then(|status| FetchError::from_status(url, status));
+        let outcome = match (&result, &status_error) {
+            (Err(e), _) | (Ok(_), Some(e)) => Err(e),
+            (Ok(_), None) => Ok(()),
+        };
+        router.report(url, &proxy, outcome);
+
+        result
+    }
+
+    async fn fetch_once(
+        &self,
+        fetcher: &dyn Fetcher,
+        url: &str,
+        options: &FetchOptions,
+    ) -> Result<FetchResponse, FetchError> {
+        // Fixtures are read from disk, not requested.
+        let guard = if fetcher.fetched_with() == FetchedWith::Fixture {
+            RequestGuard::default()
+        } else {
+            RequestGuard::new(self.robots.clone(), self.reputation.clone())
+        };
+        let options = &FetchOptions {
+            guard,
+            ..options.clone()
+        };
+        options.guard.check(url).await?;
+
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from));
+        let limited = match (&self.limiter, host) {
+            (Some(limiter), Some(host)) if fetcher.fetched_with() != FetchedWith::Fixture => {
+                Some((limiter, host))
+            }
+            _ => None,
+        };
+
+        let response = match &limited {
+            Some((limiter, host)) => {
+                let permit = limiter.acquire(host).await?;
+                let response = self.fetch_routed(fetcher, url, options).await;
+                drop(permit);
+                response?
+            }
+            None => self.fetch_routed(fetcher, url, options).await?,
+        };
+
+        if let (Some((limiter, host)), Some(duration)) =
+            (&limited, retry_after(response.status, &response.headers))
+        {
+            limiter.back_off(host, duration).await;
+            return Err(FetchError::RateLimited {
+                host: host.clone(),
+                retry_after: duration,
+            });
+        }
+
+        match response
+            .status
+            .and_then(|status| FetchError::from_status(&response.url, status))
+        {
+            Some(error) => Err(error),
+            None => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_error(status: u16) -> FetchError {
+        FetchError::from_status("https://example.com/", status).unwrap()
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        for transient in [
+            FetchError::Timeout("slow".to_string()),
+            FetchError::Connect("refused".to_string()),
+            status_error(429),
+            status_error(500),
+            status_error(502),
+            status_error(503),
+            status_error(504),
+        ] {
+            assert!(transient.is_transient(), "{}", transient);
+        }
+
+        for permanent in [
+            FetchError::Dns("no such host".to_string()),
+            FetchError::Tls("bad certificate".to_string()),
+            FetchError::Proxy("refused".to_string()),
+            FetchError::Redirect("loop".to_string()),
+            status_error(400),
+            status_error(403),
+            status_error(404),
+            status_error(501),
+            FetchError::RobotsDisallowed {
+                url: "https://example.com/".to_string(),
+                user_agent: "rushy-preview".to_string(),
+                rule: "Disallow: /".to_string(),
+            },
+            FetchError::Unsafe {
+                url: "https://example.com/".to_string(),
+                reason: "listed".to_string(),
+            },
+            FetchError::RateLimited {
+                host: "example.com".to_string(),
+                retry_after: Duration::from_secs(1),
+            },
+            FetchError::NoFetcher("auto"),
+        ] {
+            assert!(!permanent.is_transient(), "{}", permanent);
+        }
+    }
+
+    #[test]
+    fn statuses_map_to_kinds() {
+        assert!(FetchError::from_status("https://example.com/", 302).is_none());
+        assert_eq!(status_error(404).kind(), "client_error");
+        assert_eq!(status_error(404).upstream_status(), Some(404));
+        assert_eq!(status_error(503).kind(), "server_error");
+        assert_eq!(status_error(503).upstream_status(), Some(503));
+        assert_eq!(FetchError::Timeout(String::new()).upstream_status(), None);
+    }
+
+    #[test]
+    fn navigation_errors_map_to_kinds() {
+        let kind = |code: &str| FetchError::from_navigation(format!("net::{}", code)).kind();
+
+        assert_eq!(kind("ERR_NAME_NOT_RESOLVED"), "dns");
+        assert_eq!(kind("ERR_CERT_AUTHORITY_INVALID"), "tls");
+        assert_eq!(kind("ERR_SSL_PROTOCOL_ERROR"), "tls");
+        assert_eq!(kind("ERR_TIMED_OUT"), "timeout");
+        assert_eq!(kind("ERR_CONNECTION_TIMED_OUT"), "timeout");
+        assert_eq!(kind("ERR_CONNECTION_REFUSED"), "connect");
+        assert_eq!(kind("ERR_ABORTED"), "browser");
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::config::constants::Settings;
+
+/// How often and how patiently transient fetch errors are retried, see
+/// [`super::FetchError::is_transient`].
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Never retries.
+    pub fn none() -> Self {
+        Self {
+            attempts: 0,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            attempts: settings.retry_attempts,
+            base_delay: settings.retry_base_delay,
+            max_delay: settings.retry_max_delay,
+        }
+    }
+
+    /// The delay before retry number `attempt` (from 0): a random duration
+    /// up to `base_delay * 2^attempt`, capped at `max_delay` ("full jitter"),
+    /// so clients that failed together do not retry together.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        if ceiling.is_zero() {
+            return ceiling;
+        }
+
+        rand::thread_rng().gen_range(Duration::ZERO..=ceiling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(base_delay: Duration, max_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            base_delay,
+            max_delay,
+        }
+    }
+
+    #[test]
+    fn delay_is_within_the_exponential_ceiling() {
+        let policy = policy(Duration::from_millis(100), Duration::from_secs(10));
+
+        for attempt in 0..5 {
+            let ceiling = Duration::from_millis(100 * 2u64.pow(attempt));
+            for _ in 0..100 {
+                assert!(policy.delay(attempt) <= ceiling);
+            }
+        }
+    }
+
+    #[test]
+    fn delay_is_capped_at_max_delay() {
+        let policy = policy(Duration::from_millis(100), Duration::from_millis(300));
+
+        for attempt in [2, 3, 10, 40, u32::MAX] {
+            for _ in 0..100 {
+                assert!(policy.delay(attempt) <= Duration::from_millis(300));
+            }
+        }
+    }
+
+    #[test]
+    fn delay_is_jittered() {
+        let policy = policy(Duration::from_secs(1), Duration::from_secs(1));
+        let delays = (0..50)
+            .map(|_| policy.delay(0))
+            .collect::<std::collections::HashSet<_>>();
+
+        assert!(delays.len() > 1);
+    }
+
+    #[test]
+    fn no_policy_never_waits() {
+        assert_eq!(RetryPolicy::none().delay(3), Duration::ZERO);
+    }
+}
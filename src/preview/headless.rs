@@ -0,0 +1,494 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use headless_chrome::{
+    browser::{
+        tab::RequestPausedDecision,
+        transport::{SessionId, Transport},
+    },
+    protocol::cdp::{
+        types::Event,
+        Fetch::{
+            events::RequestPausedEvent, AuthChallengeResponse, AuthChallengeResponseResponse,
+            AuthChallengeSource, ContinueRequest, ContinueWithAuth, FailRequest, HeaderEntry,
+        },
+        Network::{self, CookieParam, ErrorReason, InitiatorType, ResourceType},
+    },
+    Tab,
+};
+use reqwest::{header::AUTHORIZATION, Url};
+use serde_json::json;
+use tokio::runtime::Handle;
+
+use crate::config::{constants::Settings, headless::WaitStrategy};
+
+use super::{
+    consent::consent_script,
+    fetcher::{guard::RequestGuard, FetchError, Secret},
+    model::{Redirect, RedirectKind},
+    pattern::host_matches,
+    redirect::is_cross_origin,
+};
+
+/// How often page state is polled while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long the page must stay unchanged to count as network idle.
+const NETWORK_IDLE_DURATION: Duration = Duration::from_millis(500);
+
+/// How many consent buttons are clicked at most.
+const CONSENT_ROUNDS: usize = 2;
+/// How long to let a consent click take effect before waiting for idle.
+const CONSENT_SETTLE_DELAY: Duration = Duration::from_millis(250);
+
+/// Reports the ready state and the number of resources requested so far.
+const NETWORK_STATE_SCRIPT: &str =
+    "document.readyState + ':' + performance.getEntriesByType('resource').length";
+
+/// Picks the wait strategy for a URL: the first matching per-domain rule,
+/// otherwise the global default.
+pub fn wait_strategy_for<'a>(settings: &'a Settings, url: &str) -> &'a WaitStrategy {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+
+    host.and_then(|host| {
+        settings
+            .headless_wait_domains
+            .iter()
+            .find(|(domain, _)| host_matches(&host, domain))
+            .map(|(_, strategy)| strategy)
+    })
+    .unwrap_or(&settings.headless_wait)
+}
+
+/// Blocks until the page satisfies `strategy` or `timeout` elapses.
+///
+/// # Errors
+/// Returns `FetchError::BrowserError` if the condition is not met in time.
+pub fn wait_for_page(
+    tab: &Tab,
+    strategy: &WaitStrategy,
+    timeout: Duration,
+) -> Result<(), FetchError> {
+    match strategy {
+        WaitStrategy::Load => tab
+            .wait_until_navigated()
+            .map(|_| ())
+            .map_err(|e| FetchError::BrowserError(format!("Failed to wait for page load: {}", e))),
+        WaitStrategy::NetworkIdle => wait_for_network_idle(tab, timeout),
+        WaitStrategy::Selector(selector) => tab
+            .wait_for_element_with_custom_timeout(selector, timeout)
+            .map(|_| ())
+            .map_err(|e| {
+                FetchError::BrowserError(format!("Failed to wait for {}: {}", selector, e))
+            }),
+        WaitStrategy::MetaTag(property) => {
+            let selector = format!(
+                r#"meta[property="{0}"][content]:not([content=""]), meta[name="{0}"][content]:not([content=""])"#,
+                property
+            );
+            tab.wait_for_element_with_custom_timeout(&selector, timeout)
+                .map(|_| ())
+                .map_err(|e| {
+                    FetchError::BrowserError(format!("Failed to wait for meta {}: {}", property, e))
+                })
+        }
+        WaitStrategy::Delay(delay) => {
+            sleep((*delay).min(timeout));
+            Ok(())
+        }
+    }
+}
+
+fn wait_for_network_idle(tab: &Tab, timeout: Duration) -> Result<(), FetchError> {
+    let deadline = Instant::now() + timeout;
+    let mut last_state = None;
+    let mut unchanged_since = Instant::now();
+
+    while Instant::now() < deadline {
+        let state = tab
+            .evaluate(NETWORK_STATE_SCRIPT, false)
+            .map_err(|e| FetchError::BrowserError(format!("Failed to read page state: {}", e)))?
+            .value
+            .and_then(|value| value.as_str().map(String::from));
+
+        if state != last_state {
+            last_state = state;
+            unchanged_since = Instant::now();
+        } else if last_state
+            .as_deref()
+            .is_some_and(|state| state.starts_with("complete:"))
+            && unchanged_since.elapsed() >= NETWORK_IDLE_DURATION
+        {
+            return Ok(());
+        }
+
+        sleep(POLL_INTERVAL);
+    }
+
+    Err(FetchError::BrowserError(
+        "Timed out waiting for network idle".to_string(),
+    ))
+}
+
+/// Dismisses consent banners and interstitials, then waits for the page to
+/// settle again so it is read without them.
+///
+/// A second round handles banners that open a settings dialog first, and
+/// interstitials that lead to a page with a banner of its own.
+///
+/// # Arguments
+/// * `tab` - The tab showing the page.
+/// * `selectors` - Extra buttons to click, see [`consent_script`].
+/// * `timeout` - How long to wait for the page after each click.
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with the frameworks whose banner was dismissed.
+///
+/// # Errors
+/// Returns `FetchError::BrowserError` if the script cannot be run.
+pub fn dismiss_consent(
+    tab: &Tab,
+    selectors: &[String],
+    timeout: Duration,
+) -> Result<Vec<String>, FetchError> {
+    let script = consent_script(selectors);
+    let mut dismissed = Vec::new();
+
+    for _ in 0..CONSENT_ROUNDS {
+        let framework = tab
+            .evaluate(&script, false)
+            .map_err(|e| FetchError::BrowserError(format!("Failed to dismiss consent: {}", e)))?
+            .value
+            .and_then(|value| value.as_str().map(String::from));
+        let Some(framework) = framework else {
+            break;
+        };
+
+        dismissed.push(framework);
+        sleep(CONSENT_SETTLE_DELAY);
+        wait_for_network_idle(tab, timeout)?;
+    }
+
+    Ok(dismissed)
+}
+
+/// Checks the documents the main frame navigates to, see [`intercept_requests`].
+pub struct NavigationGuard {
+    pub guard: RequestGuard,
+    /// The runtime the guard's checks run on.
+    pub runtime: Handle,
+    /// The first refusal, to be reported instead of the page.
+    pub refused: Arc<Mutex<Option<FetchError>>>,
+}
+
+/// Headers added to the requests to one origin, see [`intercept_requests`].
+pub struct OriginHeaders {
+    pub origin: Url,
+    /// Extra headers from the rules file.
+    pub headers: Vec<(String, String)>,
+    /// Sent as the `Authorization` header.
+    pub authorization: Option<Secret>,
+}
+
+impl OriginHeaders {
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.authorization.is_none()
+    }
+
+    fn entries(&self) -> impl Iterator<Item = HeaderEntry> + '_ {
+        let authorization = self.authorization.iter().map(|authorization| HeaderEntry {
+            name: AUTHORIZATION.to_string(),
+            value: authorization.expose().to_string(),
+        });
+        self.headers
+            .iter()
+            .map(|(name, value)| HeaderEntry {
+                name: name.clone(),
+                value: value.clone(),
+            })
+            .chain(authorization)
+    }
+}
+
+/// Intercepts every request of the tab to check navigations, block resources
+/// and add headers and credentials.
+///
+/// # Arguments
+/// * `tab` - The tab to intercept requests in.
+/// * `navigation` - If set, every document requested by the main frame,
+///   including redirects and meta refreshes, is checked with the guard and
+///   failed if refused.
+/// * `blocked_hosts` - If set, image, font and media requests are failed, as
+///   are requests to these hosts (and their subdomains).
+/// * `origin_headers` - If set, the headers are added to requests to the
+///   origin, replacing any of the same name, and to no other origin.
+///
+/// # Errors
+/// Returns `FetchError::BrowserError` if request interception cannot be enabled.
+pub fn intercept_requests(
+    tab: &Tab,
+    navigation: Option<NavigationGuard>,
+    blocked_hosts: Option<Vec<String>>,
+    origin_headers: Option<OriginHeaders>,
+) -> Result<(), FetchError> {
+    let origin_headers = origin_headers.filter(|headers| !headers.is_empty());
+
+    tab.enable_fetch(None, None).map_err(|e| {
+        FetchError::BrowserError(format!("Failed to enable request interception: {}", e))
+    })?;
+    // Chrome gives the main frame the id of its target.
+    let main_frame = tab.get_target_id().clone();
+
+    tab.enable_request_interception(Arc::new(
+        move |_transport: Arc<Transport>, _session_id: SessionId, event: RequestPausedEvent| {
+            let request_url = Url::parse(&event.params.request.url).ok();
+
+            if let Some(navigation) = &navigation {
+                let is_navigation = event.params.resource_Type == ResourceType::Document
+                    && event.params.frame_id == main_frame;
+                let refused = is_navigation
+                    .then(|| {
+                        navigation
+                            .guard
+                            .check_blocking(&navigation.runtime, &event.params.request.url)
+                            .err()
+                    })
+                    .flatten();
+                if let Some(e) = refused {
+                    navigation.refused.lock().unwrap().get_or_insert(e);
+                    return RequestPausedDecision::Fail(FailRequest {
+                        request_id: event.params.request_id,
+                        error_reason: ErrorReason::BlockedByClient,
+                    });
+                }
+            }
+
+            if let Some(blocked_hosts) = &blocked_hosts {
+                let blocked_type = matches!(
+                    event.params.resource_Type,
+                    ResourceType::Image | ResourceType::Font | ResourceType::Media
+                );
+                let blocked_host = request_url
+                    .as_ref()
+                    .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+                    .is_some_and(|host| {
+                        blocked_hosts
+                            .iter()
+                            .any(|domain| host_matches(&host, domain))
+                    });
+
+                if blocked_type || blocked_host {
+                    return RequestPausedDecision::Fail(FailRequest {
+                        request_id: event.params.request_id,
+                        error_reason: ErrorReason::BlockedByClient,
+                    });
+                }
+            }
+
+            let origin_headers = origin_headers.as_ref().filter(|headers| {
+                request_url
+                    .as_ref()
+                    .is_some_and(|url| !is_cross_origin(&headers.origin, url))
+            });
+            let Some(origin_headers) = origin_headers else {
+                return RequestPausedDecision::Continue(None);
+            };
+
+            let added = origin_headers.entries().collect::<Vec<_>>();
+            let mut headers = event
+                .params
+                .request
+                .headers
+                .0
+                .as_ref()
+                .and_then(|headers| headers.as_object())
+                .into_iter()
+                .flatten()
+                .filter(|(name, _)| {
+                    !added
+                        .iter()
+                        .any(|header| header.name.eq_ignore_ascii_case(name))
+                })
+                .filter_map(|(name, value)| {
+                    Some(HeaderEntry {
+                        name: name.clone(),
+                        value: value.as_str()?.to_string(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            headers.extend(added);
+
+            RequestPausedDecision::Continue(Some(ContinueRequest {
+                request_id: event.params.request_id,
+                url: None,
+                method: None,
+                post_data: None,
+                headers: Some(headers),
+                intercept_response: None,
+            }))
+        },
+    ))
+    .map_err(|e| FetchError::BrowserError(format!("Failed to intercept requests: {}", e)))
+}
+
+/// Answers proxy authentication challenges with the proxy's credentials and
+/// cancels every other challenge, so a site asking for credentials never
+/// receives the proxy's.
+///
+/// headless_chrome's own `authenticate` answers every challenge, from the
+/// proxy or from a site, with the same credentials. Its handler still answers
+/// after ours, but the challenge is settled by then.
+///
+/// Call it after [`intercept_requests`], which enables request interception
+/// without auth challenges.
+///
+/// # Errors
+/// Returns `FetchError::Proxy` if auth challenges cannot be handled.
+pub fn authenticate_proxy(
+    tab: &Arc<Tab>,
+    username: String,
+    password: String,
+) -> Result<(), FetchError> {
+    tab.enable_fetch(None, Some(true))
+        .map_err(|e| FetchError::Proxy(format!("Failed to handle proxy authentication: {}", e)))?;
+
+    // A strong reference would keep the tab alive through its own listener.
+    let weak_tab = Arc::downgrade(tab);
+    tab.add_event_listener(Arc::new(move |event: &Event| {
+        let Event::FetchAuthRequired(event) = event else {
+            return;
+        };
+        let Some(tab) = weak_tab.upgrade() else {
+            return;
+        };
+
+        let challenge = &event.params.auth_challenge;
+        let response = if challenge.source == Some(AuthChallengeSource::Proxy) {
+            AuthChallengeResponse {
+                response: AuthChallengeResponseResponse::ProvideCredentials,
+                username: Some(username.clone()),
+                password: Some(password.clone()),
+            }
+        } else {
+            eprintln!(
+                "Refusing to send proxy credentials to {} asking for authentication",
+                challenge.origin
+            );
+            AuthChallengeResponse {
+                response: AuthChallengeResponseResponse::CancelAuth,
+                username: None,
+                password: None,
+            }
+        };
+
+        if let Err(e) = tab.call_method(ContinueWithAuth {
+            request_id: event.params.request_id.clone(),
+            auth_challenge_response: response,
+        }) {
+            eprintln!("Failed to answer authentication challenge: {}", e);
+        }
+    }))
+    .map(|_| ())
+    .map_err(|e| FetchError::Proxy(format!("Failed to handle proxy authentication: {}", e)))
+}
+
+/// Sets cookies for the host of `url` before navigating to it.
+///
+/// # Errors
+/// Returns `FetchError::BrowserError` if the cookies cannot be set.
+pub fn set_cookies(tab: &Tab, url: &str, cookies: &[(String, Secret)]) -> Result<(), FetchError> {
+    let cookies = cookies
+        .iter()
+        .map(|(name, value)| {
+            serde_json::from_value::<CookieParam>(json!({
+                "name": name,
+                "value": value.expose(),
+                "url": url,
+            }))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| FetchError::BrowserError(format!("Invalid cookie: {}", e)))?;
+
+    tab.set_cookies(cookies)
+        .map_err(|e| FetchError::BrowserError(format!("Failed to set cookies: {}", e)))
+}
+
+/// Document navigations of the main frame, collected by [`track_redirects`].
+#[derive(Debug, Default)]
+pub struct Navigations {
+    main_frame: Option<String>,
+    /// The document currently loading or loaded, and its status once known.
+    current: Option<(String, Option<u16>)>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// Records every redirect of the main frame: HTTP redirects, and navigations
+/// started by a meta refresh or by a script once a document has loaded.
+///
+/// # Errors
+/// Returns `FetchError::BrowserError` if network events cannot be enabled.
+pub fn track_redirects(tab: &Tab) -> Result<Arc<Mutex<Navigations>>, FetchError> {
+    tab.call_method(Network::Enable {
+        max_total_buffer_size: None,
+        max_resource_buffer_size: None,
+        max_post_data_size: None,
+    })
+    .map_err(|e| FetchError::BrowserError(format!("Failed to enable network events: {}", e)))?;
+
+    let navigations = Arc::new(Mutex::new(Navigations::default()));
+    let log = navigations.clone();
+
+    tab.add_event_listener(Arc::new(move |event: &Event| {
+        let mut log = log.lock().unwrap();
+        match event {
+            Event::NetworkRequestWillBeSent(event)
+                if event.params.Type == Some(ResourceType::Document) =>
+            {
+                let params = &event.params;
+                let main_frame = log
+                    .main_frame
+                    .get_or_insert_with(|| params.frame_id.clone().unwrap_or_default())
+                    .clone();
+                if params.frame_id.as_deref().unwrap_or_default() != main_frame {
+                    return;
+                }
+
+                let redirect = match (&params.redirect_response, log.current.take()) {
+                    (Some(response), _) => Some(Redirect {
+                        url: response.url.clone(),
+                        status: u16::try_from(response.status).ok(),
+                        kind: RedirectKind::Http,
+                    }),
+                    (None, Some((url, status))) if url != params.request.url => Some(Redirect {
+                        url,
+                        status,
+                        kind: match params.initiator.Type {
+                            InitiatorType::Script => RedirectKind::Javascript,
+                            _ => RedirectKind::MetaRefresh,
+                        },
+                    }),
+                    (None, _) => None,
+                };
+                log.redirects.extend(redirect);
+                log.current = Some((params.request.url.clone(), None));
+            }
+            Event::NetworkResponseReceived(event)
+                if event.params.Type == ResourceType::Document
+                    && event.params.frame_id == log.main_frame =>
+            {
+                let status = u16::try_from(event.params.response.status).ok();
+                if let Some((url, _)) = log.current.take() {
+                    log.current = Some((url, status));
+                }
+            }
+            _ => {}
+        }
+    }))
+    .map_err(|e| FetchError::BrowserError(format!("Failed to listen for navigations: {}", e)))?;
+
+    Ok(navigations)
+}
@@ -0,0 +1,180 @@
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_LANGUAGE};
+use scraper::{Html as ScraperHTML, Selector};
+
+/// Longest `lang` parameter or `Accept-Language` header forwarded to sites.
+const MAX_ACCEPT_LANGUAGE_LENGTH: usize = 200;
+
+/// ISO 639-3 codes reported by `whatlang` and their ISO 639-1 equivalents.
+const ISO_639_1: [(&str, &str); 69] = [
+    ("afr", "af"),
+    ("aka", "ak"),
+    ("amh", "am"),
+    ("ara", "ar"),
+    ("aze", "az"),
+    ("bel", "be"),
+    ("ben", "bn"),
+    ("bul", "bg"),
+    ("cat", "ca"),
+    ("ces", "cs"),
+    ("cmn", "zh"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("epo", "eo"),
+    ("est", "et"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("guj", "gu"),
+    ("heb", "he"),
+    ("hin", "hi"),
+    ("hrv", "hr"),
+    ("hun", "hu"),
+    ("hye", "hy"),
+    ("ind", "id"),
+    ("ita", "it"),
+    ("jav", "jv"),
+    ("jpn", "ja"),
+    ("kan", "kn"),
+    ("kat", "ka"),
+    ("khm", "km"),
+    ("kor", "ko"),
+    ("lat", "la"),
+    ("lav", "lv"),
+    ("lit", "lt"),
+    ("mal", "ml"),
+    ("mar", "mr"),
+    ("mkd", "mk"),
+    ("mya", "my"),
+    ("nep", "ne"),
+    ("nld", "nl"),
+    ("nob", "nb"),
+    ("ori", "or"),
+    ("pan", "pa"),
+    ("pes", "fa"),
+    ("pol", "pl"),
+    ("por", "pt"),
+    ("ron", "ro"),
+    ("rus", "ru"),
+    ("sin", "si"),
+    ("slk", "sk"),
+    ("slv", "sl"),
+    ("sna", "sn"),
+    ("spa", "es"),
+    ("srp", "sr"),
+    ("swe", "sv"),
+    ("tam", "ta"),
+    ("tel", "te"),
+    ("tgl", "tl"),
+    ("tha", "th"),
+    ("tuk", "tk"),
+    ("tur", "tr"),
+    ("ukr", "uk"),
+    ("urd", "ur"),
+    ("uzb", "uz"),
+    ("vie", "vi"),
+    ("yid", "yi"),
+    ("zul", "zu"),
+];
+
+/// Normalizes a language tag such as `en_us` or `EN-US` to `en-US`.
+///
+/// Only the first tag of a list like `Content-Language: de, en` is kept.
+///
+/// # Returns
+/// * `Some(String)` with the normalized tag.
+/// * `None` if the value is not a plausible language tag.
+pub fn normalize_language(tag: &str) -> Option<String> {
+    let tag = tag.split([',', ';']).next()?.trim().replace('_', "-");
+    let mut subtags = tag.split('-');
+
+    let primary = subtags.next()?;
+    if !(2..=8).contains(&primary.len()) || !primary.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut normalized = primary.to_ascii_lowercase();
+    for subtag in subtags {
+        if subtag.is_empty()
+            || subtag.len() > 8
+            || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        normalized.push('-');
+        match subtag.len() {
+            2 => normalized.push_str(&subtag.to_ascii_uppercase()),
+            4 => {
+                // Script subtags are title case, e.g. `zh-Hant`.
+                normalized.push_str(&subtag[..1].to_ascii_uppercase());
+                normalized.push_str(&subtag[1..].to_ascii_lowercase());
+            }
+            _ => normalized.push_str(&subtag.to_ascii_lowercase()),
+        }
+    }
+
+    Some(normalized)
+}
+
+/// Validates a `lang` parameter or `Accept-Language` header before it is
+/// forwarded to sites and used in the cache key.
+///
+/// # Returns
+/// * `Some(String)` with surrounding whitespace removed.
+/// * `None` if the value is empty, too long or not a valid header value.
+pub fn accept_language(value: &str) -> Option<String> {
+    let value = value.trim();
+    let valid = !value.is_empty()
+        && value.len() <= MAX_ACCEPT_LANGUAGE_LENGTH
+        && value.chars().all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ',' | ';' | '=' | '.' | '*' | ' ')
+        })
+        && HeaderValue::from_str(value).is_ok();
+
+    valid.then(|| value.to_string())
+}
+
+/// Reads the language a page declares in `<html lang>` (or `xml:lang`),
+/// falling back to `og:locale`.
+pub fn declared_language(document: &ScraperHTML) -> Option<String> {
+    let html_selector = Selector::parse("html").unwrap();
+    let locale_selector =
+        Selector::parse(r#"meta[property="og:locale"], meta[name="og:locale"]"#).unwrap();
+
+    let html_lang = document.select(&html_selector).next().and_then(|html| {
+        html.value()
+            .attr("lang")
+            .or_else(|| html.value().attr("xml:lang"))
+            .and_then(normalize_language)
+    });
+
+    html_lang.or_else(|| {
+        document
+            .select(&locale_selector)
+            .filter_map(|meta| meta.value().attr("content"))
+            .find_map(normalize_language)
+    })
+}
+
+/// Reads the first language from the `Content-Language` response header.
+pub fn content_language(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CONTENT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(normalize_language)
+}
+
+/// Guesses the language of a text from its trigrams and script.
+///
+/// # Returns
+/// * `Some(String)` with the ISO 639-1 code if the guess is reliable.
+/// * `None` if the text is too short or ambiguous.
+pub fn detect_language(text: &str) -> Option<String> {
+    let info = whatlang::detect(text).filter(|info| info.is_reliable())?;
+    let code = info.lang().code();
+
+    ISO_639_1
+        .iter()
+        .find(|(iso_639_3, _)| *iso_639_3 == code)
+        .map(|(_, iso_639_1)| iso_639_1.to_string())
+}
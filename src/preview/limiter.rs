@@ -0,0 +1,611 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use redis::{Client as RedisClient, Script};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+use crate::config::constants::Settings;
+
+use super::{fetcher::FetchError, pattern::host_matches};
+
+/// How often a host at its concurrency limit is polled for a free slot.
+const CONCURRENCY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a fetch may hold a concurrency slot in Redis, so a replica that
+/// dies mid-fetch does not hold it forever.
+const SLOT_LEASE: Duration = Duration::from_secs(60);
+/// How long a host backs off after a 429 without `Retry-After`.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Longest `Retry-After` honored, so one origin cannot block itself for days.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Takes a token and a concurrency slot for a host, unless the host is backing
+/// off. Returns 0 on success, -1 if every slot is taken, or else the number of
+/// milliseconds until the next token or the end of the back-off.
+const ACQUIRE_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local rate = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local concurrency = tonumber(ARGV[3])
+local lease = tonumber(ARGV[4])
+
+local until_ms = tonumber(redis.call('GET', KEYS[3]) or '0')
+if until_ms > now then
+    return until_ms - now
+end
+
+if concurrency > 0 and tonumber(redis.call('GET', KEYS[2]) or '0') >= concurrency then
+    return -1
+end
+
+if rate > 0 then
+    local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+    local tokens = tonumber(bucket[1]) or burst
+    local updated = tonumber(bucket[2]) or now
+    tokens = math.min(burst, tokens + (now - updated) * rate / 1000)
+    if tokens < 1 then
+        return math.ceil((1 - tokens) * 1000 / rate)
+    end
+    redis.call('HSET', KEYS[1], 'tokens', tostring(tokens - 1), 'updated', now)
+    redis.call('PEXPIRE', KEYS[1], math.ceil(burst * 1000 / rate) + 1000)
+end
+
+if concurrency > 0 then
+    redis.call('INCR', KEYS[2])
+    redis.call('PEXPIRE', KEYS[2], lease)
+end
+return 0
+"#;
+
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('DECR', KEYS[1]) <= 0 then
+    redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Extends a host's back-off to at least `ARGV[1]` milliseconds from now.
+const BACK_OFF_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local until_ms = now + tonumber(ARGV[1])
+if until_ms > tonumber(redis.call('GET', KEYS[1]) or '0') then
+    redis.call('SET', KEYS[1], until_ms, 'PX', ARGV[1])
+end
+return 0
+"#;
+
+/// How fast fetches to one host may go.
+///
+/// Parsed from `<rate>:<burst>:<concurrency>`, e.g. `0.5:2:1` for one request
+/// every two seconds, bursts of two and one request at a time. A rate or
+/// concurrency of 0 means no limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostLimit {
+    /// Requests per second.
+    pub rate: f64,
+    /// Requests that may be made at once after a quiet period.
+    pub burst: u32,
+    /// Requests in flight at the same time.
+    pub concurrency: u32,
+}
+
+impl FromStr for HostLimit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.split(':').map(str::trim).collect();
+        let [rate, burst, concurrency] = parts[..] else {
+            return Err(format!(
+                "Invalid rate limit {:?}, expected rate:burst:concurrency",
+                value
+            ));
+        };
+
+        let rate = rate
+            .parse::<f64>()
+            .ok()
+            .filter(|rate| rate.is_finite() && *rate >= 0.0)
+            .ok_or_else(|| format!("Invalid rate {:?}", rate))?;
+        let burst = burst
+            .parse::<u32>()
+            .map_err(|e| format!("Invalid burst {:?}: {}", burst, e))?
+            .max(1);
+        let concurrency = concurrency
+            .parse::<u32>()
+            .map_err(|e| format!("Invalid concurrency {:?}: {}", concurrency, e))?;
+
+        Ok(Self {
+            rate,
+            burst,
+            concurrency,
+        })
+    }
+}
+
+/// Parses per-domain rate limits written as `domain=limit;domain=limit`.
+///
+/// Invalid entries are reported and skipped.
+pub fn parse_domain_limits(value: &str) -> Vec<(String, HostLimit)> {
+    value
+        .split(';')
+        .filter(|rule| !rule.trim().is_empty())
+        .filter_map(|rule| {
+            let Some((domain, limit)) = rule.split_once('=') else {
+                eprintln!("Ignoring rate limit without a domain: {:?}", rule);
+                return None;
+            };
+
+            match limit.parse() {
+                Ok(limit) => Some((domain.trim().to_ascii_lowercase(), limit)),
+                Err(e) => {
+                    eprintln!("Ignoring rate limit for {}: {}", domain.trim(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Reads how long an origin asked us to wait from a 429 response, or a 503
+/// response with `Retry-After`.
+///
+/// # Returns
+/// * `Some(Duration)` if the response asks us to back off.
+/// * `None` for any other response.
+pub fn retry_after(status: Option<u16>, headers: &HeaderMap) -> Option<Duration> {
+    let header = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .and_then(|value| match value.parse::<u64>() {
+            Ok(seconds) => Some(Duration::from_secs(seconds)),
+            Err(_) => httpdate::parse_http_date(value)
+                .ok()
+                .map(|date| date.duration_since(SystemTime::now()).unwrap_or_default()),
+        });
+
+    match status {
+        Some(429) => Some(header.unwrap_or(DEFAULT_RETRY_AFTER)),
+        Some(503) => header,
+        _ => None,
+    }
+    .map(|duration| duration.min(MAX_RETRY_AFTER))
+}
+
+/// The state of one host in [`LocalLimits`].
+#[derive(Debug)]
+struct HostState {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket is full again if no token is taken.
+    full_at: Instant,
+    active: u32,
+    blocked_until: Option<Instant>,
+}
+
+impl HostState {
+    /// Whether forgetting the host would change nothing: no fetch in flight,
+    /// no back-off and a full bucket.
+    fn is_idle(&self, now: Instant) -> bool {
+        self.active == 0
+            && self.full_at <= now
+            && self.blocked_until.is_none_or(|until| until <= now)
+    }
+}
+
+type LocalLimits = Arc<Mutex<HashMap<String, HostState>>>;
+
+/// Per-host token buckets with a concurrency limit, see `RATE_LIMIT_*`.
+///
+/// With `CACHE_DATABASE_URL` set the buckets live in Redis so every replica
+/// shares them; if Redis cannot be reached, each replica limits on its own.
+pub struct HostLimiter {
+    default_limit: HostLimit,
+    domain_limits: Vec<(String, HostLimit)>,
+    /// How long a fetch may be queued before it is rejected.
+    max_wait: Duration,
+    redis: Option<RedisClient>,
+    local: LocalLimits,
+}
+
+/// A concurrency slot, given back when dropped.
+pub struct Permit {
+    host: String,
+    concurrency: u32,
+    redis: Option<RedisClient>,
+    local: LocalLimits,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.concurrency == 0 {
+            return;
+        }
+
+        match self.redis.take() {
+            Some(client) => {
+                let key = active_key(&self.host);
+                tokio::spawn(async move {
+                    let result = async {
+                        let mut conn = client.get_multiplexed_async_connection().await?;
+                        Script::new(RELEASE_SCRIPT)
+                            .key(&key)
+                            .invoke_async::<()>(&mut conn)
+                            .await
+                    }
+                    .await;
+                    if let Err(e) = result {
+                        eprintln!("Failed to release rate limit slot for {}: {}", key, e);
+                    }
+                });
+            }
+            None => {
+                if let Some(state) = self.local.lock().unwrap().get_mut(&self.host) {
+                    state.active = state.active.saturating_sub(1);
+                }
+            }
+        }
+    }
+}
+
+fn bucket_key(host: &str) -> String {
+    format!("ratelimit:{}:bucket", host)
+}
+
+fn active_key(host: &str) -> String {
+    format!("ratelimit:{}:active", host)
+}
+
+fn back_off_key(host: &str) -> String {
+    format!("ratelimit:{}:until", host)
+}
+
+/// The outcome of one attempt to take a token.
+enum Attempt {
+    Acquired,
+    /// Every concurrency slot is taken.
+    Busy,
+    /// A token is available or the back-off ends after this long.
+    Wait(Duration),
+}
+
+impl HostLimiter {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let redis = settings.cache_url.as_deref().and_then(|url| {
+            RedisClient::open(url)
+                .map_err(|e| eprintln!("Rate limits will not be shared: {}", e))
+                .ok()
+        });
+
+        Self {
+            default_limit: settings.rate_limit,
+            domain_limits: settings.rate_limit_domains.clone(),
+            max_wait: settings.rate_limit_max_wait,
+            redis,
+            local: Arc::default(),
+        }
+    }
+
+    /// Picks the limit for a host: the first matching per-domain limit,
+    /// otherwise the default.
+    fn limit_for(&self, host: &str) -> HostLimit {
+        self.domain_limits
+            .iter()
+            .find(|(domain, _)| host_matches(host, domain))
+            .map_or(self.default_limit, |(_, limit)| *limit)
+    }
+
+    /// Waits until a fetch to `host` is allowed.
+    ///
+    /// # Errors
+    /// Returns `FetchError::RateLimited` if that would take longer than
+    /// `RATE_LIMIT_MAX_WAIT_MS`.
+    pub async fn acquire(&self, host: &str) -> Result<Permit, FetchError> {
+        let host = host.to_ascii_lowercase();
+        let limit = self.limit_for(&host);
+        let deadline = Instant::now() + self.max_wait;
+
+        loop {
+            let (attempt, shared) = match &self.redis {
+                Some(client) => match self.acquire_shared(client, &host, limit).await {
+                    Ok(attempt) => (attempt, true),
+                    Err(e) => {
+                        eprintln!("Failed to check shared rate limit for {}: {}", host, e);
+                        (self.acquire_local(&host, limit), false)
+                    }
+                },
+                None => (self.acquire_local(&host, limit), false),
+            };
+
+            let wait = match attempt {
+                Attempt::Acquired => {
+                    return Ok(Permit {
+                        host,
+                        concurrency: limit.concurrency,
+                        redis: self.redis.clone().filter(|_| shared),
+                        local: self.local.clone(),
+                    })
+                }
+                Attempt::Busy => CONCURRENCY_POLL_INTERVAL,
+                Attempt::Wait(wait) => wait,
+            };
+
+            if Instant::now() + wait > deadline {
+                return Err(FetchError::RateLimited {
+                    host,
+                    retry_after: wait,
+                });
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    async fn acquire_shared(
+        &self,
+        client: &RedisClient,
+        host: &str,
+        limit: HostLimit,
+    ) -> redis::RedisResult<Attempt> {
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        let result: i64 = Script::new(ACQUIRE_SCRIPT)
+            .key(bucket_key(host))
+            .key(active_key(host))
+            .key(back_off_key(host))
+            .arg(limit.rate)
+            .arg(limit.burst)
+            .arg(limit.concurrency)
+            .arg(SLOT_LEASE.as_millis() as u64)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(match result {
+            0 => Attempt::Acquired,
+            -1 => Attempt::Busy,
+            millis => Attempt::Wait(Duration::from_millis(millis.unsigned_abs())),
+        })
+    }
+
+    fn acquire_local(&self, host: &str, limit: HostLimit) -> Attempt {
+        let now = Instant::now();
+        let mut hosts = self.local.lock().unwrap();
+        // Hosts come from requested URLs, so idle ones are forgotten rather
+        // than kept for the life of the process.
+        if !hosts.contains_key(host) {
+            hosts.retain(|_, state| !state.is_idle(now));
+        }
+        let state = hosts.entry(host.to_string()).or_insert_with(|| HostState {
+            tokens: f64::from(limit.burst),
+            updated: now,
+            full_at: now,
+            active: 0,
+            blocked_until: None,
+        });
+
+        if let Some(until) = state.blocked_until.filter(|until| *until > now) {
+            return Attempt::Wait(until - now);
+        }
+        if limit.concurrency > 0 && state.active >= limit.concurrency {
+            return Attempt::Busy;
+        }
+
+        if limit.rate > 0.0 {
+            let elapsed = now.duration_since(state.updated).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * limit.rate).min(f64::from(limit.burst));
+            state.updated = now;
+            if state.tokens < 1.0 {
+                return Attempt::Wait(Duration::from_secs_f64((1.0 - state.tokens) / limit.rate));
+            }
+            state.tokens -= 1.0;
+            state.full_at =
+                now + Duration::from_secs_f64((f64::from(limit.burst) - state.tokens) / limit.rate);
+        }
+
+        if limit.concurrency > 0 {
+            state.active += 1;
+        }
+        Attempt::Acquired
+    }
+
+    /// Stops fetching from `host` for `duration`, after it answered with 429
+    /// or `Retry-After`.
+    pub async fn back_off(&self, host: &str, duration: Duration) {
+        let host = host.to_ascii_lowercase();
+        eprintln!("Backing off from {} for {:.0?}", host, duration);
+
+        if let Some(client) = &self.redis {
+            let result = async {
+                let mut conn = client.get_multiplexed_async_connection().await?;
+                Script::new(BACK_OFF_SCRIPT)
+                    .key(back_off_key(&host))
+                    .arg(duration.as_millis().max(1) as u64)
+                    .invoke_async::<()>(&mut conn)
+                    .await
+            }
+            .await;
+            match result {
+                Ok(()) => return,
+                Err(e) => eprintln!("Failed to share back-off for {}: {}", host, e),
+            }
+        }
+
+        let until = Instant::now() + duration;
+        let mut hosts = self.local.lock().unwrap();
+        if let Some(state) = hosts.get_mut(&host) {
+            state.blocked_until = state.blocked_until.max(Some(until));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn limiter(limit: &str) -> HostLimiter {
+        HostLimiter {
+            default_limit: limit.parse().unwrap(),
+            domain_limits: parse_domain_limits("slow.example=0.1:1:1"),
+            max_wait: Duration::ZERO,
+            redis: None,
+            local: Arc::default(),
+        }
+    }
+
+    /// Moves a host's bucket back in time, as if `elapsed` had passed.
+    fn rewind(limiter: &HostLimiter, host: &str, elapsed: Duration) {
+        let mut hosts = limiter.local.lock().unwrap();
+        let state = hosts.get_mut(host).unwrap();
+        state.updated -= elapsed;
+        state.full_at -= elapsed;
+    }
+
+    fn retry_after_header(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_limits() {
+        assert_eq!(
+            "0.5:2:1".parse::<HostLimit>(),
+            Ok(HostLimit {
+                rate: 0.5,
+                burst: 2,
+                concurrency: 1
+            })
+        );
+        assert_eq!("1:0:0".parse::<HostLimit>().unwrap().burst, 1);
+        assert!("1:2".parse::<HostLimit>().is_err());
+        assert!("-1:2:0".parse::<HostLimit>().is_err());
+        assert_eq!(
+            parse_domain_limits("a.example=1:1:1;broken;b.example=x").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn picks_the_domain_limit() {
+        let limiter = limiter("10:5:0");
+
+        assert_eq!(limiter.limit_for("api.slow.example").rate, 0.1);
+        assert_eq!(limiter.limit_for("fast.example").rate, 10.0);
+    }
+
+    #[test]
+    fn bucket_allows_bursts_and_refills() {
+        let limiter = limiter("10:2:0");
+        let limit = limiter.default_limit;
+
+        assert!(matches!(
+            limiter.acquire_local("example.com", limit),
+            Attempt::Acquired
+        ));
+        assert!(matches!(
+            limiter.acquire_local("example.com", limit),
+            Attempt::Acquired
+        ));
+        match limiter.acquire_local("example.com", limit) {
+            Attempt::Wait(wait) => assert!(wait <= Duration::from_millis(100), "{:?}", wait),
+            _ => panic!("the bucket should be empty"),
+        }
+
+        rewind(&limiter, "example.com", Duration::from_millis(100));
+        assert!(matches!(
+            limiter.acquire_local("example.com", limit),
+            Attempt::Acquired
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrency_is_capped_until_permits_are_dropped() {
+        let limiter = limiter("0:1:1");
+
+        let permit = limiter.acquire("example.com").await.unwrap();
+        assert!(matches!(
+            limiter.acquire("example.com").await,
+            Err(FetchError::RateLimited { .. })
+        ));
+        assert!(limiter.acquire("other.example").await.is_ok());
+
+        drop(permit);
+        assert!(limiter.acquire("example.com").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn back_off_blocks_the_host() {
+        let limiter = limiter("0:1:0");
+        drop(limiter.acquire("example.com").await.unwrap());
+
+        limiter
+            .back_off("example.com", Duration::from_secs(30))
+            .await;
+        match limiter.acquire("example.com").await {
+            Err(FetchError::RateLimited { retry_after, .. }) => {
+                assert!(retry_after > Duration::from_secs(29))
+            }
+            _ => panic!("the host should be backing off"),
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_hosts_are_forgotten() {
+        let limiter = limiter("10:2:1");
+
+        let permit = limiter.acquire("busy.example").await.unwrap();
+        drop(limiter.acquire("drained.example").await.unwrap());
+        drop(limiter.acquire("refilled.example").await.unwrap());
+        rewind(&limiter, "refilled.example", Duration::from_secs(1));
+
+        drop(limiter.acquire("new.example").await.unwrap());
+        let mut hosts = limiter
+            .local
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        hosts.sort();
+        assert_eq!(hosts, ["busy.example", "drained.example", "new.example"]);
+        drop(permit);
+    }
+
+    #[test]
+    fn reads_retry_after() {
+        let none = HeaderMap::new();
+
+        assert_eq!(retry_after(Some(429), &none), Some(DEFAULT_RETRY_AFTER));
+        assert_eq!(
+            retry_after(Some(429), &retry_after_header("120")),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(Some(503), &retry_after_header(" 5 ")),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(retry_after(Some(503), &none), None);
+        assert_eq!(retry_after(Some(200), &retry_after_header("5")), None);
+        assert_eq!(retry_after(None, &retry_after_header("5")), None);
+        assert_eq!(
+            retry_after(Some(429), &retry_after_header("999999")),
+            Some(MAX_RETRY_AFTER)
+        );
+        assert_eq!(
+            retry_after(
+                Some(429),
+                &retry_after_header("Wed, 21 Oct 2015 07:28:00 GMT")
+            ),
+            Some(Duration::ZERO)
+        );
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(90));
+        let wait = retry_after(Some(429), &retry_after_header(&later)).unwrap();
+        assert!(wait > Duration::from_secs(85) && wait <= Duration::from_secs(90));
+    }
+}
@@ -0,0 +1,358 @@
+use lopdf::{decode_text_string, Document as PdfDocument};
+use percent_encoding::percent_decode_str;
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+
+use super::{
+    feed::{feed_kind, sniff_feed},
+    model::{FeedKind, MediaInfo, MediaKind, MetaDataResponse},
+    text::{normalize_text, MAX_FIELD_LENGTH, MAX_TITLE_LENGTH},
+};
+
+/// Number of leading bytes needed to recognise a file by its magic bytes.
+pub const SNIFF_BYTES: usize = 512;
+
+/// Enough of an image to read its dimensions from the header.
+const IMAGE_BODY_BUDGET: usize = 512 * 1024;
+/// PDFs keep their document information near the end, so they are read whole
+/// up to this size and reported without document metadata above it.
+const PDF_BODY_BUDGET: usize = 10 * 1024 * 1024;
+/// Enough of an audio or video file to find a header near the start.
+const AUDIO_VIDEO_BODY_BUDGET: usize = 1024 * 1024;
+/// Feeds are parsed whole, so they are read up to this size.
+const FEED_BODY_BUDGET: usize = 2 * 1024 * 1024;
+
+/// What a fetched resource turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Html,
+    Feed(FeedKind),
+    Media(MediaKind),
+}
+
+impl ContentKind {
+    /// The number of body bytes worth downloading for this kind of content.
+    pub fn body_budget(self) -> usize {
+        match self {
+            ContentKind::Html => usize::MAX,
+            ContentKind::Feed(_) => FEED_BODY_BUDGET,
+            ContentKind::Media(MediaKind::Image) => IMAGE_BODY_BUDGET,
+            ContentKind::Media(MediaKind::Pdf) => PDF_BODY_BUDGET,
+            ContentKind::Media(MediaKind::Audio | MediaKind::Video) => AUDIO_VIDEO_BODY_BUDGET,
+            ContentKind::Media(MediaKind::File) => SNIFF_BYTES,
+        }
+    }
+}
+
+/// Determines the kind of a resource from its `Content-Type` and magic bytes.
+///
+/// An HTML or feed `Content-Type` is trusted as-is, and feeds served as
+/// generic XML or JSON are recognized by their root element. Otherwise the
+/// magic bytes win over the header, since servers frequently label files
+/// `application/octet-stream`. A response with neither is treated as HTML.
+///
+/// # Arguments
+/// * `content_type` - The value of the `Content-Type` response header, if any.
+/// * `prefix` - The first bytes of the body.
+///
+/// # Returns
+/// * The detected `ContentKind` and the effective MIME type.
+pub fn classify(content_type: Option<&str>, prefix: &[u8]) -> (ContentKind, Option<String>) {
+    let declared = content_type.map(mime_essence);
+
+    if let Some(mime) = declared.as_deref() {
+        if is_html_mime(mime) {
+            return (ContentKind::Html, declared);
+        }
+        if let Some(kind) = feed_kind(mime) {
+            return (ContentKind::Feed(kind), declared);
+        }
+    }
+
+    if let Some(kind) = sniff_feed(declared.as_deref(), prefix) {
+        return (ContentKind::Feed(kind), declared);
+    }
+
+    let mime = infer::get(prefix)
+        .map(|kind| kind.mime_type().to_string())
+        .or(declared.filter(|mime| mime != "application/octet-stream"));
+
+    match mime.as_deref() {
+        None => (ContentKind::Html, None),
+        Some(mime) if is_html_mime(mime) => (ContentKind::Html, Some(mime.to_string())),
+        Some(mime) => (ContentKind::Media(media_kind(mime)), Some(mime.to_string())),
+    }
+}
+
+fn mime_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn is_html_mime(mime: &str) -> bool {
+    matches!(mime, "text/html" | "application/xhtml+xml")
+}
+
+fn media_kind(mime: &str) -> MediaKind {
+    match mime.split_once('/') {
+        Some(("image", _)) => MediaKind::Image,
+        Some(("audio", _)) => MediaKind::Audio,
+        Some(("video", _)) => MediaKind::Video,
+        _ if mime == "application/pdf" => MediaKind::Pdf,
+        _ => MediaKind::File,
+    }
+}
+
+/// Builds a preview for a resource that is not an HTML page.
+///
+/// # Arguments
+/// * `kind` - The kind of media, as returned by [`classify`].
+/// * `mime_type` - The effective MIME type, as returned by [`classify`].
+/// * `url` - The final URL of the resource.
+/// * `headers` - The response headers.
+/// * `body` - The downloaded part of the body.
+/// * `truncated` - Whether `body` stops short of the full resource.
+///
+/// # Returns
+/// * `MetaDataResponse` with `media` populated.
+pub fn extract_media_metadata(
+    kind: MediaKind,
+    mime_type: Option<String>,
+    url: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+    truncated: bool,
+) -> MetaDataResponse {
+    let mut media = MediaInfo::new(kind);
+    media.mime_type = mime_type;
+    media.filename = filename(url, headers);
+    media.size = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .or_else(|| (!truncated).then_some(body.len() as u64));
+
+    let mut title = None;
+    let mut image = None;
+
+    match kind {
+        MediaKind::Image => {
+            image = Some(url.to_string());
+            if let Ok(size) = imagesize::blob_size(body) {
+                media.width = u32::try_from(size.width).ok();
+                media.height = u32::try_from(size.height).ok();
+            }
+        }
+        MediaKind::Pdf if !truncated => {
+            if let Ok(document) = PdfDocument::load_mem(body) {
+                media.page_count = u32::try_from(document.get_pages().len()).ok();
+                title = pdf_info(&document, b"Title")
+                    .and_then(|value| normalize_text(&value, MAX_TITLE_LENGTH));
+                media.author = pdf_info(&document, b"Author")
+                    .and_then(|value| normalize_text(&value, MAX_FIELD_LENGTH));
+            }
+        }
+        MediaKind::Audio | MediaKind::Video => {
+            let (duration, codec) = if body.starts_with(b"RIFF") {
+                wav_info(body)
+            } else {
+                mp4_info(body)
+            };
+            media.duration = duration;
+            media.codec = codec.or_else(|| {
+                headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(codecs_parameter)
+            });
+        }
+        _ => {}
+    }
+
+    MetaDataResponse {
+        title: title.or_else(|| media.filename.clone()),
+        image,
+        media: Some(media),
+        ..Default::default()
+    }
+}
+
+/// Takes the filename from `Content-Disposition`, falling back to the last
+/// segment of the URL path.
+fn filename(url: &str, headers: &HeaderMap) -> Option<String> {
+    let from_header = headers
+        .get(CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let params = value.split(';').skip(1).filter_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                Some((name.trim().to_ascii_lowercase(), value.trim()))
+            });
+
+            let mut plain = None;
+            for (name, value) in params {
+                match name.as_str() {
+                    // RFC 5987: filename*=UTF-8''name%20with%20spaces.pdf
+                    "filename*" => {
+                        let encoded = value.split_once("''").map_or(value, |(_, name)| name);
+                        return Some(percent_decode_str(encoded).decode_utf8_lossy().into_owned());
+                    }
+                    "filename" => plain = Some(value.trim_matches('"').to_string()),
+                    _ => {}
+                }
+            }
+            plain
+        });
+
+    from_header
+        .or_else(|| {
+            let path = reqwest::Url::parse(url).ok()?.path().to_string();
+            let segment = path.rsplit('/').find(|segment| !segment.is_empty())?;
+            Some(percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        })
+        .and_then(|name| normalize_text(&name, MAX_FIELD_LENGTH))
+}
+
+/// Reads an entry of the PDF document information dictionary.
+fn pdf_info(document: &PdfDocument, key: &[u8]) -> Option<String> {
+    let info = document.trailer.get(b"Info").ok()?;
+    let (_, info) = document.dereference(info).ok()?;
+    let value = info.as_dict().ok()?.get(key).ok()?;
+    let (_, value) = document.dereference(value).ok()?;
+    decode_text_string(value).ok()
+}
+
+/// Extracts the `codecs` parameter of a `Content-Type` value.
+fn codecs_parameter(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("codecs")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Iterates over the ISO base media file format boxes in `data`, yielding each
+/// box type and payload. A box cut off by the end of `data` yields what is there.
+fn mp4_boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+
+        let size = u32::from_be_bytes(data[0..4].try_into().ok()?) as u64;
+        let box_type = &data[4..8];
+        let (header, size) = match size {
+            0 => (8, data.len() as u64),
+            1 if data.len() >= 16 => (16, u64::from_be_bytes(data[8..16].try_into().ok()?)),
+            _ => (8, size),
+        };
+
+        if size < header as u64 {
+            return None;
+        }
+
+        let end = usize::try_from(size).unwrap_or(usize::MAX).min(data.len());
+        let payload = &data[header.min(end)..end];
+        data = &data[end..];
+        Some((box_type, payload))
+    })
+}
+
+fn mp4_child<'a>(data: &'a [u8], box_type: &[u8]) -> Option<&'a [u8]> {
+    mp4_boxes(data).find_map(|(kind, payload)| (kind == box_type).then_some(payload))
+}
+
+/// Reads the duration from `moov/mvhd` and the sample entry formats from each
+/// track's `stsd`. Only works when `moov` precedes the media data.
+fn mp4_info(data: &[u8]) -> (Option<f64>, Option<String>) {
+    let Some(moov) = mp4_child(data, b"moov") else {
+        return (None, None);
+    };
+
+    let duration = mp4_child(moov, b"mvhd").and_then(|mvhd| {
+        let (timescale, duration) = match *mvhd.first()? {
+            0 if mvhd.len() >= 20 => (
+                u32::from_be_bytes(mvhd[12..16].try_into().ok()?),
+                u32::from_be_bytes(mvhd[16..20].try_into().ok()?) as u64,
+            ),
+            1 if mvhd.len() >= 32 => (
+                u32::from_be_bytes(mvhd[20..24].try_into().ok()?),
+                u64::from_be_bytes(mvhd[24..32].try_into().ok()?),
+            ),
+            _ => return None,
+        };
+        (timescale > 0).then(|| duration as f64 / timescale as f64)
+    });
+
+    let codecs = mp4_boxes(moov)
+        .filter(|(kind, _)| *kind == b"trak")
+        .filter_map(|(_, trak)| {
+            let stsd = [b"mdia", b"minf", b"stbl", b"stsd"]
+                .iter()
+                .try_fold(trak, |data, child| mp4_child(data, *child))?;
+            // Full box header and entry count precede the first sample entry.
+            let (format, _) = mp4_boxes(stsd.get(8..)?).next()?;
+            Some(String::from_utf8_lossy(format).trim().to_string())
+        })
+        .collect::<Vec<_>>();
+
+    (duration, (!codecs.is_empty()).then(|| codecs.join(", ")))
+}
+
+/// Reads the format and duration from the `fmt ` and `data` chunks of a WAV file.
+fn wav_info(data: &[u8]) -> (Option<f64>, Option<String>) {
+    if data.len() < 12 || &data[8..12] != b"WAVE" {
+        return (None, None);
+    }
+
+    let mut codec = None;
+    let mut byte_rate = None;
+    let mut duration = None;
+    let mut chunks = &data[12..];
+
+    while chunks.len() >= 8 {
+        let id = &chunks[0..4];
+        let size = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+        let payload = &chunks[8..chunks.len().min(8 + size)];
+
+        match id {
+            b"fmt " if payload.len() >= 12 => {
+                let format = u16::from_le_bytes([payload[0], payload[1]]);
+                codec = Some(match format {
+                    0x0001 => "pcm".to_string(),
+                    0x0003 => "ieee_float".to_string(),
+                    0x0006 => "alaw".to_string(),
+                    0x0007 => "mulaw".to_string(),
+                    0x0055 => "mp3".to_string(),
+                    other => format!("0x{:04x}", other),
+                });
+                byte_rate = Some(u32::from_le_bytes([
+                    payload[8],
+                    payload[9],
+                    payload[10],
+                    payload[11],
+                ]));
+            }
+            b"data" => {
+                duration = byte_rate
+                    .filter(|rate| *rate > 0)
+                    .map(|rate| size as f64 / rate as f64);
+                break;
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length.
+        let advance = 8 + size + (size & 1);
+        if advance > chunks.len() {
+            break;
+        }
+        chunks = &chunks[advance..];
+    }
+
+    (duration, codec)
+}
@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use super::fetcher::{FetchError, FetchResponse};
+
+/// Counts fetch outcomes for `/metrics`, in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct FetchMetrics {
+    /// Fetch attempts by fetcher and outcome, which is `ok` or the error kind.
+    fetches: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+    /// Time spent on successful fetches by fetcher, and how many were timed.
+    durations: Mutex<BTreeMap<&'static str, (Duration, u64)>>,
+    /// Time until the response headers arrived by fetcher, where it is known.
+    first_bytes: Mutex<BTreeMap<&'static str, (Duration, u64)>>,
+    /// Retries by the kind of error that caused them.
+    retries: Mutex<BTreeMap<&'static str, u64>>,
+    /// Preview requests that failed, by error kind.
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl FetchMetrics {
+    pub fn record_fetch(&self, fetcher: &'static str, result: &Result<FetchResponse, FetchError>) {
+        let outcome = match result {
+            Ok(response) => {
+                add_time(&self.durations, fetcher, response.timings.total);
+                if let Some(first_byte) = response.timings.first_byte {
+                    add_time(&self.first_bytes, fetcher, first_byte);
+                }
+                "ok"
+            }
+            Err(e) => e.kind(),
+        };
+        *self
+            .fetches
+            .lock()
+            .unwrap()
+            .entry((fetcher, outcome))
+            .or_default() += 1;
+    }
+
+    pub fn record_retry(&self, error: &FetchError) {
+        *self
+            .retries
+            .lock()
+            .unwrap()
+            .entry(error.kind())
+            .or_default() += 1;
+    }
+
+    pub fn record_failure(&self, error: &FetchError) {
+        *self
+            .failures
+            .lock()
+            .unwrap()
+            .entry(error.kind())
+            .or_default() += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        output.push_str("# HELP preview_fetches_total Fetch attempts by fetcher and outcome.\n");
+        output.push_str("# TYPE preview_fetches_total counter\n");
+        for ((fetcher, outcome), count) in self.fetches.lock().unwrap().iter() {
+            let _ = writeln!(
+                output,
+                "preview_fetches_total{{fetcher=\"{}\",outcome=\"{}\"}} {}",
+                fetcher, outcome, count
+            );
+        }
+
+        render_times(
+            &mut output,
+            "preview_fetch_duration_seconds",
+            "Time spent on successful fetches by fetcher.",
+            &self.durations,
+        );
+        render_times(
+            &mut output,
+            "preview_fetch_first_byte_seconds",
+            "Time until the response headers arrived by fetcher.",
+            &self.first_bytes,
+        );
+
+        output.push_str("# HELP preview_fetch_retries_total Retried fetches by error kind.\n");
+        output.push_str("# TYPE preview_fetch_retries_total counter\n");
+        for (kind, count) in self.retries.lock().unwrap().iter() {
+            let _ = writeln!(
+                output,
+                "preview_fetch_retries_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+
+        output.push_str("# HELP preview_failures_total Failed preview requests by error kind.\n");
+        output.push_str("# TYPE preview_failures_total counter\n");
+        for (kind, count) in self.failures.lock().unwrap().iter() {
+            let _ = writeln!(
+                output,
+                "preview_failures_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+
+        output
+    }
+}
+
+fn add_time(
+    times: &Mutex<BTreeMap<&'static str, (Duration, u64)>>,
+    fetcher: &'static str,
+    time: Duration,
+) {
+    let mut times = times.lock().unwrap();
+    let (sum, count) = times.entry(fetcher).or_default();
+    *sum += time;
+    *count += 1;
+}
+
+/// Writes a Prometheus summary with only `_sum` and `_count`.
+fn render_times(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    times: &Mutex<BTreeMap<&'static str, (Duration, u64)>>,
+) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} summary", name);
+    for (fetcher, (sum, count)) in times.lock().unwrap().iter() {
+        let _ = writeln!(
+            output,
+            "{}_sum{{fetcher=\"{}\"}} {}",
+            name,
+            fetcher,
+            sum.as_secs_f64()
+        );
+        let _ = writeln!(
+            output,
+            "{}_count{{fetcher=\"{}\"}} {}",
+            name, fetcher, count
+        );
+    }
+}
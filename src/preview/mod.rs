@@ -1,6 +1,29 @@
 pub mod cache_repository;
+pub mod consent;
 pub mod controller;
+pub mod encoding;
+pub mod extractor;
+pub mod feed;
+pub mod fetcher;
+pub mod headless;
+pub mod language;
+pub mod limiter;
+pub mod media;
+pub mod metrics;
 pub mod model;
+pub mod opengraph;
+pub mod pattern;
+pub mod proxy;
+pub mod readability;
+pub mod redirect;
 pub mod repository;
+pub mod reputation;
+pub mod robots;
+pub mod rules;
+pub mod safety;
+pub mod sanitize;
 pub mod service;
+pub mod strategy;
+pub mod structured;
+pub mod text;
 pub mod url;
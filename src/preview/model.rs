@@ -1,4 +1,7 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Serialize, Clone, Debug)]
 pub struct MetaData {
@@ -6,15 +9,324 @@ pub struct MetaData {
     pub description: Option<String>,
     pub keywords: Option<String>,
     pub image: Option<String>,
+    pub open_graph: Option<OpenGraph>,
+    pub media: Option<MediaInfo>,
+    pub extras: Option<BTreeMap<String, Value>>,
+    pub word_count: Option<u32>,
+    pub reading_time: Option<u32>,
+    pub language: Option<String>,
+    pub feeds: Option<Vec<Feed>>,
+    pub publication: Option<Publication>,
+    pub robots: Option<RobotsDecision>,
+    pub final_url: Option<String>,
+    pub redirects: Option<Vec<Redirect>>,
+    pub reputation: Option<Reputation>,
+    pub safety: Option<Safety>,
+    pub fetched_with: Option<FetchedWith>,
     pub link: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct MetaDataResponse {
     pub title: Option<String>,
     pub description: Option<String>,
     pub keywords: Option<String>,
     pub image: Option<String>,
+    pub open_graph: Option<OpenGraph>,
+    pub media: Option<MediaInfo>,
+    /// Site-specific fields from a site extractor, e.g. a repository's stars.
+    pub extras: Option<BTreeMap<String, Value>>,
+    /// Words in the main article text.
+    pub word_count: Option<u32>,
+    /// Estimated minutes to read the main article text.
+    pub reading_time: Option<u32>,
+    /// The page's language tag, e.g. `en` or `pt-BR`.
+    pub language: Option<String>,
+    /// RSS, Atom and JSON feeds the page links to, or the feed itself.
+    pub feeds: Option<Vec<Feed>>,
+    /// Bibliographic details of scholarly and library pages.
+    pub publication: Option<Publication>,
+    /// What robots.txt and the page's robots directives allowed, when
+    /// `ROBOTS_COMPLIANCE` is on.
+    pub robots: Option<RobotsDecision>,
+    /// The URL the preview was read from, after following redirects.
+    pub final_url: Option<String>,
+    /// The redirects followed from the requested URL to `final_url`, in order.
+    pub redirects: Option<Vec<Redirect>>,
+    /// Why the URL, or a URL it redirected through, may be unsafe.
+    pub reputation: Option<Reputation>,
+    /// Whether the content may be adult, gambling or violent.
+    pub safety: Option<Safety>,
+    pub fetched_with: Option<FetchedWith>,
+}
+
+/// Site-wide and type-specific OpenGraph properties, see <https://ogp.me/#types>.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OpenGraph {
+    #[serde(rename = "type")]
+    pub og_type: Option<String>,
+    pub site_name: Option<String>,
+    pub locale: Option<String>,
+    pub article: Option<OpenGraphArticle>,
+    pub profile: Option<OpenGraphProfile>,
+    pub book: Option<OpenGraphBook>,
+    pub music: Option<OpenGraphMusic>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OpenGraphArticle {
+    pub published_time: Option<String>,
+    pub modified_time: Option<String>,
+    pub expiration_time: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub section: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OpenGraphProfile {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub gender: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OpenGraphBook {
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub isbn: Option<String>,
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OpenGraphMusic {
+    /// Duration in seconds.
+    pub duration: Option<u32>,
+    pub album: Option<String>,
+    #[serde(default)]
+    pub musicians: Vec<String>,
+    #[serde(default)]
+    pub creators: Vec<String>,
+    pub release_date: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Image,
+    Pdf,
+    Audio,
+    Video,
+    File,
+}
+
+/// Authors, dates and identifiers from `citation_*`, Dublin Core, microdata
+/// or RDFa markup.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Publication {
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub published: Option<String>,
+    pub modified: Option<String>,
+    pub doi: Option<String>,
+    /// The journal, conference or book the work appeared in.
+    pub journal: Option<String>,
+    pub publisher: Option<String>,
+    pub volume: Option<String>,
+    pub issue: Option<String>,
+    pub pages: Option<String>,
+    pub pdf_url: Option<String>,
+}
+
+impl Publication {
+    /// Fills every field missing here from `fallback`.
+    pub fn or(self, fallback: Publication) -> Publication {
+        Publication {
+            authors: if self.authors.is_empty() {
+                fallback.authors
+            } else {
+                self.authors
+            },
+            published: self.published.or(fallback.published),
+            modified: self.modified.or(fallback.modified),
+            doi: self.doi.or(fallback.doi),
+            journal: self.journal.or(fallback.journal),
+            publisher: self.publisher.or(fallback.publisher),
+            volume: self.volume.or(fallback.volume),
+            issue: self.issue.or(fallback.issue),
+            pages: self.pages.or(fallback.pages),
+            pdf_url: self.pdf_url.or(fallback.pdf_url),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedKind {
+    Rss,
+    Atom,
+    Json,
+}
+
+/// A syndication feed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Feed {
+    pub url: String,
+    pub kind: FeedKind,
+    pub title: Option<String>,
+    /// The latest entries, newest first. Only present once the feed was fetched.
+    pub entries: Option<Vec<FeedEntry>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FeedEntry {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    /// RFC 3339 publication (or else update) time.
+    pub published: Option<String>,
+}
+
+/// How the robots.txt rules and robots directives applied to a preview.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RobotsDecision {
+    /// The robots.txt line that allowed the URL, if any matched.
+    pub matched_rule: Option<String>,
+    /// Directives from `<meta name="robots">` and `X-Robots-Tag`, e.g. `nosnippet`.
+    #[serde(default)]
+    pub directives: Vec<String>,
+    /// Preview fields removed because of `directives`.
+    #[serde(default)]
+    pub suppressed: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectKind {
+    /// A 3xx response with `Location`.
+    Http,
+    /// `<meta http-equiv="refresh">`.
+    MetaRefresh,
+    /// A script assigning `location`, seen in the headless browser.
+    Javascript,
+}
+
+/// One step of a redirect chain.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Redirect {
+    /// The URL that redirected.
+    pub url: String,
+    /// Its HTTP status, e.g. 301, or 200 for meta and script redirects.
+    pub status: Option<u16>,
+    pub kind: RedirectKind,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreatKind {
+    /// The domain is on a blocklist from `BLOCKLIST_FILES`.
+    Blocklist,
+    /// The URL's full hash is in `HASH_PREFIX_FILES`.
+    HashList,
+    /// The URL matches only a shorter hash prefix from `HASH_PREFIX_FILES`,
+    /// which unrelated URLs may share, so it is never refused.
+    HashPrefix,
+    /// The punycode host imitates another domain.
+    Homograph,
+    /// The top-level domain is often used for abuse.
+    SuspiciousTld,
+}
+
+impl ThreatKind {
+    /// Whether the URL is known to be unsafe rather than merely suspicious.
+    pub fn is_listed(self) -> bool {
+        matches!(self, ThreatKind::Blocklist | ThreatKind::HashList)
+    }
+}
+
+/// One reason a URL may be unsafe.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReputationFlag {
+    pub kind: ThreatKind,
+    /// The requested URL or the redirect it was found on.
+    pub url: String,
+    pub reason: String,
+}
+
+/// What the URL reputation checks found.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Reputation {
+    pub flags: Vec<ReputationFlag>,
+}
+
+/// How sure the safety classifier is that a preview belongs to a category.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SafetyFlag {
+    pub flagged: bool,
+    /// From 0 to 1.
+    pub confidence: f32,
+    /// What was found, e.g. `rating: adult` or `keyword: casino`.
+    #[serde(default)]
+    pub signals: Vec<String>,
+}
+
+/// Content categories not every audience should see.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Safety {
+    pub adult: SafetyFlag,
+    pub gambling: SafetyFlag,
+    pub violence: SafetyFlag,
+}
+
+impl Safety {
+    pub fn is_flagged(&self) -> bool {
+        self.adult.flagged || self.gambling.flagged || self.violence.flagged
+    }
+}
+
+/// Where a shortened URL led, as cached for the shortener.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResolvedRedirect {
+    pub final_url: String,
+    pub redirects: Vec<Redirect>,
+}
+
+/// Details about a URL that points at a file rather than an HTML page.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MediaInfo {
+    pub kind: MediaKind,
+    pub mime_type: Option<String>,
+    /// Size in bytes.
+    pub size: Option<u64>,
+    pub filename: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub page_count: Option<u32>,
+    pub author: Option<String>,
+    /// Duration in seconds.
+    pub duration: Option<f64>,
+    pub codec: Option<String>,
+}
+
+impl MediaInfo {
+    pub fn new(kind: MediaKind) -> Self {
+        Self {
+            kind,
+            mime_type: None,
+            size: None,
+            filename: None,
+            width: None,
+            height: None,
+            page_count: None,
+            author: None,
+            duration: None,
+            codec: None,
+        }
+    }
 }
 
 impl From<MetaData> for MetaDataResponse {
@@ -24,6 +336,20 @@ impl From<MetaData> for MetaDataResponse {
             description: metadata.description,
             keywords: metadata.keywords,
             image: metadata.image,
+            open_graph: metadata.open_graph,
+            media: metadata.media,
+            extras: metadata.extras,
+            word_count: metadata.word_count,
+            reading_time: metadata.reading_time,
+            language: metadata.language,
+            feeds: metadata.feeds,
+            publication: metadata.publication,
+            robots: metadata.robots,
+            final_url: metadata.final_url,
+            redirects: metadata.redirects,
+            reputation: metadata.reputation,
+            safety: metadata.safety,
+            fetched_with: metadata.fetched_with,
         }
     }
 }
@@ -35,6 +361,20 @@ impl From<&MetaData> for MetaDataResponse {
             description: metadata.description.clone(),
             keywords: metadata.keywords.clone(),
             image: metadata.image.clone(),
+            open_graph: metadata.open_graph.clone(),
+            media: metadata.media.clone(),
+            extras: metadata.extras.clone(),
+            word_count: metadata.word_count,
+            reading_time: metadata.reading_time,
+            language: metadata.language.clone(),
+            feeds: metadata.feeds.clone(),
+            publication: metadata.publication.clone(),
+            robots: metadata.robots.clone(),
+            final_url: metadata.final_url.clone(),
+            redirects: metadata.redirects.clone(),
+            reputation: metadata.reputation.clone(),
+            safety: metadata.safety.clone(),
+            fetched_with: metadata.fetched_with,
         }
     }
 }
@@ -48,32 +388,111 @@ impl TryFrom<MetaDataResponse> for MetaData {
 }
 
 impl MetaDataResponse {
-    #[allow(dead_code)]
     pub fn into_metadata(self, link: String) -> MetaData {
         MetaData {
             title: self.title,
             description: self.description,
             keywords: self.keywords,
             image: self.image,
+            open_graph: self.open_graph,
+            media: self.media,
+            extras: self.extras,
+            word_count: self.word_count,
+            reading_time: self.reading_time,
+            language: self.language,
+            feeds: self.feeds,
+            publication: self.publication,
+            robots: self.robots,
+            final_url: self.final_url,
+            redirects: self.redirects,
+            reputation: self.reputation,
+            safety: self.safety,
+            fetched_with: self.fetched_with,
             link,
         }
     }
+
+    /// Fills every field missing here from `fallback`. Extras are combined,
+    /// with this response's values winning.
+    pub fn or(self, fallback: MetaDataResponse) -> MetaDataResponse {
+        let extras = match (self.extras, fallback.extras) {
+            (Some(mut extras), Some(fallback)) => {
+                for (key, value) in fallback {
+                    extras.entry(key).or_insert(value);
+                }
+                Some(extras)
+            }
+            (extras, fallback) => extras.or(fallback),
+        };
+
+        MetaDataResponse {
+            title: self.title.or(fallback.title),
+            description: self.description.or(fallback.description),
+            keywords: self.keywords.or(fallback.keywords),
+            image: self.image.or(fallback.image),
+            open_graph: self.open_graph.or(fallback.open_graph),
+            media: self.media.or(fallback.media),
+            extras,
+            word_count: self.word_count.or(fallback.word_count),
+            reading_time: self.reading_time.or(fallback.reading_time),
+            language: self.language.or(fallback.language),
+            feeds: self.feeds.or(fallback.feeds),
+            publication: self.publication.or(fallback.publication),
+            robots: self.robots.or(fallback.robots),
+            final_url: self.final_url.or(fallback.final_url),
+            redirects: self.redirects.or(fallback.redirects),
+            reputation: self.reputation.or(fallback.reputation),
+            safety: self.safety.or(fallback.safety),
+            fetched_with: self.fetched_with.or(fallback.fetched_with),
+        }
+    }
 }
 
-impl Default for MetaDataResponse {
-    fn default() -> Self {
-        Self {
-            title: None,
-            description: None,
-            keywords: None,
-            image: None,
+/// How a page should be fetched.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchStrategy {
+    /// Plain HTTP request only.
+    Static,
+    /// Headless browser only.
+    Browser,
+    /// Plain HTTP request, falling back to the headless browser for SPA shells.
+    #[default]
+    Auto,
+}
+
+impl FetchStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FetchStrategy::Static => "static",
+            FetchStrategy::Browser => "browser",
+            FetchStrategy::Auto => "auto",
         }
     }
 }
 
+/// Which fetcher produced a preview.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchedWith {
+    Static,
+    Browser,
+    Fixture,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PreviewParams {
     pub url: String,
+    #[serde(default)]
+    pub strategy: FetchStrategy,
+    /// Sent as `Accept-Language`; defaults to the caller's own header.
+    pub lang: Option<String>,
+    /// Fetch the discovered feeds and return their latest entries.
+    #[serde(default)]
+    pub feed_entries: bool,
+    /// Leave out the image of previews flagged as unsafe.
+    #[serde(default)]
+    pub hide_flagged_images: bool,
 }
 
 impl MetaData {
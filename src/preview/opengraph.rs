@@ -0,0 +1,94 @@
+use scraper::{Html as ScraperHTML, Selector};
+
+use super::{
+    model::{OpenGraph, OpenGraphArticle, OpenGraphBook, OpenGraphMusic, OpenGraphProfile},
+    text::{normalize_text, MAX_FIELD_LENGTH},
+};
+
+/// Collects every `<meta property="...">` (and namespaced `<meta name="...">`) pair
+/// in document order.
+fn collect_properties(document: &ScraperHTML) -> Vec<(String, String)> {
+    let selector = Selector::parse("meta[property], meta[name]").unwrap();
+
+    document
+        .select(&selector)
+        .filter_map(|el| {
+            let key = el
+                .value()
+                .attr("property")
+                .or_else(|| el.value().attr("name"))?;
+            let content = normalize_text(el.value().attr("content")?, MAX_FIELD_LENGTH)?;
+
+            Some((key.trim().to_ascii_lowercase(), content))
+        })
+        .collect()
+}
+
+/// Extracts `og:type`, `og:site_name`, `og:locale` and the typed `article:*`,
+/// `profile:*`, `book:*` and `music:*` namespaces from the given document.
+///
+/// # Arguments
+/// * `document` - The parsed HTML document.
+///
+/// # Returns
+/// * `Some(OpenGraph)` if at least one of the supported properties is present.
+/// * `None` otherwise.
+pub fn extract_open_graph(document: &ScraperHTML) -> Option<OpenGraph> {
+    let mut open_graph = OpenGraph::default();
+    let mut article = OpenGraphArticle::default();
+    let mut profile = OpenGraphProfile::default();
+    let mut book = OpenGraphBook::default();
+    let mut music = OpenGraphMusic::default();
+
+    for (key, value) in collect_properties(document) {
+        let Some((namespace, property)) = key.split_once(':') else {
+            continue;
+        };
+
+        match (namespace, property) {
+            ("og", "type") => set_once(&mut open_graph.og_type, value),
+            ("og", "site_name") => set_once(&mut open_graph.site_name, value),
+            ("og", "locale") => set_once(&mut open_graph.locale, value),
+
+            ("article", "published_time") => set_once(&mut article.published_time, value),
+            ("article", "modified_time") => set_once(&mut article.modified_time, value),
+            ("article", "expiration_time") => set_once(&mut article.expiration_time, value),
+            ("article", "author") => article.authors.push(value),
+            ("article", "section") => set_once(&mut article.section, value),
+            ("article", "tag") => article.tags.push(value),
+
+            ("profile", "first_name") => set_once(&mut profile.first_name, value),
+            ("profile", "last_name") => set_once(&mut profile.last_name, value),
+            ("profile", "username") => set_once(&mut profile.username, value),
+            ("profile", "gender") => set_once(&mut profile.gender, value),
+
+            ("book", "author") => book.authors.push(value),
+            ("book", "isbn") => set_once(&mut book.isbn, value),
+            ("book", "release_date") => set_once(&mut book.release_date, value),
+            ("book", "tag") => book.tags.push(value),
+
+            ("music", "duration") if music.duration.is_none() => {
+                music.duration = value.parse().ok();
+            }
+            ("music", "album") => set_once(&mut music.album, value),
+            ("music", "musician") => music.musicians.push(value),
+            ("music", "creator") => music.creators.push(value),
+            ("music", "release_date") => set_once(&mut music.release_date, value),
+            _ => {}
+        }
+    }
+
+    open_graph.article = (article != OpenGraphArticle::default()).then_some(article);
+    open_graph.profile = (profile != OpenGraphProfile::default()).then_some(profile);
+    open_graph.book = (book != OpenGraphBook::default()).then_some(book);
+    open_graph.music = (music != OpenGraphMusic::default()).then_some(music);
+
+    (open_graph != OpenGraph::default()).then_some(open_graph)
+}
+
+/// OpenGraph allows repeated properties; for single-valued fields the first one wins.
+fn set_once(field: &mut Option<String>, value: String) {
+    if field.is_none() {
+        *field = Some(value);
+    }
+}
@@ -0,0 +1,153 @@
+use reqwest::Url;
+
+/// Whether `host` is `domain` or one of its subdomains.
+pub fn host_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Matches `text` against a glob where `*` matches any run of characters
+/// except `/` and `**` matches any run of characters.
+///
+/// On a mismatch only the last `*` is retried one character further, or,
+/// once it would have to match a `/`, the last `**`, so patterns with many
+/// wildcards cannot make matching exponential.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+
+    let (mut p, mut t) = (0, 0);
+    // The positions after the last `*` and the last `**`, and the text
+    // positions they matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    let mut globstar: Option<(usize, usize)> = None;
+
+    loop {
+        if pattern[p..].starts_with(b"**") {
+            p += 2;
+            globstar = Some((p, t));
+            star = None;
+            continue;
+        }
+        if p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        if p == pattern.len() {
+            if t == text.len() {
+                return true;
+            }
+        } else if t < text.len() && pattern[p].eq_ignore_ascii_case(&text[t]) {
+            p += 1;
+            t += 1;
+            continue;
+        }
+
+        match (star, globstar) {
+            (Some((after_star, matched)), _) if matched < text.len() && text[matched] != b'/' => {
+                star = Some((after_star, matched + 1));
+                p = after_star;
+                t = matched + 1;
+            }
+            (_, Some((after_star, matched))) if matched < text.len() => {
+                globstar = Some((after_star, matched + 1));
+                star = None;
+                p = after_star;
+                t = matched + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// A host and path pattern a URL can be matched against.
+///
+/// The host is a glob matched against the URL's host and each of its parent
+/// domains, so `amazon.co.uk` matches `www.amazon.co.uk`. A `*` in the host
+/// also matches dots, so `amazon.*` matches `amazon.example.com` as well.
+/// The path is a glob matched against the URL's path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlPattern {
+    pub host: String,
+    pub path: String,
+}
+
+impl UrlPattern {
+    pub fn new(host: &str, path: &str) -> Self {
+        Self {
+            host: host.to_ascii_lowercase(),
+            path: path.to_string(),
+        }
+    }
+
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+
+        let host_matches = std::iter::successors(Some(host.as_str()), |host| {
+            host.split_once('.').map(|(_, parent)| parent)
+        })
+        .any(|candidate| glob_matches(&self.host, candidate));
+
+        host_matches && glob_matches(&self.path, url.path())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_matches_the_domain_and_its_subdomains() {
+        assert!(host_matches("example.com", "example.com"));
+        assert!(host_matches("www.example.com", "example.com"));
+        assert!(host_matches("a.b.example.com", "example.com"));
+        assert!(!host_matches("badexample.com", "example.com"));
+        assert!(!host_matches("example.com.evil", "example.com"));
+        assert!(!host_matches("com", "example.com"));
+    }
+
+    #[test]
+    fn single_star_stays_within_a_segment() {
+        assert!(glob_matches("/questions/*", "/questions/123"));
+        assert!(glob_matches("/questions/*", "/questions/"));
+        assert!(!glob_matches("/questions/*", "/questions/123/title"));
+        assert!(glob_matches("/*/dp/*", "/Some-Product/dp/B000"));
+        assert!(glob_matches("/a*b*c", "/aXbYc"));
+        assert!(!glob_matches("/a*c", "/ab/c"));
+        assert!(glob_matches("amazon.*", "amazon.co.uk"));
+    }
+
+    #[test]
+    fn double_star_crosses_segments() {
+        assert!(glob_matches("/**/dp/**", "/Some-Product/dp/B000"));
+        assert!(glob_matches("/**/dp/**", "/a/b/c/dp/B000/ref=x"));
+        assert!(!glob_matches("/**/dp/**", "/dp/B000"));
+        assert!(glob_matches("**", ""));
+        assert!(glob_matches("/**", "/any/thing"));
+        assert!(glob_matches("/**/x*y", "/a/b/xzzy"));
+        assert!(!glob_matches("/**/x*y", "/a/xz/y"));
+        assert!(glob_matches("/**/a*/b", "/a1/a2/b"));
+        assert!(glob_matches("/**/*.pdf", "/papers/2024/Paper.PDF"));
+    }
+
+    #[test]
+    fn literals_match_whole_text_case_insensitively() {
+        assert!(glob_matches("/Watch", "/watch"));
+        assert!(!glob_matches("/watch", "/watch/more"));
+        assert!(!glob_matches("/watch", "/wat"));
+    }
+
+    #[test]
+    fn many_wildcards_do_not_backtrack_exponentially() {
+        let path = format!("/{}", "a".repeat(5000));
+
+        assert!(!glob_matches(&format!("{}b", "/*a".repeat(50)), &path));
+        assert!(!glob_matches(&format!("{}b", "**a".repeat(50)), &path));
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use scraper::{ElementRef, Html as ScraperHTML, Selector};
+
+/// Elements whose text is scored as article content.
+const PARAGRAPH_SELECTOR: &str = "p, pre, td, blockquote";
+
+/// Paragraphs shorter than this many characters are ignored.
+const MIN_PARAGRAPH_LENGTH: usize = 25;
+
+/// Average adult silent reading speed used for `reading_time`.
+const WORDS_PER_MINUTE: u32 = 200;
+
+/// Elements that never hold the main content.
+const SKIPPED_ELEMENTS: [&str; 9] = [
+    "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form",
+];
+
+/// `class` and `id` fragments of content containers.
+const POSITIVE_HINTS: [&str; 10] = [
+    "article", "body", "content", "entry", "main", "page", "post", "text", "blog", "story",
+];
+
+/// `class` and `id` fragments of page chrome around the content.
+const NEGATIVE_HINTS: [&str; 16] = [
+    "comment", "meta", "footer", "footnote", "nav", "sidebar", "sponsor", "ad-", "promo",
+    "related", "share", "social", "menu", "widget", "banner", "cookie",
+];
+
+/// The main text of a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Article {
+    /// The article's paragraphs as plain text, separated by single spaces.
+    pub text: String,
+    pub word_count: u32,
+}
+
+impl Article {
+    /// Estimated reading time in whole minutes, at least one.
+    pub fn reading_time(&self) -> u32 {
+        self.word_count.div_ceil(WORDS_PER_MINUTE).max(1)
+    }
+
+    /// The start of the article text, cut at a word boundary so it has at
+    /// most `max_length` characters including the trailing ellipsis.
+    pub fn excerpt(&self, max_length: usize) -> Option<String> {
+        if self.text.chars().count() <= max_length {
+            return Some(self.text.clone()).filter(|text| !text.is_empty());
+        }
+
+        let window = self
+            .text
+            .chars()
+            .take(max_length.saturating_sub(1))
+            .collect::<String>();
+        let cut = window
+            .rfind(' ')
+            .filter(|&cut| cut > 0)
+            .unwrap_or(window.len());
+        let mut excerpt = window[..cut]
+            .trim_end_matches(|c: char| c.is_whitespace() || c == ',' || c == ';' || c == ':')
+            .to_string();
+
+        if excerpt.is_empty() {
+            return None;
+        }
+        excerpt.push('…');
+        Some(excerpt)
+    }
+}
+
+/// Finds the main article body of a page, in the spirit of Mozilla's
+/// Readability.
+///
+/// Every paragraph of at least `MIN_PARAGRAPH_LENGTH` characters outside of
+/// navigation, headers, footers and similar chrome adds to the score of its
+/// parent and, by half, its grandparent. Longer paragraphs and ones with more
+/// commas score higher, and containers are boosted or penalized by their
+/// `class` and `id`. The best container, discounted by how much of its text
+/// is links, is taken as the article together with any sibling that scored
+/// close to it.
+///
+/// # Arguments
+/// * `document` - The parsed HTML document.
+///
+/// # Returns
+/// * `Some(Article)` with the text of the best-scoring container.
+/// * `None` if the page has no paragraph long enough to score.
+pub fn extract_article(document: &ScraperHTML) -> Option<Article> {
+    let selector = Selector::parse(PARAGRAPH_SELECTOR).unwrap();
+
+    let paragraphs = document
+        .select(&selector)
+        .filter(|paragraph| !is_skipped(paragraph))
+        .filter_map(|paragraph| {
+            let text = paragraph_text(&paragraph);
+            (text.chars().count() >= MIN_PARAGRAPH_LENGTH).then_some((paragraph, text))
+        })
+        .collect::<Vec<_>>();
+
+    let mut scores: HashMap<_, f64> = HashMap::new();
+    for (paragraph, text) in &paragraphs {
+        let length = text.chars().count();
+        let score = 1.0 + text.matches(',').count() as f64 + (length / 100).min(3) as f64;
+
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        for (ancestor, share) in ancestors.by_ref().take(2).zip([1.0, 0.5]) {
+            *scores
+                .entry(ancestor.id())
+                .or_insert_with(|| initial_score(&ancestor)) += score * share;
+        }
+    }
+
+    let (top, top_score) = scores
+        .iter()
+        .filter_map(|(&id, &score)| {
+            let element = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((element, score * (1.0 - link_density(&element))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+    // Content split across sibling containers, e.g. one per section.
+    let threshold = (top_score * 0.2).max(10.0);
+    let containers = match top.parent() {
+        Some(parent) => parent
+            .children()
+            .filter_map(ElementRef::wrap)
+            .filter(|sibling| {
+                sibling.id() == top.id()
+                    || scores
+                        .get(&sibling.id())
+                        .is_some_and(|&score| score * (1.0 - link_density(sibling)) >= threshold)
+            })
+            .collect::<Vec<_>>(),
+        None => vec![top],
+    };
+
+    let text = paragraphs
+        .iter()
+        .filter(|(paragraph, _)| {
+            paragraph.ancestors().any(|ancestor| {
+                containers
+                    .iter()
+                    .any(|container| container.id() == ancestor.id())
+            })
+        })
+        .map(|(_, text)| text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let word_count = u32::try_from(text.split_whitespace().count()).unwrap_or(u32::MAX);
+
+    Some(Article { text, word_count })
+}
+
+fn paragraph_text(paragraph: &ElementRef) -> String {
+    paragraph
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether the element sits inside page chrome rather than content.
+fn is_skipped(element: &ElementRef) -> bool {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|ancestor| {
+            SKIPPED_ELEMENTS.contains(&ancestor.value().name()) || is_unlikely(&ancestor)
+        })
+}
+
+/// A container whose `class` or `id` only hints at chrome. The document,
+/// body and semantic content elements are never unlikely.
+fn is_unlikely(element: &ElementRef) -> bool {
+    if matches!(element.value().name(), "html" | "body" | "article" | "main") {
+        return false;
+    }
+
+    let hints = hint_text(element);
+    NEGATIVE_HINTS.iter().any(|hint| hints.contains(hint))
+        && !POSITIVE_HINTS.iter().any(|hint| hints.contains(hint))
+}
+
+fn hint_text(element: &ElementRef) -> String {
+    let class = element.value().attr("class").unwrap_or_default();
+    let id = element.value().id().unwrap_or_default();
+    format!("{} {}", class, id).to_ascii_lowercase()
+}
+
+/// The starting score of a container, from its tag and `class`/`id`.
+fn initial_score(element: &ElementRef) -> f64 {
+    let tag_score = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "dd" | "dt" | "li" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+
+    tag_score + class_weight(element)
+}
+
+/// +25 for a content-like `class` or `id`, -25 for a chrome-like one.
+fn class_weight(element: &ElementRef) -> f64 {
+    let attributes = [element.value().attr("class"), element.value().id()];
+
+    attributes
+        .into_iter()
+        .flatten()
+        .map(str::to_ascii_lowercase)
+        .map(|value| {
+            if NEGATIVE_HINTS.iter().any(|hint| value.contains(hint)) {
+                -25.0
+            } else if POSITIVE_HINTS.iter().any(|hint| value.contains(hint)) {
+                25.0
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+/// The share of the element's text that is inside links.
+fn link_density(element: &ElementRef) -> f64 {
+    let selector = Selector::parse("a").unwrap();
+    let length = element.text().map(|text| text.trim().len()).sum::<usize>();
+    if length == 0 {
+        return 0.0;
+    }
+
+    let link_length = element
+        .select(&selector)
+        .flat_map(|link| link.text())
+        .map(|text| text.trim().len())
+        .sum::<usize>();
+
+    link_length as f64 / length as f64
+}
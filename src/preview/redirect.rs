@@ -0,0 +1,97 @@
+use reqwest::Url;
+use scraper::{Html as ScraperHTML, Selector};
+
+use super::pattern::host_matches;
+
+/// Redirects followed before a fetch gives up, counting HTTP redirects and
+/// meta refreshes together.
+pub const MAX_REDIRECTS: usize = 10;
+
+/// A meta refresh is only followed when it fires within this many seconds;
+/// slower ones are page reloads, not redirects.
+const MAX_META_REFRESH_DELAY: f64 = 5.0;
+
+/// URL shorteners and click-tracking redirectors whose targets are cached,
+/// see `SHORTENER_HOSTS`.
+pub const DEFAULT_SHORTENER_HOSTS: [&str; 20] = [
+    "bit.ly",
+    "bitly.com",
+    "t.co",
+    "lnkd.in",
+    "goo.gl",
+    "tinyurl.com",
+    "ow.ly",
+    "buff.ly",
+    "is.gd",
+    "t.ly",
+    "rebrand.ly",
+    "cutt.ly",
+    "shorturl.at",
+    "tiny.cc",
+    "dlvr.it",
+    "fb.me",
+    "trib.al",
+    "amzn.to",
+    "spoti.fi",
+    "l.facebook.com",
+];
+
+/// Whether `url` points at a URL shortener or tracking redirector.
+///
+/// # Arguments
+/// * `url` - The requested URL.
+/// * `hosts` - The shortener domains, see `SHORTENER_HOSTS`.
+pub fn is_shortener(url: &str, hosts: &[String]) -> bool {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        .is_some_and(|host| hosts.iter().any(|domain| host_matches(&host, domain)))
+}
+
+/// Reads the target of a `<meta http-equiv="refresh" content="0; url=...">`
+/// that fires quickly enough to count as a redirect.
+///
+/// # Returns
+/// * `Some(Url)` with the target resolved against `base`, if it differs
+///   from `base`.
+/// * `None` if there is no such meta tag, or it only reloads the page.
+pub fn meta_refresh_target(html: &str, base: &Url) -> Option<Url> {
+    let document = ScraperHTML::parse_document(html);
+    let selector = Selector::parse("meta[http-equiv][content]").unwrap();
+
+    let content = document
+        .select(&selector)
+        .find(|meta| {
+            meta.value()
+                .attr("http-equiv")
+                .is_some_and(|value| value.trim().eq_ignore_ascii_case("refresh"))
+        })?
+        .value()
+        .attr("content")?;
+
+    let (delay, target) = content.split_once([';', ','])?;
+    let delay = delay.trim().parse::<f64>().ok()?;
+    if delay > MAX_META_REFRESH_DELAY {
+        return None;
+    }
+
+    let target = target.trim();
+    let target = match target.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url=") => &target[4..],
+        _ => target,
+    };
+    let target = target.trim().trim_matches(['\'', '"']);
+    if target.is_empty() {
+        return None;
+    }
+
+    base.join(target)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url != base)
+}
+
+/// Whether a redirect from `from` to `to` leaves the origin, in which case
+/// credentials must not follow it.
+pub fn is_cross_origin(from: &Url, to: &Url) -> bool {
+    from.origin() != to.origin()
+}
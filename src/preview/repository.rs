@@ -1,15 +1,36 @@
-use crate::preview::model::MetaData;
+use crate::preview::model::{
+    Feed, MediaInfo, MetaData, OpenGraph, Publication, Redirect, Reputation, RobotsDecision, Safety,
+};
 use async_trait::async_trait;
-use sqlx::{Error as SqlxError, PgPool, Row};
-use std::sync::Arc;
+use serde_json::Value;
+use sqlx::{types::Json, Error as SqlxError, PgPool, Row};
+use std::{collections::BTreeMap, sync::Arc};
 
 const INSERT_METADATA_QUERY: &str = r#"
-    INSERT INTO preview (title, description, keywords, image, link)
-    VALUES ($1, $2, $3, $4, $5)
+    INSERT INTO preview (title, description, keywords, image, open_graph, media, extras, word_count, reading_time, language, feeds, publication, robots, final_url, redirects, reputation, safety, link)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)
+    ON CONFLICT (link) DO UPDATE SET
+        title = EXCLUDED.title,
+        description = EXCLUDED.description,
+        keywords = EXCLUDED.keywords,
+        image = EXCLUDED.image,
+        open_graph = EXCLUDED.open_graph,
+        media = EXCLUDED.media,
+        extras = EXCLUDED.extras,
+        word_count = EXCLUDED.word_count,
+        reading_time = EXCLUDED.reading_time,
+        language = EXCLUDED.language,
+        feeds = EXCLUDED.feeds,
+        publication = EXCLUDED.publication,
+        robots = EXCLUDED.robots,
+        final_url = EXCLUDED.final_url,
+        redirects = EXCLUDED.redirects,
+        reputation = EXCLUDED.reputation,
+        safety = EXCLUDED.safety
 "#;
 
 const GET_METADATA_QUERY: &str = r#"
-    SELECT title, description, keywords, image, link
+    SELECT title, description, keywords, image, open_graph, media, extras, word_count, reading_time, language, feeds, publication, robots, final_url, redirects, reputation, safety, link
     FROM preview
     WHERE link = $1
 "#;
@@ -20,7 +41,6 @@ pub enum RepositoryError {
     Database(#[from] SqlxError),
     #[error("Table does not exist")]
     TableNotFound,
-    #[allow(dead_code)]
     #[error("Other error: {0}")]
     Other(String),
 }
@@ -29,7 +49,6 @@ pub type Result<T> = std::result::Result<T, RepositoryError>;
 
 #[async_trait]
 pub trait MetadataRepository {
-    #[allow(dead_code)]
     async fn insert_metadata(&self, metadata: &MetaData) -> Result<()>;
     #[allow(dead_code)]
     async fn get_metadata_by_url(&self, link: &str) -> Result<Option<MetaData>>;
@@ -41,23 +60,19 @@ pub struct Repository {
 
 #[derive(Default)]
 pub struct RepositoryBuilder {
-    #[allow(dead_code)]
     pool: Option<Arc<PgPool>>,
 }
 
 impl RepositoryBuilder {
-    #[allow(dead_code)]
     pub fn new() -> Self {
         Self::default()
     }
 
-    #[allow(dead_code)]
     pub fn with_pool(mut self, pool: Arc<PgPool>) -> Self {
         self.pool = Some(pool);
         self
     }
 
-    #[allow(dead_code)]
     pub fn build(self) -> Result<Repository> {
         let pool = self
             .pool
@@ -68,7 +83,6 @@ impl RepositoryBuilder {
 }
 
 impl Repository {
-    #[allow(dead_code)]
     pub fn builder() -> RepositoryBuilder {
         RepositoryBuilder::new()
     }
@@ -95,6 +109,27 @@ impl MetadataRepository for Repository {
             .bind(&metadata.description)
             .bind(&metadata.keywords)
             .bind(&metadata.image)
+            .bind(metadata.open_graph.as_ref().map(Json))
+            .bind(metadata.media.as_ref().map(Json))
+            .bind(metadata.extras.as_ref().map(Json))
+            .bind(
+                metadata
+                    .word_count
+                    .and_then(|count| i32::try_from(count).ok()),
+            )
+            .bind(
+                metadata
+                    .reading_time
+                    .and_then(|minutes| i32::try_from(minutes).ok()),
+            )
+            .bind(&metadata.language)
+            .bind(metadata.feeds.as_ref().map(Json))
+            .bind(metadata.publication.as_ref().map(Json))
+            .bind(metadata.robots.as_ref().map(Json))
+            .bind(&metadata.final_url)
+            .bind(metadata.redirects.as_ref().map(Json))
+            .bind(metadata.reputation.as_ref().map(Json))
+            .bind(metadata.safety.as_ref().map(Json))
             .bind(&metadata.link)
             .execute(&*self.pool)
             .await
@@ -115,6 +150,42 @@ impl MetadataRepository for Repository {
                     description: row.get("description"),
                     keywords: row.get("keywords"),
                     image: row.get("image"),
+                    open_graph: row
+                        .get::<Option<Json<OpenGraph>>, _>("open_graph")
+                        .map(|open_graph| open_graph.0),
+                    media: row
+                        .get::<Option<Json<MediaInfo>>, _>("media")
+                        .map(|media| media.0),
+                    extras: row
+                        .get::<Option<Json<BTreeMap<String, Value>>>, _>("extras")
+                        .map(|extras| extras.0),
+                    word_count: row
+                        .get::<Option<i32>, _>("word_count")
+                        .and_then(|count| u32::try_from(count).ok()),
+                    reading_time: row
+                        .get::<Option<i32>, _>("reading_time")
+                        .and_then(|minutes| u32::try_from(minutes).ok()),
+                    language: row.get("language"),
+                    feeds: row
+                        .get::<Option<Json<Vec<Feed>>>, _>("feeds")
+                        .map(|feeds| feeds.0),
+                    publication: row
+                        .get::<Option<Json<Publication>>, _>("publication")
+                        .map(|publication| publication.0),
+                    robots: row
+                        .get::<Option<Json<RobotsDecision>>, _>("robots")
+                        .map(|robots| robots.0),
+                    final_url: row.get("final_url"),
+                    redirects: row
+                        .get::<Option<Json<Vec<Redirect>>>, _>("redirects")
+                        .map(|redirects| redirects.0),
+                    reputation: row
+                        .get::<Option<Json<Reputation>>, _>("reputation")
+                        .map(|reputation| reputation.0),
+                    safety: row
+                        .get::<Option<Json<Safety>>, _>("safety")
+                        .map(|safety| safety.0),
+                    fetched_with: None,
                     link: row.get("link"),
                 })
             })
@@ -0,0 +1,641 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs, io,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, CONTROLS};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use tokio::time::MissedTickBehavior;
+
+use crate::config::constants::Settings;
+
+use super::{
+    fetcher::FetchError,
+    model::{Reputation, ReputationFlag, ThreatKind},
+};
+
+/// Top-level domains that are cheap, rarely used legitimately, or easily
+/// mistaken for file names, flagged unless `SUSPICIOUS_TLDS` adds more.
+pub const DEFAULT_SUSPICIOUS_TLDS: [&str; 20] = [
+    "zip", "mov", "tk", "ml", "ga", "cf", "gq", "top", "xyz", "click", "country", "kim", "work",
+    "rest", "fit", "loan", "men", "bid", "cam", "icu",
+];
+
+/// Latin look-alikes of Cyrillic and Greek letters, used to show which
+/// ASCII domain a homograph imitates.
+const CONFUSABLES: [(char, char); 40] = [
+    ('а', 'a'),
+    ('в', 'b'),
+    ('с', 'c'),
+    ('ԁ', 'd'),
+    ('е', 'e'),
+    ('һ', 'h'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('к', 'k'),
+    ('ӏ', 'l'),
+    ('м', 'm'),
+    ('н', 'h'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('ԛ', 'q'),
+    ('ѕ', 's'),
+    ('т', 't'),
+    ('ս', 'u'),
+    ('ѵ', 'v'),
+    ('ԝ', 'w'),
+    ('х', 'x'),
+    ('у', 'y'),
+    ('ӡ', 'z'),
+    ('ь', 'b'),
+    ('ɡ', 'g'),
+    ('α', 'a'),
+    ('β', 'b'),
+    ('ε', 'e'),
+    ('η', 'n'),
+    ('ι', 'i'),
+    ('κ', 'k'),
+    ('ν', 'v'),
+    ('ο', 'o'),
+    ('ρ', 'p'),
+    ('τ', 't'),
+    ('υ', 'u'),
+    ('χ', 'x'),
+    ('γ', 'y'),
+    ('ϲ', 'c'),
+    ('ѡ', 'w'),
+];
+
+/// Characters percent-encoded in canonical URLs for hash matching: controls,
+/// space, non-ASCII, `#` and `%`.
+const CANONICAL_ESCAPES: &AsciiSet = &CONTROLS.add(b' ').add(b'#').add(b'%');
+
+/// How often [`ReputationChecker::watch`] checks the blocklist files for
+/// changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What to do with URLs that match a blocklist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReputationAction {
+    /// Refuse to preview them.
+    Refuse,
+    /// Preview them and report the match in `reputation`.
+    Flag,
+}
+
+impl FromStr for ReputationAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "refuse" => Ok(ReputationAction::Refuse),
+            "flag" => Ok(ReputationAction::Flag),
+            _ => Err(format!("Invalid reputation action: {:?}", value)),
+        }
+    }
+}
+
+/// Domains and hash prefixes loaded from the blocklist files.
+#[derive(Debug, Default)]
+struct Blocklists {
+    domains: HashSet<String>,
+    /// Full hashes and prefixes of Safe Browsing URL expressions.
+    hashes: HashSet<Vec<u8>>,
+    /// The lengths of the entries in `hashes`.
+    hash_lengths: BTreeSet<usize>,
+}
+
+impl Blocklists {
+    fn add_domains(&mut self, source: &str) {
+        self.domains.extend(parse_domain_list(source));
+    }
+
+    /// Reads hash prefixes in the Safe Browsing format: one hex-encoded
+    /// prefix or full SHA-256 hash per line, or `<prefix size> <base64>`
+    /// lines holding concatenated prefixes, as in the Update API's
+    /// `rawHashes`.
+    ///
+    /// # Errors
+    /// Returns the first invalid line.
+    fn add_hashes(&mut self, source: &str) -> Result<(), String> {
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = |reason: &str| format!("line {}: {}", number + 1, reason);
+            let hashes = match line.split_once(char::is_whitespace) {
+                Some((size, raw)) => {
+                    let size = size
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|size| (4..=32).contains(size))
+                        .ok_or_else(|| invalid("prefix size must be between 4 and 32"))?;
+                    let raw = STANDARD
+                        .decode(raw.trim())
+                        .map_err(|e| invalid(&format!("invalid base64: {}", e)))?;
+                    if raw.len() % size != 0 {
+                        return Err(invalid("raw hashes are not a multiple of the prefix size"));
+                    }
+                    raw.chunks(size).map(<[u8]>::to_vec).collect()
+                }
+                None => vec![decode_hex(line)
+                    .filter(|hash| (4..=32).contains(&hash.len()))
+                    .ok_or_else(|| invalid("expected 8 to 64 hex digits"))?],
+            };
+
+            for hash in hashes {
+                self.hash_lengths.insert(hash.len());
+                self.hashes.insert(hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// The blocklisted domain `host` is or is under, looked up for the host
+    /// and each of its parent domains.
+    fn listed_domain<'a>(&self, host: &'a str) -> Option<&'a str> {
+        std::iter::successors(Some(host), |host| {
+            host.split_once('.').map(|(_, parent)| parent)
+        })
+        .find(|candidate| self.domains.contains(*candidate))
+    }
+
+    /// The URL expression whose hash, or a prefix of it, is listed.
+    ///
+    /// # Returns
+    /// * `Some((expression, true))` if the full hash of an expression is
+    ///   listed, preferred over prefix matches.
+    /// * `Some((expression, false))` if only a prefix is: without the full
+    ///   hash from Safe Browsing this may be a collision.
+    /// * `None` if nothing matches.
+    fn listed_expression(&self, url: &Url) -> Option<(String, bool)> {
+        if self.hashes.is_empty() {
+            return None;
+        }
+
+        let mut prefix_match = None;
+        for expression in url_expressions(url) {
+            let hash = Sha256::digest(expression.as_bytes());
+            if self.hashes.contains(&hash[..]) {
+                return Some((expression, true));
+            }
+            if prefix_match.is_none()
+                && self
+                    .hash_lengths
+                    .iter()
+                    .any(|length| self.hashes.contains(&hash[..*length]))
+            {
+                prefix_match = Some(expression);
+            }
+        }
+        prefix_match.map(|expression| (expression, false))
+    }
+}
+
+/// Reads a domain list with one domain per line. Hosts-file lines
+/// (`0.0.0.0 example.com`) and `*.example.com` are accepted, and `#` starts
+/// a comment.
+pub fn parse_domain_list(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| line.split('#').next()?.split_whitespace().last())
+        .map(|domain| {
+            domain
+                .trim_start_matches("*.")
+                .trim_matches('.')
+                .to_ascii_lowercase()
+        })
+        .filter(|domain| !domain.is_empty() && domain != "localhost")
+        .collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&value[index..index + 2], 16).ok())
+        .collect()
+}
+
+/// Canonicalizes a URL for hash matching as Safe Browsing does: the
+/// fragment is dropped, escapes are decoded repeatedly and re-applied
+/// minimally, and repeated dots and slashes are collapsed.
+///
+/// # Returns
+/// * `(host, path, query)`, or `None` for URLs without a host.
+fn canonicalize(url: &Url) -> Option<(String, String, Option<String>)> {
+    let unescape = |value: &str| {
+        let mut value = value.to_string();
+        loop {
+            let decoded = percent_decode_str(&value).decode_utf8_lossy().into_owned();
+            if decoded == value {
+                return value;
+            }
+            value = decoded;
+        }
+    };
+    let escape = |value: &str| percent_encode(value.as_bytes(), CANONICAL_ESCAPES).to_string();
+
+    let host = unescape(url.host_str()?)
+        .trim_matches('.')
+        .split('.')
+        .filter(|label| !label.is_empty())
+        .collect::<Vec<_>>()
+        .join(".");
+    let mut path = unescape(url.path());
+    while path.contains("//") {
+        path = path.replace("//", "/");
+    }
+    let query = url.query().map(|query| escape(&unescape(query)));
+
+    Some((escape(&host), escape(&path), query))
+}
+
+/// The host suffix and path prefix combinations Safe Browsing hashes for a
+/// URL, most specific first.
+fn url_expressions(url: &Url) -> Vec<String> {
+    let Some((host, path, query)) = canonicalize(url) else {
+        return Vec::new();
+    };
+
+    let mut hosts = vec![host.clone()];
+    if url.domain().is_some() {
+        let labels = host.split('.').collect::<Vec<_>>();
+        for count in (2..=labels.len().saturating_sub(1).min(5)).rev() {
+            hosts.push(labels[labels.len() - count..].join("."));
+        }
+    }
+
+    let mut paths = Vec::new();
+    if let Some(query) = &query {
+        paths.push(format!("{}?{}", path, query));
+    }
+    paths.push(path.clone());
+    let mut prefix = String::from("/");
+    paths.push(prefix.clone());
+    let segments = path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    for segment in segments
+        .iter()
+        .take(segments.len().saturating_sub(1))
+        .take(3)
+    {
+        prefix = format!("{}{}/", prefix, segment);
+        paths.push(prefix.clone());
+    }
+
+    let mut expressions = Vec::new();
+    for host in &hosts {
+        for path in &paths {
+            let expression = format!("{}{}", host, path);
+            if !expressions.contains(&expression) {
+                expressions.push(expression);
+            }
+        }
+    }
+    expressions
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Other,
+}
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        '0'..='9' | '-' => None,
+        'a'..='z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => Some(Script::Latin),
+        '\u{0370}'..='\u{03FF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+        _ => Some(Script::Other),
+    }
+}
+
+/// Explains why an internationalized host looks like a homograph.
+///
+/// A label is suspicious when it mixes Latin with Cyrillic or Greek letters,
+/// or consists only of Cyrillic or Greek letters that all look Latin, unless
+/// the top-level domain is in that script too.
+fn homograph(host: &str) -> Option<String> {
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return None;
+    }
+
+    let (unicode, result) = idna::domain_to_unicode(host);
+    if result.is_err() {
+        return Some(format!("{} is not valid punycode", host));
+    }
+
+    let labels = unicode.split('.').collect::<Vec<_>>();
+    let tld_script = labels
+        .last()
+        .and_then(|tld| tld.chars().find_map(script))
+        .unwrap_or(Script::Latin);
+
+    let suspicious = labels.iter().any(|label| {
+        let scripts = label.chars().filter_map(script).collect::<HashSet<_>>();
+        let mixed = scripts.contains(&Script::Latin)
+            && (scripts.contains(&Script::Cyrillic) || scripts.contains(&Script::Greek));
+        let lookalike = !scripts.is_empty()
+            && scripts.iter().all(|script| *script != Script::Latin)
+            && !scripts.contains(&tld_script)
+            && label
+                .chars()
+                .filter(|c| script(*c).is_some())
+                .all(|c| CONFUSABLES.iter().any(|(confusable, _)| *confusable == c));
+        mixed || lookalike
+    });
+    if !suspicious {
+        return None;
+    }
+
+    let skeleton = unicode
+        .chars()
+        .map(|c| {
+            CONFUSABLES
+                .iter()
+                .find(|(confusable, _)| *confusable == c)
+                .map_or(c, |(_, latin)| *latin)
+        })
+        .collect::<String>();
+    Some(if skeleton.is_ascii() {
+        format!(
+            "{} is shown as {} and looks like {}",
+            host, unicode, skeleton
+        )
+    } else {
+        format!("{} is shown as {} and mixes scripts", host, unicode)
+    })
+}
+
+struct LoadedBlocklists {
+    modified: Vec<Option<SystemTime>>,
+    blocklists: Arc<Blocklists>,
+}
+
+/// Judges URLs before they are fetched: blocklisted domains and Safe
+/// Browsing hash prefixes from local files, punycode homographs and
+/// suspicious top-level domains.
+///
+/// The blocklist files are checked in the background and reloaded when any
+/// of them changes, without a restart.
+pub struct ReputationChecker {
+    domain_files: Vec<PathBuf>,
+    hash_files: Vec<PathBuf>,
+    suspicious_tlds: Vec<String>,
+    action: ReputationAction,
+    loaded: RwLock<LoadedBlocklists>,
+}
+
+impl ReputationChecker {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let checker = Self {
+            domain_files: settings.blocklist_files.clone(),
+            hash_files: settings.hash_prefix_files.clone(),
+            suspicious_tlds: settings.suspicious_tlds.clone(),
+            action: settings.reputation_action,
+            loaded: RwLock::new(LoadedBlocklists {
+                modified: Vec::new(),
+                blocklists: Arc::new(Blocklists::default()),
+            }),
+        };
+        checker.reload_if_changed();
+        checker
+    }
+
+    /// Checks `urls`: the URL about to be requested, or a requested URL and
+    /// the URLs it redirected through.
+    ///
+    /// # Returns
+    /// * `Ok(None)` if nothing looks unsafe.
+    /// * `Ok(Some(Reputation))` with the reasons to be careful.
+    /// * `Err(FetchError::Unsafe)` if a URL is blocklisted and
+    ///   `REPUTATION_ACTION` is `refuse`.
+    pub fn check<'a>(
+        &self,
+        urls: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Option<Reputation>, FetchError> {
+        let blocklists = self.loaded.read().unwrap().blocklists.clone();
+
+        let mut flags = Vec::new();
+        for url in urls {
+            for flag in self.flags(&blocklists, url) {
+                if !flags.contains(&flag) {
+                    flags.push(flag);
+                }
+            }
+        }
+
+        let listed = flags.iter().find(|flag| flag.kind.is_listed());
+        match listed {
+            Some(flag) if self.action == ReputationAction::Refuse => Err(FetchError::Unsafe {
+                url: flag.url.clone(),
+                reason: flag.reason.clone(),
+            }),
+            _ => Ok((!flags.is_empty()).then_some(Reputation { flags })),
+        }
+    }
+
+    fn flags(&self, blocklists: &Blocklists, url: &str) -> Vec<ReputationFlag> {
+        let Some(parsed) = Url::parse(url).ok().filter(|url| url.host_str().is_some()) else {
+            return Vec::new();
+        };
+        let host = parsed.host_str().unwrap_or_default().to_ascii_lowercase();
+        let flag = |kind, reason| ReputationFlag {
+            kind,
+            url: url.to_string(),
+            reason,
+        };
+
+        let mut flags = Vec::new();
+        if let Some(domain) = blocklists.listed_domain(&host) {
+            flags.push(flag(
+                ThreatKind::Blocklist,
+                format!("{} is on the domain blocklist", domain),
+            ));
+        }
+        match blocklists.listed_expression(&parsed) {
+            Some((expression, true)) => flags.push(flag(
+                ThreatKind::HashList,
+                format!("{} matches a listed hash", expression),
+            )),
+            Some((expression, false)) => flags.push(flag(
+                ThreatKind::HashPrefix,
+                format!("{} matches a listed hash prefix", expression),
+            )),
+            None => {}
+        }
+        if let Some(reason) = homograph(&host) {
+            flags.push(flag(ThreatKind::Homograph, reason));
+        }
+        let tld = host.rsplit('.').next().unwrap_or_default();
+        if self
+            .suspicious_tlds
+            .iter()
+            .any(|suspicious| suspicious == tld)
+        {
+            flags.push(flag(
+                ThreatKind::SuspiciousTld,
+                format!(".{} is often used for abuse", tld),
+            ));
+        }
+        flags
+    }
+
+    /// Checks the blocklist files every [`RELOAD_INTERVAL`] in the
+    /// background and reloads them when a modification time changed, so
+    /// requests never wait on the file system.
+    pub fn watch(self: Arc<Self>) {
+        if self.domain_files.is_empty() && self.hash_files.is_empty() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes at once, and the files were just loaded.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let checker = self.clone();
+                if let Err(e) =
+                    tokio::task::spawn_blocking(move || checker.reload_if_changed()).await
+                {
+                    eprintln!("Failed to check the blocklist files: {}", e);
+                }
+            }
+        });
+    }
+
+    fn reload_if_changed(&self) {
+        let files = self.domain_files.iter().chain(&self.hash_files);
+        let modified = files
+            .map(|path| {
+                fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+
+        if self.loaded.read().unwrap().modified == modified {
+            return;
+        }
+        let mut loaded = self.loaded.write().unwrap();
+        if loaded.modified == modified {
+            return;
+        }
+        loaded.modified = modified;
+
+        let mut blocklists = Blocklists::default();
+        for path in &self.domain_files {
+            match fs::read_to_string(path) {
+                Ok(source) => blocklists.add_domains(&source),
+                Err(e) => report_unreadable(path, e),
+            }
+        }
+        for path in &self.hash_files {
+            match fs::read_to_string(path) {
+                Ok(source) => {
+                    if let Err(e) = blocklists.add_hashes(&source) {
+                        eprintln!("Skipping the rest of {}: {}", path.display(), e);
+                    }
+                }
+                Err(e) => report_unreadable(path, e),
+            }
+        }
+
+        if !self.domain_files.is_empty() || !self.hash_files.is_empty() {
+            println!(
+                "Loaded {} blocklisted domains and {} hash prefixes",
+                blocklists.domains.len(),
+                blocklists.hashes.len()
+            );
+        }
+        loaded.blocklists = Arc::new(blocklists);
+    }
+}
+
+fn report_unreadable(path: &std::path::Path, error: io::Error) {
+    eprintln!("Cannot read blocklist {}: {}", path.display(), error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_domain_matches_the_host_and_its_parents() {
+        let mut blocklists = Blocklists::default();
+        blocklists.add_domains("0.0.0.0 evil.example\n*.tracker.test\n");
+
+        assert_eq!(
+            blocklists.listed_domain("evil.example"),
+            Some("evil.example")
+        );
+        assert_eq!(
+            blocklists.listed_domain("a.b.evil.example"),
+            Some("evil.example")
+        );
+        assert_eq!(
+            blocklists.listed_domain("cdn.tracker.test"),
+            Some("tracker.test")
+        );
+        assert_eq!(blocklists.listed_domain("notevil.example"), None);
+        assert_eq!(blocklists.listed_domain("example"), None);
+    }
+
+    fn checker(hashes: &str) -> ReputationChecker {
+        let mut blocklists = Blocklists::default();
+        blocklists.add_hashes(hashes).unwrap();
+        ReputationChecker {
+            domain_files: Vec::new(),
+            hash_files: Vec::new(),
+            suspicious_tlds: Vec::new(),
+            action: ReputationAction::Refuse,
+            loaded: RwLock::new(LoadedBlocklists {
+                modified: Vec::new(),
+                blocklists: Arc::new(blocklists),
+            }),
+        }
+    }
+
+    fn hex_hash(expression: &str) -> String {
+        Sha256::digest(expression.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    #[test]
+    fn full_hash_matches_are_refused() {
+        let checker = checker(&hex_hash("evil.example/"));
+
+        assert!(matches!(
+            checker.check(["https://www.evil.example/some/page?q=1"]),
+            Err(FetchError::Unsafe { .. })
+        ));
+        assert_eq!(checker.check(["https://good.example/"]).unwrap(), None);
+    }
+
+    #[test]
+    fn prefix_only_matches_are_flagged() {
+        let checker = checker(&hex_hash("evil.example/")[..8]);
+
+        let reputation = checker.check(["https://evil.example/"]).unwrap().unwrap();
+        assert_eq!(reputation.flags.len(), 1);
+        assert_eq!(reputation.flags[0].kind, ThreatKind::HashPrefix);
+        assert!(!ThreatKind::HashPrefix.is_listed());
+    }
+}
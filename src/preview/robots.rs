@@ -0,0 +1,552 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::HeaderMap, Client, ClientBuilder, Proxy, Url};
+use scraper::{Html as ScraperHTML, Selector};
+
+use super::{
+    fetcher::FetchError,
+    model::{MetaDataResponse, RobotsDecision},
+    proxy::ProxyRouter,
+};
+
+/// How long a fetched robots.txt is trusted, as recommended by RFC 9309.
+const ROBOTS_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long fetching robots.txt may take before the origin counts as unreachable.
+const ROBOTS_TIMEOUT: Duration = Duration::from_secs(10);
+/// robots.txt content past this size is ignored, as allowed by RFC 9309.
+const MAX_ROBOTS_BYTES: usize = 500 * 1024;
+/// Origins whose robots.txt is cached at most; past this the oldest is
+/// dropped.
+const MAX_CACHED_ORIGINS: usize = 10_000;
+/// Proxied clients kept at most; past this they are all rebuilt on demand.
+const MAX_PROXIED_CLIENTS: usize = 64;
+
+/// A parsed `Allow` or `Disallow` line.
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Rule {
+    fn describe(&self) -> String {
+        format!(
+            "{}: {}",
+            if self.allow { "Allow" } else { "Disallow" },
+            self.pattern
+        )
+    }
+}
+
+/// The rules of a robots.txt that apply to one user agent.
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTxt {
+    rules: Vec<Rule>,
+    /// Set when the origin could not be asked, in which case nothing is allowed.
+    unreachable: bool,
+}
+
+impl RobotsTxt {
+    /// Parses the groups of a robots.txt and keeps the rules for `token`,
+    /// or for `*` if no group names it.
+    ///
+    /// # Arguments
+    /// * `body` - The robots.txt content.
+    /// * `token` - Our product token, lowercase, e.g. `rushy-preview`.
+    pub fn parse(body: &str, token: &str) -> Self {
+        let mut specific = Vec::new();
+        let mut wildcard = Vec::new();
+        let mut named = false;
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim();
+
+            match key.as_str() {
+                "user-agent" => {
+                    // A user-agent line after rules starts a new group.
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    let agent = value.to_ascii_lowercase();
+                    named |= agent == token;
+                    agents.push(agent);
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    // An empty `Disallow:` allows everything and adds no rule.
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = Rule {
+                        allow: key == "allow",
+                        pattern: value.to_string(),
+                    };
+                    if agents.iter().any(|agent| agent == token) {
+                        specific.push(rule.clone());
+                    }
+                    if agents.iter().any(|agent| agent == "*") {
+                        wildcard.push(rule);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            rules: if named { specific } else { wildcard },
+            unreachable: false,
+        }
+    }
+
+    /// Decides whether `url` may be fetched. The longest matching rule wins,
+    /// and `Allow` wins a tie.
+    ///
+    /// # Returns
+    /// * Whether the URL is allowed, and the rule that decided, if any.
+    pub fn check(&self, url: &Url) -> (bool, Option<String>) {
+        if self.unreachable {
+            return (false, Some("robots.txt is unreachable".to_string()));
+        }
+        if url.path() == "/robots.txt" {
+            return (true, None);
+        }
+
+        let target = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &target))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .map_or((true, None), |rule| (rule.allow, Some(rule.describe())))
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` matches any run of characters
+/// and a trailing `$` anchors the end. Patterns otherwise match prefixes.
+///
+/// On a mismatch only the last `*` is retried one character further, so
+/// patterns with many `*` cannot make matching exponential.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern.as_bytes(), true),
+        None => (pattern.as_bytes(), false),
+    };
+    let path = path.as_bytes();
+
+    let (mut p, mut t) = (0, 0);
+    // The position after the last `*` and the path position it matched up to.
+    let mut star: Option<(usize, usize)> = None;
+
+    loop {
+        if p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        if p == pattern.len() {
+            if !anchored || t == path.len() {
+                return true;
+            }
+        } else if t < path.len() && pattern[p] == path[t] {
+            p += 1;
+            t += 1;
+            continue;
+        }
+
+        match star {
+            Some((after_star, matched)) if matched < path.len() => {
+                star = Some((after_star, matched + 1));
+                p = after_star;
+                t = matched + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Fetches robots.txt once per origin and checks URLs against it.
+pub struct RobotsChecker {
+    client: Client,
+    /// Routes robots.txt requests like page requests, see `PROXIES`.
+    proxies: Option<Arc<ProxyRouter>>,
+    /// A client per proxy, since reqwest sets proxies per client.
+    proxied_clients: Mutex<HashMap<String, Client>>,
+    user_agent: String,
+    /// Our product token, the user agent up to its version.
+    token: String,
+    cache: Mutex<HashMap<String, (Instant, Arc<RobotsTxt>)>>,
+}
+
+impl RobotsChecker {
+    pub fn new(user_agent: &str) -> Self {
+        let token = user_agent
+            .split(['/', ' '])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        Self {
+            client: client_builder(user_agent)
+                .build()
+                .expect("Failed to build robots.txt client"),
+            proxies: None,
+            proxied_clients: Mutex::new(HashMap::new()),
+            user_agent: user_agent.to_string(),
+            token,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_proxies(mut self, proxies: Option<Arc<ProxyRouter>>) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /// The client for `proxy`, or for direct connections.
+    fn client(&self, proxy: Option<&str>) -> Result<Client, FetchError> {
+        let Some(proxy) = proxy else {
+            return Ok(self.client.clone());
+        };
+
+        let mut clients = self.proxied_clients.lock().unwrap();
+        if let Some(client) = clients.get(proxy) {
+            return Ok(client.clone());
+        }
+
+        let client = Proxy::all(proxy)
+            .and_then(|proxy| client_builder(&self.user_agent).proxy(proxy).build())
+            .map_err(|e| FetchError::Proxy(format!("Invalid proxy: {}", e)))?;
+        if clients.len() >= MAX_PROXIED_CLIENTS {
+            clients.clear();
+        }
+        clients.insert(proxy.to_string(), client.clone());
+        Ok(client)
+    }
+
+    /// Checks `url` against its origin's robots.txt.
+    ///
+    /// # Errors
+    /// Returns `FetchError::RobotsDisallowed` if the URL may not be fetched,
+    /// including when robots.txt could not be fetched because of a server or
+    /// network error.
+    pub async fn check(&self, url: &str) -> Result<RobotsDecision, FetchError> {
+        let Ok(parsed) = Url::parse(url) else {
+            // Fetchers report invalid URLs themselves.
+            return Ok(RobotsDecision::default());
+        };
+
+        let robots = self.robots_for(&parsed).await;
+        let (allowed, rule) = robots.check(&parsed);
+
+        if allowed {
+            Ok(RobotsDecision {
+                matched_rule: rule,
+                ..Default::default()
+            })
+        } else {
+            Err(FetchError::RobotsDisallowed {
+                url: url.to_string(),
+                user_agent: self.user_agent.clone(),
+                rule: rule.unwrap_or_default(),
+            })
+        }
+    }
+
+    async fn robots_for(&self, url: &Url) -> Arc<RobotsTxt> {
+        let origin = url.origin().ascii_serialization();
+
+        if let Some((fetched_at, robots)) = self.cache.lock().unwrap().get(&origin) {
+            if fetched_at.elapsed() < ROBOTS_CACHE_TTL {
+                return robots.clone();
+            }
+        }
+
+        let robots = self.fetch(&origin).await;
+        // An unreachable origin is asked again on the next request.
+        if !robots.unreachable {
+            let mut cache = self.cache.lock().unwrap();
+            cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < ROBOTS_CACHE_TTL);
+            if cache.len() >= MAX_CACHED_ORIGINS {
+                let oldest = cache
+                    .iter()
+                    .min_by_key(|(_, (fetched_at, _))| *fetched_at)
+                    .map(|(origin, _)| origin.clone());
+                if let Some(oldest) = oldest {
+                    cache.remove(&oldest);
+                }
+            }
+            cache.insert(origin, (Instant::now(), robots.clone()));
+        }
+        robots
+    }
+
+    /// Fetches and parses robots.txt following RFC 9309: a 4xx response
+    /// allows everything, a 5xx response or network error allows nothing.
+    /// The request goes through the proxy routed for the origin, if any.
+    async fn fetch(&self, origin: &str) -> Arc<RobotsTxt> {
+        let unreachable = Arc::new(RobotsTxt {
+            rules: Vec::new(),
+            unreachable: true,
+        });
+        let url = format!("{}/robots.txt", origin);
+
+        let routed = self
+            .proxies
+            .as_ref()
+            .and_then(|router| router.pick(&url).map(|proxy| (router, proxy)));
+        let response = match self.client(routed.as_ref().map(|(_, proxy)| proxy.as_str())) {
+            Ok(client) => client.get(&url).send().await.map_err(FetchError::from),
+            Err(e) => Err(e),
+        };
+        if let Some((router, proxy)) = &routed {
+            let status_error = response
+                .as_ref()
+                .ok()
+                .and_then(|response| FetchError::from_status(&url, response.status().as_u16()));
+            let outcome = match (&response, &status_error) {
+                (Err(e), _) | (Ok(_), Some(e)) => Err(e),
+                (Ok(_), None) => Ok(()),
+            };
+            router.report(&url, proxy, outcome);
+        }
+
+        let mut response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Failed to fetch robots.txt for {}: {}", origin, e);
+                return unreachable;
+            }
+        };
+
+        let status = response.status();
+        if status.is_client_error() {
+            return Arc::new(RobotsTxt::default());
+        }
+        if !status.is_success() {
+            eprintln!("robots.txt for {} returned {}", origin, status);
+            return unreachable;
+        }
+
+        let mut body = Vec::new();
+        while body.len() < MAX_ROBOTS_BYTES {
+            match response.chunk().await {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Failed to read robots.txt for {}: {}", origin, e);
+                    return unreachable;
+                }
+            }
+        }
+        body.truncate(MAX_ROBOTS_BYTES);
+
+        Arc::new(RobotsTxt::parse(
+            &String::from_utf8_lossy(&body),
+            &self.token,
+        ))
+    }
+
+    /// Collects the indexing directives that apply to us from
+    /// `<meta name="robots">`, `<meta name="<token>">` and `X-Robots-Tag`.
+    ///
+    /// # Arguments
+    /// * `html` - The page, or `None` for other resources.
+    /// * `headers` - The response headers.
+    ///
+    /// # Returns
+    /// * The lowercase directives, e.g. `["noindex", "nosnippet"]`.
+    pub fn directives(&self, html: Option<&str>, headers: &HeaderMap) -> Vec<String> {
+        let mut directives = Vec::new();
+
+        if let Some(html) = html {
+            let document = ScraperHTML::parse_document(html);
+            let selector = Selector::parse("meta[name][content]").unwrap();
+            for meta in document.select(&selector) {
+                let name = meta.value().attr("name").unwrap_or_default().trim();
+                if name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(&self.token) {
+                    directives.extend(split_directives(
+                        meta.value().attr("content").unwrap_or_default(),
+                    ));
+                }
+            }
+        }
+
+        for value in headers.get_all("x-robots-tag") {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            // `X-Robots-Tag: otherbot: noindex` only applies to that crawler.
+            let value = match value.split_once(':') {
+                Some((agent, rest)) if !agent.contains(',') && !is_directive(agent) => {
+                    if !agent.trim().eq_ignore_ascii_case(&self.token) {
+                        continue;
+                    }
+                    rest
+                }
+                _ => value,
+            };
+            directives.extend(split_directives(value));
+        }
+
+        directives.sort();
+        directives.dedup();
+        directives
+    }
+}
+
+/// The client configuration shared by direct and proxied clients.
+fn client_builder(user_agent: &str) -> ClientBuilder {
+    Client::builder()
+        .user_agent(user_agent)
+        .timeout(ROBOTS_TIMEOUT)
+}
+
+fn split_directives(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .filter(|directive| !directive.is_empty())
+}
+
+/// Directives that take a value, and so contain a colon themselves.
+fn is_directive(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "max-snippet" | "max-image-preview" | "max-video-preview" | "unavailable_after"
+    )
+}
+
+/// Removes the preview fields the page asked us not to show and records the
+/// directives and what was removed in `robots`.
+///
+/// * `noindex` (or `none`) removes everything describing the page.
+/// * `nosnippet` (or `max-snippet:0`) removes the description.
+/// * `noimageindex` removes the image.
+pub fn apply_directives(
+    metadata: MetaDataResponse,
+    decision: RobotsDecision,
+    directives: Vec<String>,
+) -> MetaDataResponse {
+    let has = |wanted: &[&str]| {
+        directives
+            .iter()
+            .any(|directive| wanted.contains(&directive.as_str()))
+    };
+    let noindex = has(&["noindex", "none"]);
+    let nosnippet = noindex || has(&["nosnippet", "max-snippet:0", "max-snippet: 0"]);
+    let noimageindex = noindex || has(&["noimageindex"]);
+
+    let mut suppressed = Vec::new();
+    let mut metadata = metadata;
+
+    if noindex {
+        let fields = [
+            ("title", metadata.title.is_some()),
+            ("keywords", metadata.keywords.is_some()),
+            ("open_graph", metadata.open_graph.is_some()),
+            ("extras", metadata.extras.is_some()),
+            ("word_count", metadata.word_count.is_some()),
+            ("reading_time", metadata.reading_time.is_some()),
+            ("feeds", metadata.feeds.is_some()),
+            ("publication", metadata.publication.is_some()),
+        ];
+        suppressed.extend(
+            fields
+                .into_iter()
+                .filter(|(_, present)| *present)
+                .map(|(name, _)| name.to_string()),
+        );
+
+        metadata.title = None;
+        metadata.keywords = None;
+        metadata.open_graph = None;
+        metadata.extras = None;
+        metadata.word_count = None;
+        metadata.reading_time = None;
+        metadata.feeds = None;
+        metadata.publication = None;
+    }
+    if nosnippet && metadata.description.take().is_some() {
+        suppressed.push("description".to_string());
+    }
+    if noimageindex && metadata.image.take().is_some() {
+        suppressed.push("image".to_string());
+    }
+
+    MetaDataResponse {
+        robots: Some(RobotsDecision {
+            directives,
+            suppressed,
+            ..decision
+        }),
+        ..metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_prefixes() {
+        assert!(pattern_matches("/private", "/private/page"));
+        assert!(pattern_matches("/", "/anything"));
+        assert!(!pattern_matches("/private", "/public"));
+    }
+
+    #[test]
+    fn wildcards_match_any_run() {
+        assert!(pattern_matches("/*.pdf", "/docs/file.pdf?download=1"));
+        assert!(pattern_matches("/a*b*c", "/axxbyyc"));
+        assert!(pattern_matches("/*", "/"));
+        assert!(!pattern_matches("/a*b*c", "/axxbyy"));
+    }
+
+    #[test]
+    fn dollar_anchors_the_end() {
+        assert!(pattern_matches("/*.pdf$", "/docs/file.pdf"));
+        assert!(!pattern_matches("/*.pdf$", "/docs/file.pdf?download=1"));
+        assert!(pattern_matches("/page$", "/page"));
+        assert!(!pattern_matches("/page$", "/pages"));
+        assert!(pattern_matches("/*a$", "/banana"));
+    }
+
+    #[test]
+    fn many_wildcards_do_not_backtrack_exponentially() {
+        let pattern = format!("{}b", "/*a".repeat(50));
+        let path = format!("/{}", "a".repeat(5000));
+
+        assert!(!pattern_matches(&pattern, &path));
+    }
+
+    #[test]
+    fn rules_for_our_token_replace_the_wildcard_group() {
+        let robots = RobotsTxt::parse(
+            "User-agent: *\nDisallow: /\n\nUser-agent: rushy-preview\nDisallow: /private\n",
+            "rushy-preview",
+        );
+
+        let url = Url::parse("https://example.com/public").unwrap();
+        assert_eq!(robots.check(&url), (true, None));
+        let url = Url::parse("https://example.com/private/page").unwrap();
+        assert_eq!(
+            robots.check(&url),
+            (false, Some("Disallow: /private".to_string()))
+        );
+    }
+}
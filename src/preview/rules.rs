@@ -0,0 +1,529 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION},
+    Url,
+};
+use scraper::{Html as ScraperHTML, Selector};
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::headless::WaitStrategy;
+
+use super::{
+    consent::consent_cookies,
+    extractor::{select_attr, select_text},
+    fetcher::{FetchOptions, Secret},
+    model::{FetchStrategy, MetaDataResponse},
+    pattern::UrlPattern,
+    text::{normalize_text, MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH, MAX_TITLE_LENGTH},
+};
+
+#[derive(Error, Debug)]
+pub enum RulesError {
+    #[error("Failed to read rules file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to parse rules file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// Where to read a field from: the text of the first element matching
+/// `selector`, or its `attribute` when given.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldRule {
+    pub selector: String,
+    pub attribute: Option<String>,
+}
+
+impl FieldRule {
+    fn select(&self, document: &ScraperHTML, max_length: usize) -> Option<String> {
+        match &self.attribute {
+            Some(attribute) => select_attr(document, &self.selector, attribute)
+                .and_then(|value| normalize_text(&value, max_length)),
+            None => select_text(document, &self.selector, max_length),
+        }
+    }
+
+    /// Reads the field as a URL and resolves it against the page URL.
+    fn select_url(&self, document: &ScraperHTML, base: &Url) -> Option<String> {
+        let value = match &self.attribute {
+            Some(attribute) => select_attr(document, &self.selector, attribute),
+            None => select_text(document, &self.selector, MAX_FIELD_LENGTH),
+        }?;
+
+        base.join(&value).ok().map(String::from)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AuthKind {
+    Basic,
+    Bearer,
+}
+
+/// Credentials for a site, read from an environment variable or a file
+/// (such as a Docker secret) so they never appear in the rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAuth {
+    #[serde(rename = "type")]
+    kind: AuthKind,
+    /// The user name for basic auth.
+    username: Option<String>,
+    /// The environment variable holding the password or token.
+    secret_env: Option<String>,
+    /// The file holding the password or token.
+    secret_file: Option<PathBuf>,
+}
+
+impl RawAuth {
+    /// Reads the secret and builds the `Authorization` header value.
+    fn authorization(&self) -> Result<Secret, String> {
+        let secret = match (&self.secret_env, &self.secret_file) {
+            (Some(name), None) => {
+                env::var(name).map_err(|e| format!("Cannot read secret from ${}: {}", name, e))?
+            }
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| format!("Cannot read secret from {}: {}", path.display(), e))?
+                .trim()
+                .to_string(),
+            _ => return Err("auth needs exactly one of secret_env and secret_file".to_string()),
+        };
+
+        let value = match (self.kind, &self.username) {
+            (AuthKind::Basic, Some(username)) => {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", username, secret))
+                )
+            }
+            (AuthKind::Bearer, None) => format!("Bearer {}", secret),
+            (AuthKind::Basic, None) => return Err("basic auth needs a username".to_string()),
+            (AuthKind::Bearer, Some(_)) => {
+                return Err("bearer auth does not take a username".to_string())
+            }
+        };
+        HeaderValue::from_str(&value).map_err(|_| "The secret is not a valid header value")?;
+
+        Ok(Secret::new(value))
+    }
+}
+
+/// Whether `value` can be sent as a cookie name or value without quoting.
+fn is_cookie_safe(value: &str) -> bool {
+    value
+        .bytes()
+        .all(|byte| byte.is_ascii_graphic() && !matches!(byte, b';' | b',' | b'"' | b'\\'))
+}
+
+/// A rule as written in the rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    host: String,
+    #[serde(default = "default_path")]
+    path: String,
+    strategy: Option<FetchStrategy>,
+    wait: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    cookies: BTreeMap<String, String>,
+    auth: Option<RawAuth>,
+    #[serde(default)]
+    consent: Vec<String>,
+    title: Option<FieldRule>,
+    description: Option<FieldRule>,
+    image: Option<FieldRule>,
+    #[serde(default)]
+    fields: BTreeMap<String, FieldRule>,
+}
+
+fn default_path() -> String {
+    "/**".to_string()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RawRule>,
+}
+
+/// How to fetch and extract the pages matching a URL pattern.
+#[derive(Debug)]
+pub struct Rule {
+    pub pattern: UrlPattern,
+    /// Used instead of `auto`; an explicit `strategy` parameter still wins.
+    pub strategy: Option<FetchStrategy>,
+    pub wait: Option<WaitStrategy>,
+    pub headers: Vec<(String, String)>,
+    /// Static cookies, which make previews private.
+    pub cookies: Vec<(String, Secret)>,
+    /// Cookies from the consent presets.
+    pub consent_cookies: Vec<(String, Secret)>,
+    pub authorization: Option<Secret>,
+    pub title: Option<FieldRule>,
+    pub description: Option<FieldRule>,
+    pub image: Option<FieldRule>,
+    /// Custom fields returned in `extras`.
+    pub fields: BTreeMap<String, FieldRule>,
+}
+
+impl TryFrom<RawRule> for Rule {
+    type Error = String;
+
+    fn try_from(raw: RawRule) -> Result<Self, Self::Error> {
+        let wait = raw.wait.as_deref().map(str::parse).transpose()?;
+
+        for (name, value) in &raw.headers {
+            let header = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format!("Invalid header name {:?}: {}", name, e))?;
+            // Credentials go in `auth` and `cookies`, which keep them out of
+            // logs and the cache.
+            if [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION].contains(&header) {
+                return Err(format!(
+                    "Send {} with `auth` or `cookies`, not `headers`",
+                    name
+                ));
+            }
+            HeaderValue::from_str(value)
+                .map_err(|e| format!("Invalid value for header {}: {}", name, e))?;
+        }
+
+        for (name, value) in &raw.cookies {
+            if name.is_empty() || !is_cookie_safe(name) || name.contains('=') {
+                return Err(format!("Invalid cookie name {:?}", name));
+            }
+            if !is_cookie_safe(value) {
+                return Err(format!("Invalid value for cookie {}", name));
+            }
+        }
+
+        let mut consent = Vec::new();
+        for preset in &raw.consent {
+            let cookies = consent_cookies(preset)
+                .ok_or_else(|| format!("Unknown consent preset {:?}", preset))?;
+            consent.extend(
+                cookies
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), Secret::new(value.to_string()))),
+            );
+        }
+
+        let authorization = raw.auth.as_ref().map(RawAuth::authorization).transpose()?;
+
+        let selectors = [&raw.title, &raw.description, &raw.image]
+            .into_iter()
+            .flatten()
+            .chain(raw.fields.values());
+        for field in selectors {
+            Selector::parse(&field.selector)
+                .map_err(|e| format!("Invalid selector {:?}: {}", field.selector, e))?;
+        }
+
+        Ok(Self {
+            pattern: UrlPattern::new(&raw.host, &raw.path),
+            strategy: raw.strategy,
+            wait,
+            headers: raw.headers.into_iter().collect(),
+            cookies: raw
+                .cookies
+                .into_iter()
+                .map(|(name, value)| (name, Secret::new(value)))
+                .collect(),
+            consent_cookies: consent,
+            authorization,
+            title: raw.title,
+            description: raw.description,
+            image: raw.image,
+            fields: raw.fields,
+        })
+    }
+}
+
+impl Rule {
+    fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        let extras = self
+            .fields
+            .iter()
+            .filter_map(|(name, field)| {
+                field
+                    .select(document, MAX_FIELD_LENGTH)
+                    .map(|value| (name.clone(), Value::from(value)))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        MetaDataResponse {
+            title: self
+                .title
+                .as_ref()
+                .and_then(|field| field.select(document, MAX_TITLE_LENGTH)),
+            description: self
+                .description
+                .as_ref()
+                .and_then(|field| field.select(document, MAX_DESCRIPTION_LENGTH)),
+            image: self
+                .image
+                .as_ref()
+                .and_then(|field| field.select_url(document, url)),
+            extras: (!extras.is_empty()).then_some(extras),
+            ..Default::default()
+        }
+    }
+}
+
+/// The rules loaded from `RULES_FILE`, in file order.
+///
+/// When several rules match a URL, each setting is taken from the first
+/// rule that has it.
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Parses a TOML rules file made of `[[rule]]` tables.
+    ///
+    /// Rules with an invalid wait strategy, header or selector are reported
+    /// and skipped, as are rules that put credentials in `headers`.
+    ///
+    /// # Errors
+    /// Returns `RulesError::Parse` if the file is not valid TOML or does not
+    /// follow the rules schema.
+    pub fn parse(source: &str) -> Result<Self, RulesError> {
+        let file: RulesFile = toml::from_str(source)?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .filter_map(|raw| {
+                let pattern = format!("{}{}", raw.host, raw.path);
+                Rule::try_from(raw)
+                    .map_err(|e| eprintln!("Ignoring rule for {}: {}", pattern, e))
+                    .ok()
+            })
+            .collect();
+
+        Ok(Self { rules })
+    }
+
+    pub fn patterns(&self) -> Vec<UrlPattern> {
+        self.rules.iter().map(|rule| rule.pattern.clone()).collect()
+    }
+
+    /// The rules matching `url`, in file order.
+    pub fn matching<'a>(&'a self, url: &'a Url) -> impl Iterator<Item = &'a Rule> + 'a {
+        self.rules.iter().filter(|rule| rule.pattern.matches(url))
+    }
+
+    /// The fetch strategy forced for `url`, if any.
+    pub fn fetch_strategy(&self, url: &str) -> Option<FetchStrategy> {
+        let url = Url::parse(url).ok()?;
+        let strategy = self.matching(&url).find_map(|rule| rule.strategy);
+        strategy
+    }
+
+    /// The headers, cookies, credentials and wait strategy fetchers should
+    /// use for `url`.
+    ///
+    /// Cookies and credentials come from the first matching rule that has
+    /// any; a static cookie or credential marks the request as private.
+    pub fn fetch_options(&self, url: &str) -> FetchOptions {
+        let Ok(url) = Url::parse(url) else {
+            return FetchOptions::default();
+        };
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for (name, value) in self.matching(&url).flat_map(|rule| &rule.headers) {
+            if !headers
+                .iter()
+                .any(|(seen, _)| seen.eq_ignore_ascii_case(name))
+            {
+                headers.push((name.clone(), value.clone()));
+            }
+        }
+
+        let mut cookies: Vec<(String, Secret)> = Vec::new();
+        for (name, value) in self
+            .matching(&url)
+            .flat_map(|rule| rule.cookies.iter().chain(&rule.consent_cookies))
+        {
+            if !cookies.iter().any(|(seen, _)| seen == name) {
+                cookies.push((name.clone(), value.clone()));
+            }
+        }
+
+        let authorization = self
+            .matching(&url)
+            .find_map(|rule| rule.authorization.clone());
+        let private =
+            authorization.is_some() || self.matching(&url).any(|rule| !rule.cookies.is_empty());
+
+        let wait = self.matching(&url).find_map(|rule| rule.wait.clone());
+        FetchOptions {
+            headers,
+            cookies,
+            authorization,
+            private,
+            wait,
+            ..Default::default()
+        }
+    }
+
+    /// Extracts the fields configured by every rule matching `url`.
+    pub fn extract(&self, url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+        self.matching(url)
+            .fold(MetaDataResponse::default(), |metadata, rule| {
+                metadata.or(rule.extract(url, document))
+            })
+    }
+}
+
+/// How often [`RuleStore::watch`] checks the rules file for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+struct LoadedRules {
+    modified: Option<SystemTime>,
+    rules: Arc<Rules>,
+}
+
+/// Holds the current rules and reloads them when the rules file changes.
+pub struct RuleStore {
+    path: Option<PathBuf>,
+    loaded: RwLock<LoadedRules>,
+}
+
+impl RuleStore {
+    /// Loads the rules file at `path`, or starts with no rules if there is
+    /// none.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let store = Self {
+            path,
+            loaded: RwLock::new(LoadedRules {
+                modified: None,
+                rules: Arc::new(Rules::default()),
+            }),
+        };
+        store.reload_if_changed();
+        store
+    }
+
+    /// Returns the current rules.
+    pub fn current(&self) -> Arc<Rules> {
+        self.loaded.read().unwrap().rules.clone()
+    }
+
+    /// Checks the rules file every [`RELOAD_INTERVAL`] in the background and
+    /// reloads it when its modification time changed, so requests never wait
+    /// on the file system.
+    ///
+    /// If the new file cannot be read or parsed the error is reported and
+    /// the previous rules stay in effect.
+    pub fn watch(self: Arc<Self>) {
+        if self.path.is_none() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes at once, and the file was just loaded.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let store = self.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || store.reload_if_changed()).await
+                {
+                    eprintln!("Failed to check the rules file: {}", e);
+                }
+            }
+        });
+    }
+
+    fn reload_if_changed(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        let modified = match modified {
+            Ok(modified) => modified,
+            Err(e) => {
+                let mut loaded = self.loaded.write().unwrap();
+                if loaded.modified.take().is_some() {
+                    eprintln!(
+                        "Keeping previous rules, {} is unavailable: {}",
+                        path.display(),
+                        e
+                    );
+                }
+                return;
+            }
+        };
+
+        if self.loaded.read().unwrap().modified == Some(modified) {
+            return;
+        }
+
+        let mut loaded = self.loaded.write().unwrap();
+        if loaded.modified == Some(modified) {
+            return;
+        }
+        loaded.modified = Some(modified);
+
+        match fs::read_to_string(path)
+            .map_err(RulesError::from)
+            .and_then(|source| Rules::parse(&source))
+        {
+            Ok(rules) => {
+                println!("Loaded {} rules from {}", rules.rules.len(), path.display());
+                loaded.rules = Arc::new(rules);
+            }
+            Err(e) => eprintln!("Keeping previous rules: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_headers_are_refused() {
+        let rules = Rules::parse(
+            r#"
+            [[rule]]
+            host = "example.com"
+            headers = { "Authorization" = "Bearer token" }
+
+            [[rule]]
+            host = "example.org"
+            headers = { "cookie" = "session=1" }
+
+            [[rule]]
+            host = "example.net"
+            headers = { "Accept-Language" = "en-US" }
+            "#,
+        )
+        .unwrap();
+
+        let hosts = rules
+            .patterns()
+            .into_iter()
+            .map(|pattern| pattern.host)
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, ["example.net"]);
+    }
+}
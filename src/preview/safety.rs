@@ -0,0 +1,555 @@
+use std::{fs, path::PathBuf, str::FromStr};
+
+use reqwest::{header::HeaderMap, Url};
+use scraper::{Html as ScraperHTML, Selector};
+
+use crate::config::constants::Settings;
+
+use super::{
+    model::{MetaDataResponse, Safety, SafetyFlag},
+    pattern::host_matches,
+    reputation::parse_domain_list,
+};
+
+/// Confidence at which a category is flagged.
+pub const FLAG_THRESHOLD: f32 = 0.5;
+
+/// Confidence added by a `rating` meta tag or header marking adult content.
+const RATING_CONFIDENCE: f32 = 0.95;
+/// Confidence added by a domain on a category list.
+const DOMAIN_LIST_CONFIDENCE: f32 = 0.9;
+/// Confidence added by a category's top-level domain, e.g. `.xxx`.
+const TLD_CONFIDENCE: f32 = 0.8;
+/// Confidence added by a category keyword inside the host, e.g. `pornhub`.
+const HOST_KEYWORD_CONFIDENCE: f32 = 0.5;
+
+/// `rating` values that mark adult content. `RTA-5042-1996-1400-1577-RTA`
+/// is the Restricted To Adults label.
+const ADULT_RATINGS: [&str; 5] = ["adult", "mature", "restricted", "rta-5042", "18+"];
+
+/// Top-level domains reserved for a category.
+const ADULT_TLDS: [&str; 5] = ["xxx", "adult", "porn", "sex", "sexy"];
+const GAMBLING_TLDS: [&str; 4] = ["bet", "casino", "poker", "bingo"];
+
+/// Keywords that count anywhere in a host name. Only words that rarely
+/// occur inside unrelated names, unlike `milf` in `milford`.
+const ADULT_HOST_KEYWORDS: [&str; 3] = ["porn", "xxx", "hentai"];
+const GAMBLING_HOST_KEYWORDS: [&str; 3] = ["casino", "sportsbook", "betting"];
+
+/// Words and phrases in the title, description and keywords, with the
+/// confidence each one adds.
+const ADULT_KEYWORDS: [(&str, f32); 14] = [
+    ("porn", 0.6),
+    ("hentai", 0.6),
+    ("milf", 0.6),
+    ("xxx", 0.5),
+    ("nsfw", 0.5),
+    ("camgirl", 0.5),
+    ("onlyfans", 0.4),
+    ("nude", 0.4),
+    ("nudes", 0.4),
+    ("erotic", 0.4),
+    ("escort", 0.35),
+    ("fetish", 0.35),
+    ("sex", 0.3),
+    ("adult content", 0.4),
+];
+const GAMBLING_KEYWORDS: [(&str, f32); 12] = [
+    ("casino", 0.45),
+    ("sportsbook", 0.5),
+    ("free spins", 0.5),
+    ("bookmaker", 0.45),
+    ("betting", 0.4),
+    ("roulette", 0.4),
+    ("slots", 0.35),
+    ("blackjack", 0.35),
+    ("jackpot", 0.3),
+    ("poker", 0.3),
+    ("gambling", 0.3),
+    ("odds", 0.15),
+];
+const VIOLENCE_KEYWORDS: [(&str, f32); 11] = [
+    ("gore", 0.6),
+    ("beheading", 0.6),
+    ("dismembered", 0.5),
+    ("graphic violence", 0.5),
+    ("execution video", 0.5),
+    ("torture", 0.35),
+    ("massacre", 0.3),
+    ("bloody", 0.2),
+    ("murder", 0.2),
+    ("shooting", 0.2),
+    ("stabbing", 0.2),
+];
+
+/// What a preview can be flagged for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafetyCategory {
+    Adult,
+    Gambling,
+    Violence,
+}
+
+impl SafetyCategory {
+    const ALL: [SafetyCategory; 3] = [
+        SafetyCategory::Adult,
+        SafetyCategory::Gambling,
+        SafetyCategory::Violence,
+    ];
+
+    fn keywords(self) -> &'static [(&'static str, f32)] {
+        match self {
+            SafetyCategory::Adult => &ADULT_KEYWORDS,
+            SafetyCategory::Gambling => &GAMBLING_KEYWORDS,
+            SafetyCategory::Violence => &VIOLENCE_KEYWORDS,
+        }
+    }
+
+    fn host_keywords(self) -> &'static [&'static str] {
+        match self {
+            SafetyCategory::Adult => &ADULT_HOST_KEYWORDS,
+            SafetyCategory::Gambling => &GAMBLING_HOST_KEYWORDS,
+            SafetyCategory::Violence => &[],
+        }
+    }
+
+    fn tlds(self) -> &'static [&'static str] {
+        match self {
+            SafetyCategory::Adult => &ADULT_TLDS,
+            SafetyCategory::Gambling => &GAMBLING_TLDS,
+            SafetyCategory::Violence => &[],
+        }
+    }
+}
+
+impl FromStr for SafetyCategory {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "adult" => Ok(SafetyCategory::Adult),
+            "gambling" => Ok(SafetyCategory::Gambling),
+            "violence" => Ok(SafetyCategory::Violence),
+            _ => Err(format!("Invalid safety category: {:?}", value)),
+        }
+    }
+}
+
+/// Parses domain list files per category written as
+/// `category=path,path;category=path`.
+///
+/// Invalid entries are reported and skipped.
+pub fn parse_category_lists(value: &str) -> Vec<(SafetyCategory, Vec<PathBuf>)> {
+    value
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let Some((category, paths)) = entry.split_once('=') else {
+                eprintln!(
+                    "Ignoring safety domain list without a category: {:?}",
+                    entry
+                );
+                return None;
+            };
+
+            match category.parse() {
+                Ok(category) => Some((
+                    category,
+                    paths
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|path| !path.is_empty())
+                        .map(PathBuf::from)
+                        .collect(),
+                )),
+                Err(e) => {
+                    eprintln!("Ignoring safety domain list: {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Collects the confidence and reasons for one category.
+struct Evidence {
+    /// The chance that none of the signals is right.
+    doubt: f32,
+    signals: Vec<String>,
+}
+
+impl Evidence {
+    fn new() -> Self {
+        Self {
+            doubt: 1.0,
+            signals: Vec::new(),
+        }
+    }
+
+    /// Adds an independent signal, so that two signals of 0.5 give 0.75.
+    fn add(&mut self, confidence: f32, signal: String) {
+        if !self.signals.contains(&signal) {
+            self.doubt *= 1.0 - confidence;
+            self.signals.push(signal);
+        }
+    }
+
+    fn into_flag(self) -> SafetyFlag {
+        let confidence = ((1.0 - self.doubt) * 100.0).round() / 100.0;
+        SafetyFlag {
+            flagged: confidence >= FLAG_THRESHOLD,
+            confidence,
+            signals: self.signals,
+        }
+    }
+}
+
+/// Flags adult, gambling and violent content from page signals, without
+/// calling out to any service.
+///
+/// Signals are `rating` meta tags and headers, domain category lists from
+/// `SAFETY_DOMAIN_LISTS`, category top-level domains, and keywords in the
+/// title, description, keywords and host. Each category's confidence
+/// combines its signals as independent evidence.
+#[derive(Default)]
+pub struct SafetyClassifier {
+    domains: Vec<(SafetyCategory, Vec<String>)>,
+}
+
+impl SafetyClassifier {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let domains = settings
+            .safety_domain_lists
+            .iter()
+            .map(|(category, paths)| {
+                let domains = paths
+                    .iter()
+                    .filter_map(|path| {
+                        fs::read_to_string(path)
+                            .map_err(|e| {
+                                eprintln!("Cannot read domain list {}: {}", path.display(), e)
+                            })
+                            .ok()
+                    })
+                    .flat_map(|source| parse_domain_list(&source))
+                    .collect::<Vec<_>>();
+                println!(
+                    "Loaded {} domains into the {:?} list",
+                    domains.len(),
+                    category
+                );
+                (*category, domains)
+            })
+            .collect();
+
+        Self { domains }
+    }
+
+    /// Sets `safety` on a preview.
+    ///
+    /// # Arguments
+    /// * `metadata` - The extracted preview.
+    /// * `urls` - The requested URL and the URL the preview was read from.
+    /// * `html` - The page, or `None` for other resources.
+    /// * `headers` - The response headers.
+    pub fn apply(
+        &self,
+        metadata: MetaDataResponse,
+        urls: &[&str],
+        html: Option<&str>,
+        headers: &HeaderMap,
+    ) -> MetaDataResponse {
+        let hosts = urls
+            .iter()
+            .filter_map(|url| {
+                Url::parse(url)
+                    .ok()?
+                    .host_str()
+                    .map(str::to_ascii_lowercase)
+            })
+            .collect::<Vec<_>>();
+        let text = words(
+            &[&metadata.title, &metadata.description, &metadata.keywords]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        let ratings = ratings(html, headers);
+
+        let flags = SafetyCategory::ALL.map(|category| {
+            let mut evidence = Evidence::new();
+
+            if category == SafetyCategory::Adult {
+                for rating in &ratings {
+                    evidence.add(RATING_CONFIDENCE, format!("rating: {}", rating));
+                }
+            }
+
+            for host in &hosts {
+                let listed = self
+                    .domains
+                    .iter()
+                    .filter(|(listed, _)| *listed == category)
+                    .flat_map(|(_, domains)| domains)
+                    .find(|domain| host_matches(host, domain));
+                if let Some(domain) = listed {
+                    evidence.add(DOMAIN_LIST_CONFIDENCE, format!("domain list: {}", domain));
+                }
+
+                let tld = host.rsplit('.').next().unwrap_or_default();
+                if category.tlds().contains(&tld) {
+                    evidence.add(TLD_CONFIDENCE, format!("tld: .{}", tld));
+                }
+
+                for keyword in category.host_keywords() {
+                    if host.contains(keyword) {
+                        evidence.add(HOST_KEYWORD_CONFIDENCE, format!("host: {}", keyword));
+                    }
+                }
+            }
+
+            for (keyword, weight) in category.keywords() {
+                if text.contains(&format!(" {} ", keyword)) {
+                    evidence.add(*weight, format!("keyword: {}", keyword));
+                }
+            }
+
+            evidence
+        });
+
+        let [adult, gambling, violence] = flags.map(Evidence::into_flag);
+        MetaDataResponse {
+            safety: Some(Safety {
+                adult,
+                gambling,
+                violence,
+            }),
+            ..metadata
+        }
+    }
+}
+
+/// Lowercases `text` and pads every word with spaces, so that keywords and
+/// phrases can be matched on word boundaries.
+fn words(text: &str) -> String {
+    let words = text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(" {} ", words)
+}
+
+/// The adult `rating` values declared by the page's meta tags and the
+/// `Rating` response header.
+fn ratings(html: Option<&str>, headers: &HeaderMap) -> Vec<String> {
+    let mut values = headers
+        .get_all("rating")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    if let Some(html) = html {
+        let document = ScraperHTML::parse_document(html);
+        let selector = Selector::parse("meta[content]").unwrap();
+        values.extend(
+            document
+                .select(&selector)
+                .filter(|meta| {
+                    [meta.value().attr("name"), meta.value().attr("http-equiv")]
+                        .into_iter()
+                        .flatten()
+                        .any(|name| name.trim().eq_ignore_ascii_case("rating"))
+                })
+                .filter_map(|meta| meta.value().attr("content"))
+                .map(String::from),
+        );
+    }
+
+    let mut ratings = values
+        .into_iter()
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| ADULT_RATINGS.iter().any(|rating| value.starts_with(rating)))
+        .collect::<Vec<_>>();
+    ratings.sort();
+    ratings.dedup();
+    ratings
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn classify(
+        classifier: &SafetyClassifier,
+        title: &str,
+        urls: &[&str],
+        html: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Safety {
+        let metadata = MetaDataResponse {
+            title: Some(title.to_string()),
+            ..Default::default()
+        };
+        classifier
+            .apply(metadata, urls, html, headers)
+            .safety
+            .unwrap()
+    }
+
+    fn classify_title(title: &str) -> Safety {
+        classify(
+            &SafetyClassifier::default(),
+            title,
+            &["https://example.com/"],
+            None,
+            &HeaderMap::new(),
+        )
+    }
+
+    #[test]
+    fn evidence_combines_independent_signals() {
+        let mut evidence = Evidence::new();
+        evidence.add(0.5, "a".to_string());
+        evidence.add(0.5, "b".to_string());
+        // A repeated signal counts once.
+        evidence.add(0.5, "b".to_string());
+
+        let flag = evidence.into_flag();
+        assert_eq!(flag.confidence, 0.75);
+        assert!(flag.flagged);
+        assert_eq!(flag.signals, ["a", "b"]);
+    }
+
+    #[test]
+    fn flags_from_the_threshold() {
+        let mut below = Evidence::new();
+        below.add(0.49, "weak".to_string());
+        assert!(!below.into_flag().flagged);
+
+        let mut at = Evidence::new();
+        at.add(FLAG_THRESHOLD, "enough".to_string());
+        assert!(at.into_flag().flagged);
+
+        let none = Evidence::new().into_flag();
+        assert_eq!(none.confidence, 0.0);
+        assert!(!none.flagged);
+    }
+
+    #[test]
+    fn keywords_match_whole_words() {
+        assert_eq!(words("Free-Spins, NOW!"), " free spins now ");
+
+        assert!(classify_title("Hot MILF videos").adult.flagged);
+        let milford = classify_title("Milford town council meeting").adult;
+        assert!(!milford.flagged);
+        assert!(milford.signals.is_empty());
+        assert!(classify_title("Sussex news").adult.signals.is_empty());
+
+        let gambling = classify_title("Casino: 50 free spins").gambling;
+        assert!(gambling.flagged);
+        assert_eq!(gambling.signals, ["keyword: casino", "keyword: free spins"]);
+        assert!(!classify_title("Betting odds explained").violence.flagged);
+    }
+
+    #[test]
+    fn ratings_come_from_meta_tags_and_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "rating",
+            HeaderValue::from_static("RTA-5042-1996-1400-1577-RTA"),
+        );
+        let html = r#"<html><head>
+            <meta name="Rating" content="adult">
+            <meta http-equiv="rating" content=" Adult ">
+            <meta name="rating" content="general">
+        </head></html>"#;
+
+        assert_eq!(
+            ratings(Some(html), &headers),
+            ["adult", "rta-5042-1996-1400-1577-rta"]
+        );
+        assert!(ratings(
+            Some("<meta name=\"rating\" content=\"general\">"),
+            &HeaderMap::new()
+        )
+        .is_empty());
+
+        let adult = classify(
+            &SafetyClassifier::default(),
+            "Welcome",
+            &["https://example.com/"],
+            Some(html),
+            &HeaderMap::new(),
+        )
+        .adult;
+        assert!(adult.flagged);
+        assert_eq!(adult.confidence, 0.95);
+        assert_eq!(adult.signals, ["rating: adult"]);
+    }
+
+    #[test]
+    fn hosts_add_tld_keyword_and_list_signals() {
+        let classifier = SafetyClassifier {
+            domains: vec![(SafetyCategory::Violence, vec!["gore.example".to_string()])],
+        };
+        let headers = HeaderMap::new();
+
+        let tld = classify(&classifier, "Home", &["https://site.xxx/"], None, &headers);
+        assert!(tld.adult.flagged);
+        assert_eq!(tld.adult.signals, ["tld: .xxx", "host: xxx"]);
+
+        let host = classify(
+            &classifier,
+            "Home",
+            &["https://www.bestcasino.example/"],
+            None,
+            &headers,
+        );
+        assert_eq!(host.gambling.signals, ["host: casino"]);
+        assert!(host.gambling.flagged);
+
+        let listed = classify(
+            &classifier,
+            "Home",
+            &["https://example.com/", "https://cdn.gore.example/video"],
+            None,
+            &headers,
+        );
+        assert!(listed.violence.flagged);
+        assert_eq!(listed.violence.signals, ["domain list: gore.example"]);
+
+        let clean = classify(
+            &classifier,
+            "Home",
+            &["https://milford.example/"],
+            None,
+            &headers,
+        );
+        assert!(!clean.adult.flagged && !clean.gambling.flagged && !clean.violence.flagged);
+    }
+
+    #[test]
+    fn parses_category_lists() {
+        let lists =
+            parse_category_lists("adult=/a.txt, /b.txt;unknown=/c.txt;gambling=/d.txt;broken; ");
+
+        assert_eq!(
+            lists,
+            [
+                (
+                    SafetyCategory::Adult,
+                    vec![PathBuf::from("/a.txt"), PathBuf::from("/b.txt")]
+                ),
+                (SafetyCategory::Gambling, vec![PathBuf::from("/d.txt")]),
+            ]
+        );
+        assert_eq!(" Violence ".parse(), Ok(SafetyCategory::Violence));
+    }
+}
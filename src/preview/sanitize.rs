@@ -0,0 +1,564 @@
+use reqwest::Url;
+use serde_json::Value;
+
+use super::{
+    model::{
+        Feed, FeedEntry, MediaInfo, MetaData, MetaDataResponse, OpenGraph, OpenGraphArticle,
+        OpenGraphBook, OpenGraphMusic, OpenGraphProfile, Publication, Redirect, Reputation,
+        ReputationFlag, RobotsDecision, Safety, SafetyFlag,
+    },
+    text::{
+        clamp, MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH, MAX_KEYWORDS_LENGTH, MAX_TITLE_LENGTH,
+        MAX_URL_LENGTH,
+    },
+};
+
+/// URL schemes a preview may link to.
+const ALLOWED_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// Whether `c` is invisible formatting that can disguise text: bidi
+/// embeddings, overrides and isolates, and zero-width characters. The
+/// zero-width joiner is kept because emoji sequences need it.
+fn is_format_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}'
+            | '\u{200B}'
+            | '\u{200C}'
+            | '\u{200E}'
+            | '\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{FEFF}'
+    )
+}
+
+/// Removes control and bidi characters from `value`, turning whitespace
+/// controls into spaces, and cuts it to `max_length` characters.
+///
+/// # Returns
+/// * `None` if nothing but whitespace remains.
+pub fn clean_text(value: &str, max_length: usize) -> Option<String> {
+    let cleaned = value
+        .chars()
+        .filter(|c| !is_format_control(*c))
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    let cleaned = cleaned.trim();
+
+    (!cleaned.is_empty()).then(|| clamp(cleaned.to_string(), max_length))
+}
+
+/// Checks that `value` is an absolute `http` or `https` URL of reasonable
+/// length, which rules out `javascript:`, `data:` and `vbscript:` links.
+///
+/// # Returns
+/// * `Some(String)` with the URL as serialized by the URL parser, which
+///   percent-encodes spaces, double quotes and angle brackets, and with
+///   single quotes percent-encoded too.
+/// * `None` if the URL is unusable.
+pub fn safe_url(value: &str) -> Option<String> {
+    let url = Url::parse(value.trim()).ok()?;
+    // The parser keeps `'` in paths and fragments.
+    let serialized = url.to_string().replace('\'', "%27");
+    (ALLOWED_SCHEMES.contains(&url.scheme()) && serialized.len() <= MAX_URL_LENGTH)
+        .then_some(serialized)
+}
+
+fn text(value: Option<String>, max_length: usize) -> Option<String> {
+    value.and_then(|value| clean_text(&value, max_length))
+}
+
+fn texts(values: Vec<String>, max_length: usize) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(|value| clean_text(value, max_length))
+        .collect()
+}
+
+fn url(value: Option<String>) -> Option<String> {
+    value.and_then(|value| safe_url(&value))
+}
+
+/// Resolves a URL taken from the page, which may be relative such as
+/// `/img/cover.jpg`, against the URL the page was read from, then checks it
+/// with [`safe_url`].
+fn page_url(value: Option<String>, page: Option<&Url>) -> Option<String> {
+    let value = value?;
+    match page {
+        Some(page) => safe_url(page.join(value.trim()).ok()?.as_str()),
+        None => safe_url(&value),
+    }
+}
+
+/// Cleans every string in a site extractor's `extras`.
+fn value(value: Value) -> Option<Value> {
+    match value {
+        Value::String(string) => clean_text(&string, MAX_FIELD_LENGTH).map(Value::String),
+        Value::Array(values) => Some(Value::Array(
+            values.into_iter().filter_map(self::value).collect(),
+        )),
+        Value::Object(entries) => Some(Value::Object(
+            entries
+                .into_iter()
+                .filter_map(|(key, entry)| {
+                    Some((clean_text(&key, MAX_FIELD_LENGTH)?, self::value(entry)?))
+                })
+                .collect(),
+        )),
+        value => Some(value),
+    }
+}
+
+fn open_graph(open_graph: OpenGraph) -> OpenGraph {
+    let short = |value| text(value, MAX_FIELD_LENGTH);
+    let many = |values| texts(values, MAX_FIELD_LENGTH);
+
+    OpenGraph {
+        og_type: short(open_graph.og_type),
+        site_name: short(open_graph.site_name),
+        locale: short(open_graph.locale),
+        article: open_graph.article.map(|article| OpenGraphArticle {
+            published_time: short(article.published_time),
+            modified_time: short(article.modified_time),
+            expiration_time: short(article.expiration_time),
+            authors: many(article.authors),
+            section: short(article.section),
+            tags: many(article.tags),
+        }),
+        profile: open_graph.profile.map(|profile| OpenGraphProfile {
+            first_name: short(profile.first_name),
+            last_name: short(profile.last_name),
+            username: short(profile.username),
+            gender: short(profile.gender),
+        }),
+        book: open_graph.book.map(|book| OpenGraphBook {
+            authors: many(book.authors),
+            isbn: short(book.isbn),
+            release_date: short(book.release_date),
+            tags: many(book.tags),
+        }),
+        music: open_graph.music.map(|music| OpenGraphMusic {
+            duration: music.duration,
+            album: short(music.album),
+            musicians: many(music.musicians),
+            creators: many(music.creators),
+            release_date: short(music.release_date),
+        }),
+    }
+}
+
+fn media(media: MediaInfo) -> MediaInfo {
+    MediaInfo {
+        mime_type: text(media.mime_type, MAX_FIELD_LENGTH),
+        filename: text(media.filename, MAX_FIELD_LENGTH),
+        author: text(media.author, MAX_FIELD_LENGTH),
+        codec: text(media.codec, MAX_FIELD_LENGTH),
+        ..media
+    }
+}
+
+/// Drops feeds whose own URL is unusable.
+fn feed(feed: Feed) -> Option<Feed> {
+    Some(Feed {
+        url: safe_url(&feed.url)?,
+        kind: feed.kind,
+        title: text(feed.title, MAX_TITLE_LENGTH),
+        entries: feed.entries.map(|entries| {
+            entries
+                .into_iter()
+                .map(|entry| FeedEntry {
+                    title: text(entry.title, MAX_TITLE_LENGTH),
+                    url: url(entry.url),
+                    published: text(entry.published, MAX_FIELD_LENGTH),
+                })
+                .collect()
+        }),
+    })
+}
+
+fn publication(publication: Publication, page: Option<&Url>) -> Publication {
+    let short = |value| text(value, MAX_FIELD_LENGTH);
+
+    Publication {
+        authors: texts(publication.authors, MAX_FIELD_LENGTH),
+        published: short(publication.published),
+        modified: short(publication.modified),
+        doi: short(publication.doi),
+        journal: short(publication.journal),
+        publisher: short(publication.publisher),
+        volume: short(publication.volume),
+        issue: short(publication.issue),
+        pages: short(publication.pages),
+        pdf_url: page_url(publication.pdf_url, page),
+    }
+}
+
+fn robots(robots: RobotsDecision) -> RobotsDecision {
+    RobotsDecision {
+        matched_rule: text(robots.matched_rule, MAX_FIELD_LENGTH),
+        directives: texts(robots.directives, MAX_FIELD_LENGTH),
+        suppressed: robots.suppressed,
+    }
+}
+
+fn reputation(reputation: Reputation) -> Reputation {
+    Reputation {
+        flags: reputation
+            .flags
+            .into_iter()
+            .filter_map(|flag| {
+                Some(ReputationFlag {
+                    url: safe_url(&flag.url)?,
+                    reason: clean_text(&flag.reason, MAX_FIELD_LENGTH)?,
+                    ..flag
+                })
+            })
+            .collect(),
+    }
+}
+
+fn safety(safety: Safety) -> Safety {
+    let flag = |flag: SafetyFlag| SafetyFlag {
+        signals: texts(flag.signals, MAX_FIELD_LENGTH),
+        ..flag
+    };
+
+    Safety {
+        adult: flag(safety.adult),
+        gambling: flag(safety.gambling),
+        violence: flag(safety.violence),
+    }
+}
+
+/// Makes a preview built from an untrusted page safe to return.
+///
+/// Control, bidi and zero-width characters are removed from every string,
+/// which is also cut to its field's length limit. URLs that are not
+/// `http` or `https`, such as `javascript:` images, are dropped, along with
+/// feeds and redirects whose URL is dropped. Relative image and PDF URLs are
+/// resolved against the URL the page was read from first.
+///
+/// Markup is not escaped here, see [`escape_html`].
+pub fn sanitize(metadata: MetaData) -> MetaData {
+    let page = Url::parse(metadata.final_url.as_deref().unwrap_or(&metadata.link)).ok();
+    let page = page.as_ref();
+
+    MetaData {
+        title: text(metadata.title, MAX_TITLE_LENGTH),
+        description: text(metadata.description, MAX_DESCRIPTION_LENGTH),
+        keywords: text(metadata.keywords, MAX_KEYWORDS_LENGTH),
+        image: page_url(metadata.image, page),
+        open_graph: metadata.open_graph.map(open_graph),
+        media: metadata.media.map(media),
+        extras: metadata.extras.map(|extras| {
+            extras
+                .into_iter()
+                .filter_map(|(key, entry)| Some((key, value(entry)?)))
+                .collect()
+        }),
+        word_count: metadata.word_count,
+        reading_time: metadata.reading_time,
+        language: text(metadata.language, MAX_FIELD_LENGTH),
+        feeds: metadata
+            .feeds
+            .map(|feeds| feeds.into_iter().filter_map(feed).collect()),
+        publication: metadata.publication.map(|value| publication(value, page)),
+        robots: metadata.robots.map(robots),
+        final_url: url(metadata.final_url),
+        redirects: metadata.redirects.map(|redirects| {
+            redirects
+                .into_iter()
+                .filter_map(|redirect| {
+                    Some(Redirect {
+                        url: safe_url(&redirect.url)?,
+                        ..redirect
+                    })
+                })
+                .collect()
+        }),
+        reputation: metadata.reputation.map(reputation),
+        safety: metadata.safety.map(safety),
+        fetched_with: metadata.fetched_with,
+        link: metadata.link,
+    }
+}
+
+/// Escapes `&`, `<`, `>`, `"` and `'` so that `value` can be placed in HTML
+/// text or a quoted attribute as is.
+pub fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The fields of a response that hold URLs, as the object keys leading to
+/// them; arrays are passed through. They are left unescaped, see
+/// [`escape_html`].
+const URL_FIELDS: [&[&str]; 7] = [
+    &["image"],
+    &["final_url"],
+    &["feeds", "url"],
+    &["feeds", "entries", "url"],
+    &["publication", "pdf_url"],
+    &["redirects", "url"],
+    &["reputation", "flags", "url"],
+];
+
+fn is_url_field(path: &[String]) -> bool {
+    URL_FIELDS
+        .iter()
+        .any(|field| field.iter().copied().eq(path.iter().map(String::as_str)))
+}
+
+/// Escapes every string in `value` but the URL fields, `path` being the
+/// object keys leading to it.
+fn escape_value(value: Value, path: &mut Vec<String>) -> Value {
+    match value {
+        Value::String(string) if is_url_field(path) => Value::String(string),
+        Value::String(string) => Value::String(escape_text(&string)),
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| escape_value(value, path))
+                .collect(),
+        ),
+        Value::Object(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, entry)| {
+                    path.push(key);
+                    let entry = escape_value(entry, path);
+                    let key = path.pop().unwrap_or_default();
+                    (escape_text(&key), entry)
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// HTML-escapes every string in a response but its URLs, see
+/// [`escape_text`], so clients can interpolate the values into HTML without
+/// escaping them.
+///
+/// URLs are left as they are so clients can use them as links: escaping
+/// would turn `&` into `&amp;` in query strings. [`sanitize`] already keeps
+/// only `http` and `https` URLs, percent-encoded so they cannot break out of
+/// a quoted attribute.
+///
+/// Applied to responses only, so cached and stored previews keep plain text
+/// and are never escaped twice.
+pub fn escape_html(metadata: MetaDataResponse) -> MetaDataResponse {
+    let escaped = serde_json::to_value(&metadata)
+        .map(|value| escape_value(value, &mut Vec::new()))
+        .and_then(serde_json::from_value);
+
+    match escaped {
+        Ok(escaped) => escaped,
+        Err(e) => {
+            // Only reachable if the model stops round-tripping through JSON;
+            // returning nothing is safer than returning unescaped values.
+            eprintln!("Failed to escape preview: {}", e);
+            MetaDataResponse::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preview::model::FeedKind;
+
+    fn page(image: &str, final_url: Option<&str>) -> MetaData {
+        MetaDataResponse {
+            image: Some(image.to_string()),
+            final_url: final_url.map(String::from),
+            publication: Some(Publication {
+                pdf_url: Some("paper.pdf".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .into_metadata("https://example.com/start".to_string())
+    }
+
+    #[test]
+    fn resolves_relative_image_urls_against_the_page() {
+        let sanitized = sanitize(page(
+            " /img/cover.jpg ",
+            Some("https://www.example.com/articles/1"),
+        ));
+        assert_eq!(
+            sanitized.image.as_deref(),
+            Some("https://www.example.com/img/cover.jpg")
+        );
+        assert_eq!(
+            sanitized.publication.unwrap().pdf_url.as_deref(),
+            Some("https://www.example.com/articles/paper.pdf")
+        );
+
+        // Without a final URL the requested link is the base.
+        let sanitized = sanitize(page("cover.jpg", None));
+        assert_eq!(
+            sanitized.image.as_deref(),
+            Some("https://example.com/cover.jpg")
+        );
+
+        let sanitized = sanitize(page(
+            "//cdn.example.net/a.png",
+            Some("https://example.com/"),
+        ));
+        assert_eq!(
+            sanitized.image.as_deref(),
+            Some("https://cdn.example.net/a.png")
+        );
+    }
+
+    #[test]
+    fn clean_text_strips_control_and_bidi_characters() {
+        assert_eq!(
+            clean_text("invoice\u{202E}fdp.exe", MAX_TITLE_LENGTH).as_deref(),
+            Some("invoicefdp.exe")
+        );
+        assert_eq!(
+            clean_text("\u{FEFF}zero\u{200B}width\u{2066}isolate\u{2069}", 100).as_deref(),
+            Some("zerowidthisolate")
+        );
+        assert_eq!(
+            clean_text("bell\u{7}and\u{0}null\u{1B}[31m", 100).as_deref(),
+            Some("bellandnull[31m")
+        );
+        assert_eq!(
+            clean_text(" tab\tnew\nline\r\n", 100).as_deref(),
+            Some("tab new line")
+        );
+        // The zero-width joiner holds emoji sequences together.
+        assert_eq!(
+            clean_text("👩\u{200D}💻", 100).as_deref(),
+            Some("👩\u{200D}💻")
+        );
+        assert_eq!(clean_text("\u{202E}\u{200B} \n", 100), None);
+    }
+
+    #[test]
+    fn drops_urls_that_are_not_http() {
+        for image in [
+            "javascript:alert(1)",
+            " JavaScript:alert(document.cookie)",
+            "data:image/svg+xml;base64,PHN2Zz48L3N2Zz4=",
+            "vbscript:msgbox(1)",
+            "file:///etc/passwd",
+        ] {
+            assert_eq!(sanitize(page(image, None)).image, None, "{}", image);
+        }
+
+        let too_long = format!("https://example.com/{}", "a".repeat(MAX_URL_LENGTH));
+        assert_eq!(safe_url(&too_long), None);
+
+        let mut metadata = page("https://example.com/a.png", None);
+        metadata.feeds = Some(vec![
+            Feed {
+                url: "javascript:alert(1)".to_string(),
+                kind: FeedKind::Rss,
+                title: None,
+                entries: None,
+            },
+            Feed {
+                url: "https://example.com/feed".to_string(),
+                kind: FeedKind::Atom,
+                title: None,
+                entries: Some(vec![FeedEntry {
+                    title: Some("Entry".to_string()),
+                    url: Some("data:text/html,<script>alert(1)</script>".to_string()),
+                    published: None,
+                }]),
+            },
+        ]);
+        let feeds = sanitize(metadata).feeds.unwrap();
+        assert_eq!(feeds.len(), 1);
+        assert_eq!(feeds[0].url, "https://example.com/feed");
+        assert_eq!(feeds[0].entries.as_ref().unwrap()[0].url, None);
+    }
+
+    #[test]
+    fn clamps_long_fields() {
+        let mut metadata = page("https://example.com/a.png", None);
+        metadata.title = Some("t".repeat(MAX_TITLE_LENGTH * 2));
+        metadata.description = Some("d ".repeat(MAX_DESCRIPTION_LENGTH));
+        metadata.keywords = Some("k".repeat(MAX_KEYWORDS_LENGTH + 1));
+        metadata.language = Some("en".to_string());
+        let sanitized = sanitize(metadata);
+
+        let title = sanitized.title.unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_LENGTH);
+        assert!(title.ends_with('…'));
+        let description = sanitized.description.unwrap();
+        assert!(description.chars().count() <= MAX_DESCRIPTION_LENGTH);
+        assert!(description.ends_with("d…"));
+        assert_eq!(
+            sanitized.keywords.unwrap().chars().count(),
+            MAX_KEYWORDS_LENGTH
+        );
+        assert_eq!(sanitized.language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn escape_html_leaves_urls_unescaped() {
+        let escaped = escape_html(MetaDataResponse {
+            title: Some("Fish & <Chips>".to_string()),
+            image: safe_url("https://example.com/it's.png?w=1&h=2"),
+            final_url: Some("https://example.com/?a=1&b=2".to_string()),
+            feeds: Some(vec![Feed {
+                url: "https://example.com/feed?a=1&b=2".to_string(),
+                kind: FeedKind::Rss,
+                title: Some("A & B".to_string()),
+                entries: Some(vec![FeedEntry {
+                    title: None,
+                    url: Some("https://example.com/1?a=1&b=2".to_string()),
+                    published: None,
+                }]),
+            }]),
+            extras: Some(
+                [(
+                    "url".to_string(),
+                    Value::String("<script>&</script>".to_string()),
+                )]
+                .into(),
+            ),
+            ..Default::default()
+        });
+
+        assert_eq!(escaped.title.as_deref(), Some("Fish &amp; &lt;Chips&gt;"));
+        assert_eq!(
+            escaped.image.as_deref(),
+            Some("https://example.com/it%27s.png?w=1&h=2")
+        );
+        assert_eq!(
+            escaped.final_url.as_deref(),
+            Some("https://example.com/?a=1&b=2")
+        );
+        let feed = &escaped.feeds.unwrap()[0];
+        assert_eq!(feed.url, "https://example.com/feed?a=1&b=2");
+        assert_eq!(feed.title.as_deref(), Some("A &amp; B"));
+        assert_eq!(
+            feed.entries.as_ref().unwrap()[0].url.as_deref(),
+            Some("https://example.com/1?a=1&b=2")
+        );
+        assert_eq!(
+            escaped.extras.unwrap()["url"],
+            Value::String("&lt;script&gt;&amp;&lt;/script&gt;".to_string())
+        );
+    }
+}
@@ -1,76 +1,41 @@
-use headless_chrome::Browser;
+use reqwest::header::HeaderMap;
 use scraper::{Html as ScraperHTML, Selector};
-use thiserror::Error;
 
-use crate::config::constants::Settings;
+use super::{
+    extractor::ExtractorRegistry,
+    feed::{extract_feed_metadata, parse_feed},
+    fetcher::{FetchError, FetchOptions, FetcherChain},
+    language::{content_language, declared_language, detect_language},
+    media::{extract_media_metadata, ContentKind},
+    model::{Feed, FetchStrategy, FetchedWith, MetaData, MetaDataResponse, Publication, Redirect},
+    opengraph::extract_open_graph,
+    readability::extract_article,
+    robots::apply_directives,
+    safety::SafetyClassifier,
+    strategy::needs_browser,
+    structured::extract_structured,
+    text::{normalize_text, MAX_DESCRIPTION_LENGTH, MAX_KEYWORDS_LENGTH, MAX_TITLE_LENGTH},
+};
 
-use super::model::{MetaData, MetaDataResponse};
-
-#[derive(Error, Debug)]
-pub enum FetchError {
-    #[error("Request error: {0}")]
-    RequestError(#[from] reqwest::Error),
-    #[error("Browser error: {0}")]
-    BrowserError(String),
-    #[allow(dead_code)]
-    #[error("Unknown error")]
-    Unknown,
-}
-
-/// Fetches the HTML content of a URL using reqwest.
+/// Extracts metadata from a parsed HTML document using the generic
+/// OpenGraph and `<meta>` tags.
 ///
-/// # Arguments
-/// * `url` - The URL to fetch.
+/// Pages without OpenGraph tags fall back to `citation_*`, Dublin Core,
+/// microdata and RDFa markup, see [`extract_structured`], which also fills
+/// in `publication`. When the page has no description at all, an excerpt of
+/// the main article text is used instead, see [`extract_article`]. The
+/// article's word count and reading time are reported either way.
 ///
-/// # Returns
-/// * `Ok(String)` containing the HTML content if successful.
-/// * `Err(FetchError)` if an error occurs.
-pub async fn fetch_with_request(url: &str) -> Result<String, FetchError> {
-    let response = reqwest::get(url).await?;
-    let content = response.text().await?;
-    Ok(content)
-}
-
-/// Fetches the HTML content of a URL using a headless browser.
+/// Every extracted string is normalized to plain text, see [`normalize_text`].
 ///
 /// # Arguments
-/// * `url` - The URL to fetch.
-///
-/// # Returns
-/// * `Ok(String)` containing the HTML content if successful.
-/// * `Err(FetchError)` if an error occurs.
-pub async fn fetch_with_headless_browser(url: &str) -> Result<String, FetchError> {
-    let browser = Browser::default()
-        .map_err(|e| FetchError::BrowserError(format!("Failed to initialize browser: {}", e)))?;
-
-    let tab = browser
-        .new_tab()
-        .map_err(|e| FetchError::BrowserError(format!("Failed to create new tab: {}", e)))?;
-
-    tab.navigate_to(url)
-        .map_err(|e| FetchError::BrowserError(format!("Failed to navigate to {}: {}", url, e)))?;
-
-    tab.wait_for_element("html")
-        .map_err(|e| FetchError::BrowserError(format!("Failed to wait for HTML element: {}", e)))?;
-
-    let html = tab
-        .get_content()
-        .map_err(|e| FetchError::BrowserError(format!("Failed to get page content: {}", e)))?;
-
-    Ok(html)
-}
-
-/// Extracts metadata from the given HTML content.
-///
-/// # Arguments
-/// * `html` - The HTML content to extract metadata from.
+/// * `document` - The parsed HTML document to extract metadata from.
+/// * `excerpt_length` - The maximum length of the fallback excerpt.
 ///
 /// # Returns
 /// * `MetaDataResponse` containing the extracted metadata.
-pub fn extract_metadata(html: &str) -> MetaDataResponse {
-    let document = ScraperHTML::parse_document(html);
-
-    let extract_meta_content = |property: &str| {
+pub fn extract_metadata(document: &ScraperHTML, excerpt_length: usize) -> MetaDataResponse {
+    let extract_meta_attribute = |property: &str| {
         let selector = Selector::parse(&format!(
             r#"meta[property="{}"], meta[name="{}"]"#,
             property, property
@@ -80,78 +45,435 @@ pub fn extract_metadata(html: &str) -> MetaDataResponse {
             .select(&selector)
             .next()
             .and_then(|el| el.value().attr("content"))
-            .map(String::from)
     };
 
-    let title = extract_meta_content("og:title").or_else(|| {
-        let title_selector = Selector::parse("title").unwrap();
-        document
-            .select(&title_selector)
-            .next()
-            .map(|e| e.inner_html())
-    });
+    let extract_meta_content = |property: &str, max_length: usize| {
+        extract_meta_attribute(property).and_then(|content| normalize_text(content, max_length))
+    };
+
+    let structured = extract_structured(document);
+
+    let title = extract_meta_content("og:title", MAX_TITLE_LENGTH)
+        .or(structured.title)
+        .or_else(|| {
+            let title_selector = Selector::parse("title").unwrap();
+            document
+                .select(&title_selector)
+                .next()
+                .and_then(|e| normalize_text(&e.text().collect::<String>(), MAX_TITLE_LENGTH))
+        });
 
-    let description =
-        extract_meta_content("og:description").or_else(|| extract_meta_content("description"));
+    let article = extract_article(document);
 
-    let keywords = extract_meta_content("keywords");
+    let description = extract_meta_content("og:description", MAX_DESCRIPTION_LENGTH)
+        .or_else(|| extract_meta_content("description", MAX_DESCRIPTION_LENGTH))
+        .or(structured.description)
+        .or_else(|| {
+            article
+                .as_ref()
+                .and_then(|article| article.excerpt(excerpt_length))
+        });
 
-    let image = extract_meta_content("og:image");
+    let keywords = extract_meta_content("keywords", MAX_KEYWORDS_LENGTH);
+
+    // URLs are only trimmed; entity decoding would corrupt query strings.
+    let image = extract_meta_attribute("og:image")
+        .map(str::trim)
+        .filter(|image| !image.is_empty())
+        .map(String::from)
+        .or(structured.image);
+
+    let open_graph = extract_open_graph(document);
 
     MetaDataResponse {
         title,
         description,
         keywords,
         image,
+        open_graph,
+        media: None,
+        extras: None,
+        word_count: article.as_ref().map(|article| article.word_count),
+        reading_time: article.as_ref().map(|article| article.reading_time()),
+        language: declared_language(document),
+        feeds: None,
+        publication: (structured.publication != Publication::default())
+            .then_some(structured.publication),
+        robots: None,
+        final_url: None,
+        redirects: None,
+        reputation: None,
+        safety: None,
+        fetched_with: None,
     }
 }
 
-/// Fetches metadata from a URL, using a headless browser if necessary.
+/// Number of discovered feeds fetched by [`fetch_feed_entries`].
+const MAX_FETCHED_FEEDS: usize = 3;
+
+/// Fetches metadata from a URL by trying the fetchers in the chain in order.
+///
+/// Links to feeds, images, PDFs, audio, video and other files are described
+/// from the file itself instead of being parsed as HTML. In `Auto` mode an HTML
+/// page is only passed on to the next fetcher when [`needs_browser`] says so;
+/// if every later fetcher fails, that earlier preview is returned instead.
+///
+/// With `ROBOTS_COMPLIANCE` on, the URL and every redirect target are checked
+/// against their origin's robots.txt before they are requested, and fields
+/// the page's robots directives forbid are removed from the preview, see
+/// [`apply_directives`]. The URL and every redirect target are also checked
+/// against the blocklists, see `REPUTATION_ACTION`.
+///
+/// Every preview is classified for adult, gambling and violent content
+/// before any field is removed, see [`SafetyClassifier`].
 ///
 /// # Arguments
 /// * `url` - The URL to fetch metadata from.
+/// * `strategy` - Which kinds of fetcher to use.
+/// * `options` - Headers, language and wait strategy passed to every fetcher.
+/// * `fetchers` - The configured fetcher chain.
+/// * `extractors` - The extractors run over HTML pages.
+/// * `safety` - Flags unsafe content in the previews.
 ///
 /// # Returns
 /// * `Ok(MetaDataResponse)` containing the extracted metadata if successful.
-/// * `Err(FetchError)` if an error occurs.
-pub async fn fetch_metadata(url: &str) -> Result<MetaData, FetchError> {
-    let settings = Settings::from_env();
-
-    if settings.use_headless_browser_only {
-        let html = fetch_with_headless_browser(url).await?;
-        let metadata = extract_metadata(&html);
-        Ok(MetaData {
-            link: url.to_string(),
-            title: metadata.title,
-            description: metadata.description,
-            keywords: metadata.keywords,
-            image: metadata.image,
-        })
-    } else {
-        match fetch_with_request(url).await {
-            Ok(html) => {
-                let metadata = extract_metadata(&html);
-                if metadata.title.is_some() && metadata.description.is_some() {
-                    return Ok(MetaData {
-                        link: url.to_string(),
-                        title: metadata.title,
-                        description: metadata.description,
-                        keywords: metadata.keywords,
-                        image: metadata.image,
-                    });
+/// * `Err(FetchError::RobotsDisallowed)` if robots.txt disallows the URL.
+/// * `Err(FetchError::Unsafe)` if a blocklisted URL is refused.
+/// * `Err(FetchError)` with the last error if every fetcher fails.
+pub async fn fetch_metadata(
+    url: &str,
+    strategy: FetchStrategy,
+    options: &FetchOptions,
+    fetchers: &FetcherChain,
+    extractors: &ExtractorRegistry,
+    safety: &SafetyClassifier,
+) -> Result<MetaData, FetchError> {
+    let strategy = fetchers.resolve(strategy);
+    let candidates = fetchers.for_strategy(strategy);
+    let mut fallback = None;
+    let mut last_error = FetchError::NoFetcher(strategy.as_str());
+
+    let robots = match fetchers.robots() {
+        Some(checker) => Some((checker, checker.check(url).await?)),
+        None => None,
+    };
+    let comply = |metadata, final_url: &str, html: Option<&str>, headers: &HeaderMap| {
+        let metadata = safety.apply(metadata, &[url, final_url], html, headers);
+        match &robots {
+            Some((checker, decision)) => apply_directives(
+                metadata,
+                decision.clone(),
+                checker.directives(html, headers),
+            ),
+            None => metadata,
+        }
+    };
+
+    for (index, fetcher) in candidates.iter().enumerate() {
+        let mut response = match fetchers.fetch(*fetcher, url, options).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Failed to fetch with {}: {}", fetcher.name(), e);
+                last_error = e;
+                continue;
+            }
+        };
+
+        let redirects = std::mem::take(&mut response.redirects);
+        let final_url = response.url.clone();
+        let fetched = |metadata| {
+            fetched_with(
+                metadata,
+                url,
+                fetcher.fetched_with(),
+                final_url.clone(),
+                redirects.clone(),
+            )
+        };
+
+        match response.kind {
+            ContentKind::Feed(kind) => {
+                let metadata = with_language(
+                    extract_feed_metadata(kind, &response.url, &response.body),
+                    &response.headers,
+                );
+                let metadata = comply(metadata, &response.url, None, &response.headers);
+                return Ok(fetched(metadata));
+            }
+            ContentKind::Media(kind) => {
+                let metadata = extract_media_metadata(
+                    kind,
+                    response.mime_type,
+                    &response.url,
+                    &response.headers,
+                    &response.body,
+                    response.truncated,
+                );
+                let metadata = comply(metadata, &response.url, None, &response.headers);
+                return Ok(fetched(metadata));
+            }
+            ContentKind::Html => {
+                let html = response.html();
+                let metadata =
+                    with_language(extractors.extract(&response.url, &html), &response.headers);
+
+                let is_final = strategy != FetchStrategy::Auto
+                    || index + 1 == candidates.len()
+                    || fetcher.fetched_with() == FetchedWith::Browser
+                    || !needs_browser(&metadata, &html);
+
+                let metadata = comply(metadata, &response.url, Some(&html), &response.headers);
+                let metadata = fetched(metadata);
+                if is_final {
+                    return Ok(metadata);
                 }
+                fallback = Some(metadata);
             }
-            Err(e) => eprintln!("Failed to fetch with request: {}", e),
         }
+    }
+
+    fallback.ok_or(last_error)
+}
+
+/// Fetches the feeds discovered on a page and fills in their title and
+/// latest entries.
+///
+/// Up to [`MAX_FETCHED_FEEDS`] feeds are fetched, with the static fetchers
+/// only, each checked against robots.txt and the blocklists like a page. A
+/// feed that cannot be fetched or parsed is returned as discovered.
+///
+/// # Arguments
+/// * `metadata` - The page preview, with `feeds` from discovery.
+/// * `language` - Sent as `Accept-Language`, as for the page itself.
+/// * `fetchers` - The configured fetcher chain.
+pub async fn fetch_feed_entries(
+    mut metadata: MetaData,
+    language: Option<String>,
+    fetchers: &FetcherChain,
+) -> MetaData {
+    let Some(feeds) = metadata.feeds.as_mut() else {
+        return metadata;
+    };
+    let options = FetchOptions {
+        language,
+        ..Default::default()
+    };
+    let candidates = fetchers.for_strategy(FetchStrategy::Static);
+
+    for feed in feeds
+        .iter_mut()
+        .filter(|feed| feed.entries.is_none())
+        .take(MAX_FETCHED_FEEDS)
+    {
+        for fetcher in &candidates {
+            match fetchers.fetch(*fetcher, &feed.url, &options).await {
+                Ok(response) => {
+                    match response.kind {
+                        ContentKind::Feed(_) => {
+                            if let Some(fetched) = parse_feed(&response.url, &response.body) {
+                                *feed = Feed {
+                                    url: feed.url.clone(),
+                                    title: fetched.title.or(feed.title.take()),
+                                    ..fetched
+                                };
+                            }
+                        }
+                        _ => eprintln!("Discovered feed {} is not a feed", feed.url),
+                    }
+                    break;
+                }
+                Err(e) => eprintln!(
+                    "Failed to fetch feed {} with {}: {}",
+                    feed.url,
+                    fetcher.name(),
+                    e
+                ),
+            }
+        }
+    }
+
+    metadata
+}
+
+/// Fills in the language of a page that does not declare one from the
+/// `Content-Language` header, or else by detecting it from the title and
+/// description.
+fn with_language(metadata: MetaDataResponse, headers: &HeaderMap) -> MetaDataResponse {
+    let language = metadata
+        .language
+        .clone()
+        .or_else(|| content_language(headers))
+        .or_else(|| {
+            let text = [&metadata.title, &metadata.description]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ");
+            detect_language(&text)
+        });
+
+    MetaDataResponse {
+        language,
+        ..metadata
+    }
+}
+
+fn fetched_with(
+    metadata: MetaDataResponse,
+    url: &str,
+    fetched_with: FetchedWith,
+    final_url: String,
+    redirects: Vec<Redirect>,
+) -> MetaData {
+    MetaDataResponse {
+        final_url: Some(final_url),
+        redirects: (!redirects.is_empty()).then_some(redirects),
+        fetched_with: Some(fetched_with),
+        ..metadata
+    }
+    .into_metadata(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use reqwest::Url;
+
+    use super::*;
+    use crate::preview::{
+        extractor::Extractor,
+        fetcher::{fixture::FixtureFetcher, Fetcher},
+        model::{FeedKind, MediaKind},
+        pattern::UrlPattern,
+    };
+
+    /// Runs the generic extraction, without the settings the real extractor needs.
+    struct PageExtractor;
+
+    impl Extractor for PageExtractor {
+        fn priority(&self) -> i32 {
+            0
+        }
+
+        fn patterns(&self) -> Vec<UrlPattern> {
+            Vec::new()
+        }
+
+        fn matches(&self, _url: &Url) -> bool {
+            true
+        }
+
+        fn extract(&self, _url: &Url, document: &ScraperHTML) -> MetaDataResponse {
+            extract_metadata(document, 200)
+        }
+    }
+
+    fn fixtures(dir: &str) -> Box<dyn Fetcher> {
+        Box::new(FixtureFetcher::new(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(dir),
+        ))
+    }
+
+    async fn fetch(url: &str, fetchers: Vec<Box<dyn Fetcher>>) -> Result<MetaData, FetchError> {
+        fetch_metadata(
+            url,
+            FetchStrategy::Auto,
+            &FetchOptions::default(),
+            &FetcherChain::new(fetchers),
+            &ExtractorRegistry::new(vec![Box::new(PageExtractor)]),
+            &SafetyClassifier::default(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn extracts_html_pages() {
+        let url = "https://example.com/article.html";
+        let metadata = fetch(url, vec![fixtures("fixtures")]).await.unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("An article about fixtures"));
+        assert_eq!(
+            metadata.description.as_deref(),
+            Some("Fixtures let previews be tested offline.")
+        );
+        assert_eq!(
+            metadata.image.as_deref(),
+            Some("https://example.com/pixel.png?w=1&h=1")
+        );
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+        assert_eq!(metadata.final_url.as_deref(), Some(url));
+        assert_eq!(metadata.fetched_with, Some(FetchedWith::Fixture));
+        assert_eq!(metadata.link, url);
+    }
+
+    #[tokio::test]
+    async fn describes_media_from_the_file() {
+        let url = "https://example.com/pixel.png";
+        let metadata = fetch(url, vec![fixtures("fixtures")]).await.unwrap();
+        let media = metadata.media.unwrap();
+
+        assert_eq!(media.kind, MediaKind::Image);
+        assert_eq!(media.mime_type.as_deref(), Some("image/png"));
+        assert_eq!((media.width, media.height), (Some(2), Some(1)));
+        assert_eq!(media.filename.as_deref(), Some("pixel.png"));
+        assert_eq!(metadata.image.as_deref(), Some(url));
+    }
+
+    #[tokio::test]
+    async fn reads_feeds() {
+        let url = "https://example.com/feed.xml";
+        let metadata = fetch(url, vec![fixtures("fixtures")]).await.unwrap();
+        let feeds = metadata.feeds.unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Example feed"));
+        assert_eq!(
+            metadata.description.as_deref(),
+            Some("The latest posts from example.com.")
+        );
+        assert_eq!(feeds.len(), 1);
+        assert_eq!(feeds[0].kind, FeedKind::Rss);
+        let entries = feeds[0].entries.as_ref().unwrap();
+        assert_eq!(entries[0].title.as_deref(), Some("First post"));
+    }
+
+    #[tokio::test]
+    async fn auto_passes_spa_shells_to_the_next_fetcher() {
+        let url = "https://example.com/app.html";
+        let fetchers = vec![fixtures("fixtures"), fixtures("fixtures/rendered")];
+        let metadata = fetch(url, fetchers).await.unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Rendered app"));
+        assert_eq!(
+            metadata.description.as_deref(),
+            Some("Rendered by the browser.")
+        );
+    }
+
+    #[tokio::test]
+    async fn auto_keeps_the_static_preview_if_the_next_fetcher_fails() {
+        let url = "https://example.com/app.html";
+        let fetchers = vec![fixtures("fixtures"), fixtures("fixtures/missing")];
+        let metadata = fetch(url, fetchers).await.unwrap();
+
+        assert_eq!(metadata.title, None);
+        assert_eq!(metadata.final_url.as_deref(), Some(url));
+    }
+
+    #[tokio::test]
+    async fn auto_keeps_complete_pages() {
+        let url = "https://example.com/article.html";
+        let fetchers = vec![fixtures("fixtures"), fixtures("fixtures/missing")];
+        let metadata = fetch(url, fetchers).await.unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("An article about fixtures"));
+    }
+
+    #[tokio::test]
+    async fn returns_the_last_error_if_every_fetcher_fails() {
+        let result = fetch("https://example.com/none.html", vec![fixtures("fixtures")]).await;
 
-        let html = fetch_with_headless_browser(url).await?;
-        let metadata = extract_metadata(&html);
-        Ok(MetaData {
-            link: url.to_string(),
-            title: metadata.title,
-            description: metadata.description,
-            keywords: metadata.keywords,
-            image: metadata.image,
-        })
+        assert!(matches!(result, Err(FetchError::FixtureError(_))));
     }
 }
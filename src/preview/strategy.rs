@@ -0,0 +1,109 @@
+use scraper::{node::Node, Html as ScraperHTML, Selector};
+
+use super::model::MetaDataResponse;
+
+/// Pages with less visible body text than this are considered thin.
+const THIN_BODY_CHARS: usize = 200;
+
+/// Elements client-side frameworks commonly mount into.
+const MOUNT_POINT_SELECTOR: &str =
+    "#root, #app, #__next, #__nuxt, #___gatsby, #svelte, [data-reactroot], app-root";
+
+/// Script contents and attributes left behind by client-side frameworks.
+const FRAMEWORK_MARKERS: [&str; 7] = [
+    "__NEXT_DATA__",
+    "__NUXT__",
+    "__INITIAL_STATE__",
+    "__APOLLO_STATE__",
+    "ng-version",
+    "webpackJsonp",
+    "data-reactroot",
+];
+
+/// Decides whether a statically fetched page should be re-fetched with the
+/// headless browser.
+///
+/// A page that already has a title and description is kept. Otherwise the
+/// browser is only used when the page looks like an SPA shell, or when it has
+/// no title at all; pages that simply lack a description are kept as-is.
+///
+/// # Arguments
+/// * `metadata` - The metadata extracted from the static HTML.
+/// * `html` - The static HTML.
+///
+/// # Returns
+/// * `true` if the headless browser is likely to produce a better preview.
+pub fn needs_browser(metadata: &MetaDataResponse, html: &str) -> bool {
+    if metadata.title.is_some() && metadata.description.is_some() {
+        return false;
+    }
+
+    metadata.title.is_none() || looks_like_spa_shell(html)
+}
+
+/// Detects an SPA shell: a page with little server-rendered text whose content
+/// is rendered by JavaScript into an empty mount point.
+///
+/// # Arguments
+/// * `html` - The HTML content to inspect.
+///
+/// # Returns
+/// * `true` if the page has a thin body and an empty mount point, a
+///   `<noscript>` warning or a framework marker.
+pub fn looks_like_spa_shell(html: &str) -> bool {
+    let document = ScraperHTML::parse_document(html);
+
+    if visible_text_length(&document) >= THIN_BODY_CHARS {
+        return false;
+    }
+
+    has_empty_mount_point(&document)
+        || has_noscript_warning(&document)
+        || FRAMEWORK_MARKERS.iter().any(|marker| html.contains(marker))
+}
+
+/// Counts the non-whitespace characters rendered in `<body>`, ignoring
+/// scripts, styles and templates.
+fn visible_text_length(document: &ScraperHTML) -> usize {
+    let body_selector = Selector::parse("body").unwrap();
+    let Some(body) = document.select(&body_selector).next() else {
+        return 0;
+    };
+
+    body.descendants()
+        .filter_map(|node| {
+            let Node::Text(text) = node.value() else {
+                return None;
+            };
+            let parent = node.parent()?.value().as_element()?;
+            if matches!(parent.name(), "script" | "style" | "noscript" | "template") {
+                return None;
+            }
+            Some(text.chars().filter(|c| !c.is_whitespace()).count())
+        })
+        .sum()
+}
+
+fn has_empty_mount_point(document: &ScraperHTML) -> bool {
+    let selector = Selector::parse(MOUNT_POINT_SELECTOR).unwrap();
+
+    document.select(&selector).any(|element| {
+        element.children().all(|child| match child.value() {
+            Node::Text(text) => text.trim().is_empty(),
+            Node::Comment(_) => true,
+            _ => false,
+        })
+    })
+}
+
+fn has_noscript_warning(document: &ScraperHTML) -> bool {
+    let selector = Selector::parse("noscript").unwrap();
+
+    document.select(&selector).any(|element| {
+        element
+            .text()
+            .collect::<String>()
+            .to_ascii_lowercase()
+            .contains("javascript")
+    })
+}
@@ -0,0 +1,431 @@
+use scraper::{ElementRef, Html as ScraperHTML, Selector};
+
+use super::{
+    model::Publication,
+    text::{normalize_text, MAX_DESCRIPTION_LENGTH, MAX_FIELD_LENGTH, MAX_TITLE_LENGTH},
+};
+
+/// Microdata and RDFa types whose properties describe the page itself.
+const ARTICLE_TYPES: [&str; 13] = [
+    "article",
+    "blogposting",
+    "book",
+    "chapter",
+    "creativework",
+    "dataset",
+    "medicalscholarlyarticle",
+    "newsarticle",
+    "report",
+    "scholarlyarticle",
+    "techarticle",
+    "thesis",
+    "webpage",
+];
+
+/// RDFa prefixes read outside of a typed item. OpenGraph and other
+/// `property` vocabularies are handled elsewhere.
+const RDFA_PREFIXES: [&str; 3] = ["schema", "dc", "dcterms"];
+
+/// How an item vocabulary marks up properties and nested items.
+struct Syntax {
+    /// Attribute naming the properties of an element.
+    property: &'static str,
+    /// Attribute that opens a new item.
+    scope: &'static str,
+    /// Attribute holding the item's type.
+    item_type: &'static str,
+}
+
+const MICRODATA: Syntax = Syntax {
+    property: "itemprop",
+    scope: "itemscope",
+    item_type: "itemtype",
+};
+
+const RDFA: Syntax = Syntax {
+    property: "property",
+    scope: "typeof",
+    item_type: "typeof",
+};
+
+/// Core fields and bibliographic details read from one vocabulary.
+#[derive(Debug, Default)]
+pub struct Structured {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub publication: Publication,
+}
+
+impl Structured {
+    fn or(self, fallback: Structured) -> Structured {
+        Structured {
+            title: self.title.or(fallback.title),
+            description: self.description.or(fallback.description),
+            image: self.image.or(fallback.image),
+            publication: self.publication.or(fallback.publication),
+        }
+    }
+}
+
+/// Reads `citation_*` (Highwire Press) and Dublin Core meta tags, microdata
+/// and RDFa, in that order of precedence.
+///
+/// # Arguments
+/// * `document` - The parsed HTML document.
+///
+/// # Returns
+/// * `Structured` with whatever the page describes in these vocabularies.
+pub fn extract_structured(document: &ScraperHTML) -> Structured {
+    let meta = meta_tags(document);
+
+    citation(&meta)
+        .or(dublin_core(&meta))
+        .or(from_properties(&item_properties(document, &MICRODATA)))
+        .or(from_properties(&item_properties(document, &RDFA)))
+}
+
+/// All `<meta name>` tags, with lowercase names.
+fn meta_tags(document: &ScraperHTML) -> Vec<(String, String)> {
+    let selector = Selector::parse("meta[name][content]").unwrap();
+
+    document
+        .select(&selector)
+        .filter_map(|meta| {
+            let name = meta.value().attr("name")?.trim().to_ascii_lowercase();
+            let content = meta.value().attr("content")?.trim();
+            (!content.is_empty()).then(|| (name, content.to_string()))
+        })
+        .collect()
+}
+
+fn meta_values<'a>(meta: &'a [(String, String)], names: &[&str]) -> Vec<&'a str> {
+    names
+        .iter()
+        .flat_map(|name| {
+            meta.iter()
+                .filter(move |(meta_name, _)| meta_name == name)
+                .map(|(_, content)| content.as_str())
+        })
+        .collect()
+}
+
+fn meta_value(meta: &[(String, String)], names: &[&str], max_length: usize) -> Option<String> {
+    meta_values(meta, names)
+        .into_iter()
+        .find_map(|value| normalize_text(value, max_length))
+}
+
+/// The `citation_*` tags Google Scholar indexes.
+fn citation(meta: &[(String, String)]) -> Structured {
+    let mut authors = meta_values(meta, &["citation_author"])
+        .into_iter()
+        .filter_map(|author| normalize_text(author, MAX_FIELD_LENGTH))
+        .collect::<Vec<_>>();
+    if authors.is_empty() {
+        // The older form lists every author in one tag.
+        authors = meta_values(meta, &["citation_authors"])
+            .into_iter()
+            .flat_map(|authors| authors.split(';'))
+            .filter_map(|author| normalize_text(author, MAX_FIELD_LENGTH))
+            .collect();
+    }
+
+    let first_page = meta_value(meta, &["citation_firstpage"], MAX_FIELD_LENGTH);
+    let last_page = meta_value(meta, &["citation_lastpage"], MAX_FIELD_LENGTH);
+
+    Structured {
+        title: meta_value(meta, &["citation_title"], MAX_TITLE_LENGTH),
+        description: meta_value(meta, &["citation_abstract"], MAX_DESCRIPTION_LENGTH),
+        image: None,
+        publication: Publication {
+            authors,
+            published: meta_value(
+                meta,
+                &[
+                    "citation_publication_date",
+                    "citation_date",
+                    "citation_cover_date",
+                    "citation_online_date",
+                ],
+                MAX_FIELD_LENGTH,
+            )
+            .map(|date| normalize_date(&date)),
+            modified: None,
+            doi: meta_values(meta, &["citation_doi"])
+                .into_iter()
+                .find_map(doi),
+            journal: meta_value(
+                meta,
+                &[
+                    "citation_journal_title",
+                    "citation_conference_title",
+                    "citation_inbook_title",
+                ],
+                MAX_FIELD_LENGTH,
+            ),
+            publisher: meta_value(
+                meta,
+                &[
+                    "citation_publisher",
+                    "citation_dissertation_institution",
+                    "citation_technical_report_institution",
+                ],
+                MAX_FIELD_LENGTH,
+            ),
+            volume: meta_value(meta, &["citation_volume"], MAX_FIELD_LENGTH),
+            issue: meta_value(meta, &["citation_issue"], MAX_FIELD_LENGTH),
+            pages: match (first_page, last_page) {
+                (Some(first), Some(last)) => Some(format!("{}-{}", first, last)),
+                (first, last) => first.or(last),
+            },
+            pdf_url: meta_values(meta, &["citation_pdf_url"])
+                .first()
+                .map(|url| url.to_string()),
+        },
+    }
+}
+
+/// Dublin Core tags, written as `DC.*` or `DCTERMS.*` in any case.
+fn dublin_core(meta: &[(String, String)]) -> Structured {
+    let names = |terms: &[&str]| {
+        terms
+            .iter()
+            .flat_map(|term| [format!("dc.{}", term), format!("dcterms.{}", term)])
+            .collect::<Vec<_>>()
+    };
+    let value = |terms: &[&str], max_length: usize| {
+        let names = names(terms);
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        meta_value(meta, &names, max_length)
+    };
+    let values = |terms: &[&str]| {
+        let names = names(terms);
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        meta_values(meta, &names)
+    };
+
+    Structured {
+        title: value(&["title"], MAX_TITLE_LENGTH),
+        description: value(&["description", "abstract"], MAX_DESCRIPTION_LENGTH),
+        image: None,
+        publication: Publication {
+            authors: values(&["creator"])
+                .into_iter()
+                .filter_map(|author| normalize_text(author, MAX_FIELD_LENGTH))
+                .collect(),
+            published: value(
+                &["date.issued", "issued", "date", "date.created", "created"],
+                MAX_FIELD_LENGTH,
+            )
+            .map(|date| normalize_date(&date)),
+            modified: value(&["date.modified", "modified"], MAX_FIELD_LENGTH)
+                .map(|date| normalize_date(&date)),
+            doi: values(&["identifier"]).into_iter().find_map(doi),
+            journal: value(
+                &["relation.ispartof", "ispartof", "source"],
+                MAX_FIELD_LENGTH,
+            ),
+            publisher: value(&["publisher"], MAX_FIELD_LENGTH),
+            ..Default::default()
+        },
+    }
+}
+
+/// Maps schema.org and Dublin Core property names from microdata or RDFa.
+fn from_properties(properties: &[(String, String)]) -> Structured {
+    let values = |names: &[&str]| {
+        properties
+            .iter()
+            .filter(|(name, _)| names.iter().any(|wanted| name.eq_ignore_ascii_case(wanted)))
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+    };
+    let value = |names: &[&str], max_length: usize| {
+        values(names)
+            .into_iter()
+            .find_map(|value| normalize_text(value, max_length))
+    };
+
+    let page_start = value(&["pageStart"], MAX_FIELD_LENGTH);
+    let page_end = value(&["pageEnd"], MAX_FIELD_LENGTH);
+
+    Structured {
+        title: value(&["headline", "name", "title"], MAX_TITLE_LENGTH),
+        description: value(&["description", "abstract"], MAX_DESCRIPTION_LENGTH),
+        image: values(&["image", "thumbnailUrl"])
+            .first()
+            .map(|image| image.to_string()),
+        publication: Publication {
+            authors: values(&["author", "creator"])
+                .into_iter()
+                .filter_map(|author| normalize_text(author, MAX_FIELD_LENGTH))
+                .collect(),
+            published: value(
+                &["datePublished", "dateCreated", "issued", "date", "created"],
+                MAX_FIELD_LENGTH,
+            )
+            .map(|date| normalize_date(&date)),
+            modified: value(&["dateModified", "modified"], MAX_FIELD_LENGTH)
+                .map(|date| normalize_date(&date)),
+            doi: values(&["doi", "identifier", "sameAs"])
+                .into_iter()
+                .find_map(doi),
+            journal: value(&["isPartOf"], MAX_FIELD_LENGTH),
+            publisher: value(&["publisher"], MAX_FIELD_LENGTH),
+            volume: value(&["volumeNumber"], MAX_FIELD_LENGTH),
+            issue: value(&["issueNumber"], MAX_FIELD_LENGTH),
+            pages: value(&["pagination"], MAX_FIELD_LENGTH).or(match (page_start, page_end) {
+                (Some(start), Some(end)) => Some(format!("{}-{}", start, end)),
+                (start, end) => start.or(end),
+            }),
+            pdf_url: None,
+        },
+    }
+}
+
+/// Collects the properties of the first article-like item, as
+/// `(local name, value)` pairs in document order.
+///
+/// A nested item, such as an author or the journal, contributes its `name`.
+/// RDFa properties outside any typed item are read from the whole document
+/// when they use a Dublin Core or schema.org prefix.
+fn item_properties(document: &ScraperHTML, syntax: &Syntax) -> Vec<(String, String)> {
+    let scope_selector = Selector::parse(&format!("[{}]", syntax.scope)).unwrap();
+
+    let item = document.select(&scope_selector).find(|element| {
+        element.value().attr(syntax.property).is_none()
+            && element
+                .value()
+                .attr(syntax.item_type)
+                .is_some_and(|types| types.split_whitespace().any(is_article_type))
+    });
+
+    let (root, top_level) = match item {
+        Some(item) => (item, false),
+        None if syntax.scope == RDFA.scope => (document.root_element(), true),
+        None => return Vec::new(),
+    };
+
+    root.descendants()
+        .skip(1)
+        .filter_map(ElementRef::wrap)
+        .filter(|element| {
+            nearest_scope(element, syntax).map(|scope| scope.id()) == item.map(|item| item.id())
+        })
+        .filter_map(|element| {
+            let names = element.value().attr(syntax.property)?;
+            Some((element, names))
+        })
+        .flat_map(|(element, names)| {
+            let value = if element.value().attr(syntax.scope).is_some() {
+                nested_name(&element, syntax)
+            } else {
+                property_value(&element)
+            };
+
+            names
+                .split_whitespace()
+                .filter_map(|name| local_name(name, top_level))
+                .filter_map(move |name| Some((name, value.clone()?)))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The closest ancestor that opens an item.
+fn nearest_scope<'a>(element: &ElementRef<'a>, syntax: &Syntax) -> Option<ElementRef<'a>> {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|ancestor| ancestor.value().attr(syntax.scope).is_some())
+}
+
+/// The `name` of a nested item, or its text if it has none.
+fn nested_name(item: &ElementRef, syntax: &Syntax) -> Option<String> {
+    item.descendants()
+        .skip(1)
+        .filter_map(ElementRef::wrap)
+        .filter(|element| nearest_scope(element, syntax).map(|scope| scope.id()) == Some(item.id()))
+        .find(|element| {
+            element.value().attr(syntax.property).is_some_and(|names| {
+                names
+                    .split_whitespace()
+                    .filter_map(|name| local_name(name, false))
+                    .any(|name| name == "name")
+            })
+        })
+        .and_then(|element| property_value(&element))
+        .or_else(|| normalize_text(&item.text().collect::<String>(), MAX_FIELD_LENGTH))
+}
+
+/// The value of a property element, following the microdata rules: the
+/// `content` attribute, a URL attribute for links and embeds, a machine
+/// readable attribute for times and data, or else the text.
+fn property_value(element: &ElementRef) -> Option<String> {
+    let attribute = |name: &str| element.value().attr(name).map(str::trim);
+
+    let value = attribute("content").or(match element.value().name() {
+        "a" | "area" | "link" => attribute("href"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => attribute("src"),
+        "object" => attribute("data"),
+        "time" => attribute("datetime"),
+        "data" | "meter" => attribute("value"),
+        _ => None,
+    });
+
+    match value {
+        Some(value) => Some(value.to_string()).filter(|value| !value.is_empty()),
+        None => normalize_text(&element.text().collect::<String>(), MAX_DESCRIPTION_LENGTH),
+    }
+}
+
+fn is_article_type(item_type: &str) -> bool {
+    let local = item_type
+        .rsplit(['/', '#', ':'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    ARTICLE_TYPES.contains(&local.as_str())
+}
+
+/// Strips a prefix or vocabulary URL from a property name, e.g.
+/// `schema:name` or `http://schema.org/name` to `name`.
+///
+/// Outside a typed item only Dublin Core and schema.org prefixes are kept.
+fn local_name(name: &str, top_level: bool) -> Option<String> {
+    if let Some((_, local)) = name.rsplit_once(['/', '#']) {
+        return Some(local.to_string()).filter(|local| !local.is_empty());
+    }
+
+    match name.split_once(':') {
+        Some((prefix, local)) => {
+            let prefix = prefix.to_ascii_lowercase();
+            (!top_level || RDFA_PREFIXES.contains(&prefix.as_str())).then(|| local.to_string())
+        }
+        None if top_level => None,
+        None => Some(name.to_string()),
+    }
+}
+
+/// Extracts a DOI such as `10.1000/xyz123` from `doi:`, `info:doi/` and
+/// `https://doi.org/` forms.
+fn doi(value: &str) -> Option<String> {
+    let start = value.find("10.")?;
+    let doi = value[start..].trim();
+    let (prefix, suffix) = doi.split_once('/')?;
+
+    let valid_prefix =
+        prefix[3..].len() >= 4 && prefix[3..].chars().all(|c| c.is_ascii_digit() || c == '.');
+    (valid_prefix && !suffix.is_empty() && !doi.contains(char::is_whitespace))
+        .then(|| doi.to_string())
+}
+
+/// Rewrites `2020/05/01` dates, common in `citation_*` tags, as `2020-05-01`.
+fn normalize_date(date: &str) -> String {
+    if date.chars().all(|c| c.is_ascii_digit() || c == '/') {
+        date.replace('/', "-")
+    } else {
+        date.to_string()
+    }
+}
@@ -0,0 +1,100 @@
+use scraper::Html as ScraperHTML;
+
+/// Maximum number of characters kept for a title.
+pub const MAX_TITLE_LENGTH: usize = 300;
+/// Maximum number of characters kept for a description.
+pub const MAX_DESCRIPTION_LENGTH: usize = 1000;
+/// Maximum number of characters kept for keywords.
+pub const MAX_KEYWORDS_LENGTH: usize = 500;
+/// Maximum number of characters kept for any other extracted string.
+pub const MAX_FIELD_LENGTH: usize = 300;
+/// Longest URL returned; longer ones are dropped rather than cut.
+pub const MAX_URL_LENGTH: usize = 2048;
+
+/// Normalizes a string extracted from a page into plain text.
+///
+/// The value must already be decoded text, such as an attribute value or an
+/// element's text from the HTML parser, so `<` and `&` are kept as they are.
+/// Runs of whitespace are collapsed into a single space and the result is
+/// clamped to `max_length` characters, with an ellipsis marking the cut.
+///
+/// # Arguments
+/// * `value` - The decoded extracted string.
+/// * `max_length` - The maximum number of characters to keep.
+///
+/// # Returns
+/// * `Some(String)` with the normalized text.
+/// * `None` if nothing but whitespace remains.
+pub fn normalize_text(value: &str, max_length: usize) -> Option<String> {
+    let collapsed = value.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if collapsed.is_empty() {
+        return None;
+    }
+
+    Some(clamp(collapsed, max_length))
+}
+
+/// Normalizes an HTML fragment, such as an RSS description, into plain text.
+///
+/// Markup is stripped and HTML entities are decoded before the text is
+/// normalized, see [`normalize_text`]. Only use this where the value really
+/// is HTML source; decoded text would lose anything that looks like a tag.
+pub fn html_to_text(value: &str, max_length: usize) -> Option<String> {
+    if !value.contains(['<', '&']) {
+        return normalize_text(value, max_length);
+    }
+
+    let fragment = ScraperHTML::parse_fragment(value);
+    normalize_text(
+        &fragment.root_element().text().collect::<String>(),
+        max_length,
+    )
+}
+
+/// Cuts `text` to `max_length` characters, with an ellipsis marking the cut.
+pub fn clamp(text: String, max_length: usize) -> String {
+    if text.chars().count() <= max_length {
+        return text;
+    }
+
+    let mut clamped = text
+        .chars()
+        .take(max_length.saturating_sub(1))
+        .collect::<String>()
+        .trim_end()
+        .to_string();
+    clamped.push('…');
+    clamped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_text_keeps_decoded_markup_characters() {
+        assert_eq!(
+            normalize_text("<div>: The  Content\nDivision element", MAX_TITLE_LENGTH).as_deref(),
+            Some("<div>: The Content Division element")
+        );
+        assert_eq!(
+            normalize_text("Tom &amp; Jerry", MAX_TITLE_LENGTH).as_deref(),
+            Some("Tom &amp; Jerry")
+        );
+    }
+
+    #[test]
+    fn html_to_text_strips_markup_and_decodes_entities() {
+        assert_eq!(
+            html_to_text("<p>Tom &amp; <b>Jerry</b></p>", MAX_TITLE_LENGTH).as_deref(),
+            Some("Tom & Jerry")
+        );
+    }
+
+    #[test]
+    fn clamp_marks_the_cut() {
+        assert_eq!(clamp("abcdef".to_string(), 4), "abc…");
+        assert_eq!(clamp("abc".to_string(), 4), "abc");
+    }
+}